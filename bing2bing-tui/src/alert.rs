@@ -0,0 +1,108 @@
+use std::io::Write;
+use std::ops::Range;
+use std::process::Stdio;
+
+use tracing::{debug, error};
+
+/// Why the user is being alerted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertKind {
+    /// Someone said something that mentions us.
+    Mention,
+    /// Someone whispered to us.
+    Whisper,
+}
+
+impl AlertKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::Mention => "mention",
+            AlertKind::Whisper => "whisper",
+        }
+    }
+}
+
+/// Finds the places `text` mentions `name`: either as `@name` or just `name` as a word of its
+/// own (ignoring ASCII case). The ranges are byte ranges into `text`, and include the `@`.
+pub fn find_mentions(text: &str, name: &str) -> Vec<Range<usize>> {
+    if name.is_empty() {
+        return vec![];
+    }
+
+    // lowercasing ASCII doesn't change any byte offsets
+    let haystack = text.to_ascii_lowercase();
+    let needle = name.to_ascii_lowercase();
+
+    haystack
+        .match_indices(&needle)
+        .filter_map(|(start, _)| {
+            let end = start + needle.len();
+
+            let before = text[..start].chars().next_back();
+            let after = text[end..].chars().next();
+
+            // "bob" shouldn't count as a mention in "bobcat"
+            if before.is_some_and(is_name_char) || after.is_some_and(is_name_char) {
+                return None;
+            }
+
+            match before {
+                Some('@') => Some(start - 1..end),
+                _ => Some(start..end),
+            }
+        })
+        .collect()
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Lets the user know about mentions and whispers, either by ringing the terminal bell or by
+/// running a hook command.
+#[derive(Debug, Clone)]
+pub struct Alerter {
+    command: Option<String>,
+}
+
+impl Alerter {
+    /// If `command` is set it is run (with `sh -c`) for every alert instead of ringing the bell.
+    pub fn new(command: Option<String>) -> Self {
+        Self { command }
+    }
+
+    /// Fires an alert for `message` from `from`.
+    /// The hook command gets the details in the `B2B_ALERT` (`mention` or `whisper`),
+    /// `B2B_FROM` and `B2B_MESSAGE` environment variables.
+    ///
+    /// Must be called from within a tokio runtime if there is a hook command.
+    pub fn alert(&self, kind: AlertKind, from: &str, message: &str) {
+        debug!("Alerting for {:?} from {}", kind, from);
+
+        let command = match &self.command {
+            Some(command) => command,
+            None => {
+                // the bell doesn't move the cursor, so it is safe to ring even if a TUI is drawing
+                let mut stdout = std::io::stdout();
+                let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+                return;
+            }
+        };
+
+        // tokio takes care of reaping the child once it exits, so we don't need to wait on it
+        let spawned = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("B2B_ALERT", kind.as_str())
+            .env("B2B_FROM", from)
+            .env("B2B_MESSAGE", message)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        if let Err(err) = spawned {
+            error!("Couldn't run alert command {:?}: {}", command, err);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::time::{Duration, SystemTime};
+
+use libb2b::{Client, HistoryQuery};
+use tracing::trace;
+
+use crate::{format_history_entry, UiClientMessage};
+
+/// Something the user typed into one of the TUIs, after parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Should be passed on to the [Client] (see [dispatch()]).
+    Send(UiClientMessage),
+    /// Show the messages from the history that match.
+    History(HistoryQuery),
+    /// Ignore this peer, or with `None`, list who we are ignoring.
+    Ignore(Option<String>),
+    Unignore(String),
+    Quit,
+    Help,
+}
+
+/// Describes a slash command: how to invoke it and how to parse its arguments.
+/// Adding a new command is a matter of adding an entry to [COMMANDS].
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    /// Parses everything after `/name `.
+    parse: fn(&str) -> Result<Command, String>,
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "say",
+        usage: "/say <message>",
+        description: "say something to everyone (typing without a command does the same)",
+        parse: parse_say,
+    },
+    CommandSpec {
+        name: "near",
+        usage: "/near <hops> <message>",
+        description: "say something to just the peers within a few hops of us",
+        parse: parse_near,
+    },
+    CommandSpec {
+        name: "whisper",
+        usage: "/whisper <peer> <message>",
+        description: "send a message to just one peer",
+        parse: parse_whisper,
+    },
+    CommandSpec {
+        name: "history",
+        usage: "/history [peer] [minutes]",
+        description: "show saved messages (with just one peer, from the last few minutes)",
+        parse: parse_history,
+    },
+    CommandSpec {
+        name: "ignore",
+        usage: "/ignore [peer]",
+        description: "stop showing messages from a peer (or list who is ignored)",
+        parse: parse_ignore,
+    },
+    CommandSpec {
+        name: "unignore",
+        usage: "/unignore <peer>",
+        description: "show messages from a peer again",
+        parse: parse_unignore,
+    },
+    CommandSpec {
+        name: "help",
+        usage: "/help",
+        description: "show this help",
+        parse: |_| Ok(Command::Help),
+    },
+    CommandSpec {
+        name: "quit",
+        usage: "/quit",
+        description: "exit",
+        parse: |_| Ok(Command::Quit),
+    },
+];
+
+/// Parses a line of user input.
+/// Lines that don't start with `/` are treated as a `/say`.
+/// The `Err` variant is an error message meant to be shown to the user.
+pub fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+
+    let rest = match line.strip_prefix('/') {
+        Some(rest) => rest,
+        None => return parse_say(line),
+    };
+
+    let (name, args) = match rest.split_once(' ') {
+        Some((name, args)) => (name, args.trim()),
+        None => (rest, ""),
+    };
+
+    match COMMANDS.iter().find(|spec| spec.name == name) {
+        Some(spec) => (spec.parse)(args),
+        None => Err(format!("Unknown command /{}; try /help", name)),
+    }
+}
+
+/// One line per command, describing how to use it.
+pub fn help_lines() -> Vec<String> {
+    COMMANDS
+        .iter()
+        .map(|spec| format!("{:<28} {}", spec.usage, spec.description))
+        .collect()
+}
+
+/// Passes a message from the UI on to the [Client].
+pub async fn dispatch(client: &Client, message: UiClientMessage) {
+    trace!("Dispatching {:?} to client", message);
+
+    match message {
+        UiClientMessage::Say(message) => client.say(message).await,
+        UiClientMessage::SayWithin(hops, message) => client.say_within(hops, message).await,
+        UiClientMessage::Whisper(to, message) => client.whisper(to, message).await,
+    }
+}
+
+/// The lines to show for a `/history` command.
+pub fn history_lines(client: &Client, query: &HistoryQuery) -> Vec<String> {
+    let history = match client.history() {
+        Some(history) => history,
+        None => return vec!["History isn't being saved; start with --history-file".to_string()],
+    };
+
+    match history.query(query) {
+        Ok(entries) if entries.is_empty() => vec!["No messages in history".to_string()],
+        Ok(entries) => entries.iter().map(format_history_entry).collect(),
+        Err(err) => vec![format!("Couldn't read history: {}", err)],
+    }
+}
+
+/// The lines to show for an `/ignore` command.
+pub fn ignore_lines(client: &Client, peer: Option<&str>) -> Vec<String> {
+    let peer = match peer {
+        Some(peer) => peer,
+        None => {
+            let ignored = client.ignored();
+            if ignored.is_empty() {
+                return vec!["Nobody is being ignored".to_string()];
+            }
+            return vec![format!("Ignoring {}", ignored.join(", "))];
+        }
+    };
+
+    match client.ignore(peer) {
+        Ok(true) => vec![format!("Ignoring {}", peer)],
+        Ok(false) => vec![format!("Already ignoring {}", peer)],
+        Err(err) => vec![format!("Couldn't ignore {}: {}", peer, err)],
+    }
+}
+
+/// The lines to show for an `/unignore` command.
+pub fn unignore_lines(client: &Client, peer: &str) -> Vec<String> {
+    match client.unignore(peer) {
+        Ok(true) => vec![format!("No longer ignoring {}", peer)],
+        Ok(false) => vec![format!("{} wasn't being ignored", peer)],
+        Err(err) => vec![format!("Couldn't unignore {}: {}", peer, err)],
+    }
+}
+
+fn parse_say(args: &str) -> Result<Command, String> {
+    if args.is_empty() {
+        return Err("Usage: /say <message>".to_string());
+    }
+
+    Ok(Command::Send(UiClientMessage::Say(args.to_string())))
+}
+
+fn parse_near(args: &str) -> Result<Command, String> {
+    match args.split_once(' ') {
+        Some((hops, message)) if !message.trim().is_empty() => match hops.parse::<u64>() {
+            Ok(hops) if hops > 0 => Ok(Command::Send(UiClientMessage::SayWithin(
+                hops,
+                message.trim().to_string(),
+            ))),
+            _ => Err(format!("{:?} isn't a number of hops", hops)),
+        },
+        _ => Err("Usage: /near <hops> <message>".to_string()),
+    }
+}
+
+fn parse_whisper(args: &str) -> Result<Command, String> {
+    match args.split_once(' ') {
+        Some((to, message)) if !message.trim().is_empty() => Ok(Command::Send(
+            UiClientMessage::Whisper(to.to_string(), message.trim().to_string()),
+        )),
+        _ => Err("Usage: /whisper <peer> <message>".to_string()),
+    }
+}
+
+/// A number is taken to be how many minutes back to go, anything else is a peer.
+fn parse_history(args: &str) -> Result<Command, String> {
+    let usage = || "Usage: /history [peer] [minutes]".to_string();
+
+    let mut query = HistoryQuery::default();
+
+    for arg in args.split_whitespace() {
+        match arg.parse::<u64>() {
+            Ok(minutes) if query.since.is_none() => {
+                let since = minutes
+                    .checked_mul(60)
+                    .and_then(|secs| SystemTime::now().checked_sub(Duration::from_secs(secs)))
+                    .ok_or_else(usage)?;
+
+                query.since = Some(since);
+            }
+            _ if query.peer.is_none() => query.peer = Some(arg.to_string()),
+            _ => return Err(usage()),
+        }
+    }
+
+    Ok(Command::History(query))
+}
+
+fn parse_ignore(args: &str) -> Result<Command, String> {
+    match args.split_whitespace().collect::<Vec<_>>()[..] {
+        [] => Ok(Command::Ignore(None)),
+        [peer] => Ok(Command::Ignore(Some(peer.to_string()))),
+        _ => Err("Usage: /ignore [peer]".to_string()),
+    }
+}
+
+fn parse_unignore(args: &str) -> Result<Command, String> {
+    match args.split_whitespace().collect::<Vec<_>>()[..] {
+        [peer] => Ok(Command::Unignore(peer.to_string())),
+        _ => Err("Usage: /unignore <peer>".to_string()),
+    }
+}
@@ -1,8 +1,13 @@
 use chrono::Local;
-use libb2b::{Bing2BingError, Client, ClientServerMessage, Server};
+use futures::StreamExt;
+use libb2b::{
+    Bing2BingError, Client, ClientEvent, ClientServerMessage, History, HistoryEntry, Route, Server,
+    Topology,
+};
 use std::{
-    collections::VecDeque,
     io::Stdout,
+    net::SocketAddr,
+    path::PathBuf,
     sync::{Arc, Mutex},
     thread,
     time::{Duration, Instant},
@@ -12,7 +17,7 @@ use tracing::{debug, error, trace};
 use unicode_width::UnicodeWidthStr;
 
 use crossterm::{
-    event::{self, Event as CEvent, KeyCode},
+    event::{self, Event as CEvent, KeyCode, KeyModifiers},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
 
@@ -21,21 +26,35 @@ use tui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Tabs},
+    widgets::{
+        canvas::{Canvas, Map, MapResolution, Points},
+        Block, Borders, Cell, List, ListItem, Paragraph, Row, Table, Tabs, Wrap,
+    },
     Terminal,
 };
 
 use tui_logger::TuiLoggerWidget;
 
-use crate::{Cli, UiClientMessage};
+use crate::alert::{find_mentions, AlertKind, Alerter};
+use crate::command::{self, Command, COMMANDS};
+use crate::line_editor::LineEditor;
+use crate::scrollback::Scrollback;
+use crate::{
+    format_event, format_history_entry, spawn_topology_writer, Cli, UiClientMessage,
+    HISTORY_ON_STARTUP,
+};
 
-pub type UiClientTxChannel = mpsc::UnboundedSender<UiClientMessage>;
 pub type UiClientRxChannel = mpsc::UnboundedReceiver<UiClientMessage>;
 
+/// How long the draw loop waits between redraws.
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
 #[derive(Debug, Clone, Copy)]
 pub(crate) enum InputMode {
     Normal,
     Editing,
+    /// Typing in a search through the messages of the active tab.
+    Search,
 }
 
 #[derive(Debug)]
@@ -70,24 +89,62 @@ pub async fn start(args: Cli) -> Result<(), Bing2BingError> {
 
     trace!("Args: {:?}", args);
 
-    let ip_address = args.ip_address.to_string().clone();
+    let ip_address = args.ip_address;
     let port = args.port; //.to_string().clone();
 
-    let tracker_ip_address = args.tracker_ip_address.to_string().clone();
-    let tracker_port = args.tracker_port; //.to_string().clone();
+    let tracker_addr = SocketAddr::new(args.tracker_ip_address, args.tracker_port);
+
+    let location = args.location()?;
 
     let my_name = args.name;
 
     let max_connections = args.max_connections;
 
+    let topology_file = args.topology_file;
+
+    let alerter = Alerter::new(args.alert_command);
+
+    let history_file = args.history_file;
+
+    let control_socket = args.control_socket;
+
     let (ui_client_tx, ui_client_rx) = mpsc::unbounded_channel();
 
-    let (client, server) = libb2b::init(&my_name, &ip_address, port).await;
+    let (client, mut server) = libb2b::init(&my_name, ip_address, port).await;
+    server.set_location(location);
+    server.set_routing_mode(args.routing);
+    server.set_hop_limit(args.hop_limit);
+    server.set_gossip_fanout(args.gossip_fanout);
+    server.set_source_rate_limit(Some(args.source_rate_limit));
+    server.set_link_rate_limit(Some(args.link_rate_limit));
 
-    let network_client = client;
+    if let Some(session_file) = args.session_file {
+        server.set_session_file(session_file)?;
+    }
+
+    if let Some(ignore_file) = args.ignore_file {
+        client.load_ignore_list(ignore_file)?;
+    }
+    client.set_strict_ignore(args.strict_ignore);
+
+    let network_client = client.clone();
 
     let app = App::new();
 
+    if let Some(history_file) = history_file {
+        let history = History::open(history_file)?;
+
+        for entry in history.recent(HISTORY_ON_STARTUP)? {
+            app.add_from_history(&entry, client.name());
+        }
+
+        client.set_history(history);
+    }
+
+    if let Some(control_socket) = control_socket {
+        libb2b::control::spawn_control_server(control_socket, client.clone());
+    }
+
     let moved_app = app.clone();
     std::thread::spawn(move || {
         debug!("STARTING PEER!!!!");
@@ -95,10 +152,11 @@ pub async fn start(args: Cli) -> Result<(), Bing2BingError> {
             moved_app,
             network_client,
             server,
-            tracker_ip_address,
-            tracker_port,
+            tracker_addr,
             max_connections,
             ui_client_rx,
+            topology_file,
+            alerter,
         )
     });
 
@@ -143,6 +201,7 @@ pub async fn start(args: Cli) -> Result<(), Bing2BingError> {
     let mut terminal = Arc::new(Mutex::new(Terminal::new(backend)?));
     let app_moved = app.clone();
     let terminal_moved = terminal.clone();
+    let key_client = client.clone();
 
     tokio::spawn(async move {
         let app = app_moved;
@@ -152,6 +211,19 @@ pub async fn start(args: Cli) -> Result<(), Bing2BingError> {
         loop {
             match rx.recv().await {
                 Some(Event::Input(event)) => {
+                    // scrolling works no matter what mode we're in
+                    match event.code {
+                        KeyCode::PageUp => {
+                            app.scroll_messages_up();
+                            continue;
+                        }
+                        KeyCode::PageDown => {
+                            app.scroll_messages_down();
+                            continue;
+                        }
+                        _ => {}
+                    }
+
                     match app.get_input_mode() {
                         InputMode::Editing => {
                             match event.code {
@@ -159,23 +231,96 @@ pub async fn start(args: Cli) -> Result<(), Bing2BingError> {
                                 KeyCode::Enter => {
                                     debug!("got Enter");
 
-                                    let to_say = app.input_string_drain();
-
-                                    app.add_message(&to_say);
-
-                                    // we also need to say this message
-                                    debug!("calling client.say()");
-
-                                    let msg = UiClientMessage::Say(to_say);
-
-                                    ui_client_tx.send(msg).unwrap();
+                                    let input = app.edit_input(LineEditor::submit);
+
+                                    if input.trim().is_empty() {
+                                        continue;
+                                    }
+
+                                    match parse_input(&app, &input) {
+                                        Ok(Command::Send(msg)) => {
+                                            match &msg {
+                                                UiClientMessage::Say(_)
+                                                | UiClientMessage::SayWithin(..) => {
+                                                    app.add_message(&format_sent_message(&msg))
+                                                }
+                                                UiClientMessage::Whisper(to, _) => {
+                                                    app.open_conversation(to);
+                                                    app.add_whisper(to, &format_sent_message(&msg));
+                                                }
+                                            }
+
+                                            debug!("sending {:?} to the client", msg);
+                                            ui_client_tx.send(msg).unwrap();
+                                        }
+                                        Ok(Command::Help) => {
+                                            for help_line in command::help_lines() {
+                                                app.add_notice(&help_line);
+                                            }
+                                        }
+                                        Ok(Command::History(query)) => {
+                                            for history_line in
+                                                command::history_lines(&key_client, &query)
+                                            {
+                                                app.add_notice(&history_line);
+                                            }
+                                        }
+                                        Ok(Command::Ignore(peer)) => {
+                                            for ignore_line in
+                                                command::ignore_lines(&key_client, peer.as_deref())
+                                            {
+                                                app.add_notice(&ignore_line);
+                                            }
+                                        }
+                                        Ok(Command::Unignore(peer)) => {
+                                            for ignore_line in
+                                                command::unignore_lines(&key_client, &peer)
+                                            {
+                                                app.add_notice(&ignore_line);
+                                            }
+                                        }
+                                        Ok(Command::Quit) => {
+                                            quit(&app, &terminal);
+                                            break;
+                                        }
+                                        Err(err) => app.add_notice(&err),
+                                    }
+                                }
+                                KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    match c {
+                                        'a' => app.edit_input(LineEditor::home),
+                                        'e' => app.edit_input(LineEditor::end),
+                                        _ => {}
+                                    }
                                 }
                                 KeyCode::Char(c) => {
                                     trace!("got a character: {}", c);
-                                    app.input_string_push(c);
+                                    app.edit_input(|input| input.insert(c));
+                                }
+                                KeyCode::Backspace => app.edit_input(LineEditor::backspace),
+                                KeyCode::Delete => app.edit_input(LineEditor::delete),
+                                KeyCode::Left if is_word_jump(event.modifiers) => {
+                                    app.edit_input(LineEditor::word_left)
                                 }
-                                KeyCode::Backspace => {
-                                    app.input_string_pop();
+                                KeyCode::Right if is_word_jump(event.modifiers) => {
+                                    app.edit_input(LineEditor::word_right)
+                                }
+                                KeyCode::Left => app.edit_input(LineEditor::left),
+                                KeyCode::Right => app.edit_input(LineEditor::right),
+                                KeyCode::Home => app.edit_input(LineEditor::home),
+                                KeyCode::End => app.edit_input(LineEditor::end),
+                                KeyCode::Up => app.edit_input(LineEditor::history_previous),
+                                KeyCode::Down => app.edit_input(LineEditor::history_next),
+                                KeyCode::Tab => {
+                                    let commands =
+                                        COMMANDS.iter().map(|spec| spec.name).collect::<Vec<_>>();
+                                    let peers = known_peer_names(&key_client);
+
+                                    let candidates =
+                                        app.edit_input(|input| input.complete(&commands, &peers));
+                                    if !candidates.is_empty() {
+                                        app.add_notice(&candidates.join("  "));
+                                    }
                                 }
                                 KeyCode::Esc => {
                                     app.set_input_mode(InputMode::Normal);
@@ -183,32 +328,49 @@ pub async fn start(args: Cli) -> Result<(), Bing2BingError> {
                                 _ => {}
                             }
                         }
+                        InputMode::Search => match event.code {
+                            KeyCode::Char(c) => app.search_push(c),
+                            KeyCode::Backspace => app.search_pop(),
+                            KeyCode::Up => app.search_older(),
+                            KeyCode::Down => app.search_newer(),
+                            // keep the matches highlighted
+                            KeyCode::Enter => app.set_input_mode(InputMode::Normal),
+                            KeyCode::Esc => {
+                                app.clear_search();
+                                app.set_input_mode(InputMode::Normal);
+                            }
+                            _ => {}
+                        },
                         InputMode::Normal => match event.code {
                             KeyCode::Char('e') => {
                                 app.set_input_mode(InputMode::Editing);
                             }
+                            KeyCode::Char('/') => app.start_search(),
+                            KeyCode::Esc => app.clear_search(),
 
                             KeyCode::Char('q') => {
-                                debug!("we are shuttong down?!?! {}", app.is_shutdown());
-                                disable_raw_mode().unwrap();
-                                {
-                                    let mut terminal = terminal.lock().unwrap();
-                                    terminal.show_cursor().unwrap();
-                                }
-
-                                app.shutdown();
-                                debug!("After calling shutdown() {}", app.is_shutdown());
-
+                                quit(&app, &terminal);
                                 break;
                             }
                             KeyCode::Char('h') => app.set_active_menu_item(MenuItem::Home),
                             KeyCode::Char('l') => app.set_active_menu_item(MenuItem::Logs),
+                            KeyCode::Char('n') => app.set_active_menu_item(MenuItem::Network),
+                            // conversations are numbered from 1 in the menu
+                            KeyCode::Char(c @ '1'..='9') => {
+                                let i = c.to_digit(10).unwrap() as usize - 1;
+                                app.set_active_menu_item(MenuItem::Conversation(i));
+                            }
+                            KeyCode::Tab => app.next_menu_item(),
+                            KeyCode::BackTab => app.previous_menu_item(),
+                            KeyCode::Char('x') => app.close_active_conversation(),
                             _ => {}
                         },
                     }
                 }
 
-                Some(Event::Tick) => { /* we just do nothing here */ }
+                // routes can change without the topology changing (e.g., a peer going stale),
+                // so the Network tab is brought up to date every so often regardless
+                Some(Event::Tick) => app.refresh_network(&key_client),
                 None => {
                     break;
                 }
@@ -222,14 +384,70 @@ pub async fn start(args: Cli) -> Result<(), Bing2BingError> {
             break;
         }
 
-        draw(&mut app.clone(), &mut terminal);
+        draw(&mut app.clone(), &mut terminal, &client);
+
+        tokio::time::sleep(FRAME_INTERVAL).await;
     }
 
     Ok(())
 }
 
-pub fn draw(app: &mut App, terminal: &mut Arc<Mutex<Terminal<CrosstermBackend<Stdout>>>>) {
-    let menu_titles = vec!["Home", "Logs"];
+/// Restores the terminal and lets the draw loop know that it should stop.
+fn quit(app: &App, terminal: &Arc<Mutex<Terminal<CrosstermBackend<Stdout>>>>) {
+    debug!("we are shuttong down?!?! {}", app.is_shutdown());
+    disable_raw_mode().unwrap();
+    {
+        let mut terminal = terminal.lock().unwrap();
+        terminal.show_cursor().unwrap();
+    }
+
+    app.shutdown();
+    debug!("After calling shutdown() {}", app.is_shutdown());
+}
+
+/// Parses a line of input. In a conversation tab, anything that isn't a /command is
+/// whispered to the peer on the other end of the conversation.
+fn parse_input(app: &App, input: &str) -> Result<Command, String> {
+    match app.input_target() {
+        Some(peer) if !input.trim_start().starts_with('/') => Ok(Command::Send(
+            UiClientMessage::Whisper(peer, input.trim().to_string()),
+        )),
+        _ => command::parse(input),
+    }
+}
+
+/// Ctrl or Alt + Left/Right jump by words.
+fn is_word_jump(modifiers: KeyModifiers) -> bool {
+    modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+}
+
+/// Everyone we've heard an Announce from (except us), for tab completion.
+fn known_peer_names(client: &Client) -> Vec<String> {
+    client
+        .topology()
+        .nodes
+        .into_iter()
+        .map(|node| node.name)
+        .filter(|name| name != client.name())
+        .collect()
+}
+
+/// How something we sent shows up in our own message list.
+fn format_sent_message(msg: &UiClientMessage) -> String {
+    match msg {
+        UiClientMessage::Say(message) => message.clone(),
+        UiClientMessage::SayWithin(hops, message) => format!("(within {} hops) {}", hops, message),
+        UiClientMessage::Whisper(to, message) => format!("(to {}) {}", to, message),
+    }
+}
+
+pub fn draw(
+    app: &mut App,
+    terminal: &mut Arc<Mutex<Terminal<CrosstermBackend<Stdout>>>>,
+    client: &Client,
+) {
+    let menu_titles = app.menu_titles();
+    let (topology, routes) = app.network();
     let mut terminal = terminal.lock().unwrap();
     terminal
         .draw(|rect| {
@@ -247,7 +465,29 @@ pub fn draw(app: &mut App, terminal: &mut Arc<Mutex<Terminal<CrosstermBackend<St
 
             let input_mode = app.get_input_mode();
 
-            let input = get_input_box(app.clone_input_string(), input_mode);
+            let input_title = match app.input_target() {
+                Some(peer) => format!("Input (whispering to {})", peer),
+                None => "Input".to_string(),
+            };
+
+            let (input_string, input_title, cursor_width) = match input_mode {
+                InputMode::Search => {
+                    let search = app.get_search();
+                    let width = search.width();
+                    (search, "Search (Up/Down for older/newer matches)".to_string(), width)
+                }
+                _ => {
+                    let (line, cursor_width) = app.get_input();
+                    (line, input_title, cursor_width)
+                }
+            };
+
+            // if the line doesn't fit, scroll it sideways so that the cursor is always in view
+            let input_view_width = chunks[2].width.saturating_sub(2) as usize;
+            let input_scroll = (cursor_width + 1).saturating_sub(input_view_width);
+
+            let input = get_input_box(input_string, input_title, input_mode)
+                .scroll((0, input_scroll as u16));
 
             rect.render_widget(input, chunks[2]);
 
@@ -258,53 +498,52 @@ pub fn draw(app: &mut App, terminal: &mut Arc<Mutex<Terminal<CrosstermBackend<St
                     // Hide the cursor. `Frame` does this by default, so we don't need to do anything here
                     {}
 
-                InputMode::Editing => {
+                InputMode::Editing | InputMode::Search => {
                     // Make the cursor visible and ask tui-rs to put it at the specified coordinates after rendering
                     rect.set_cursor(
-                        // Put cursor past the end of the input text
-                        chunks[2].x + app.input_string_width() as u16 + 1,
+                        // Put cursor where it is in the input text
+                        chunks[2].x + (cursor_width - input_scroll) as u16 + 1,
                         // Move one line down, from the border to the input line
                         chunks[2].y + 1,
                     )
                 }
             }
 
-            // HACK
-            // we really need to use a stateful widget and scroll here, but for the moment,
-            // we will just truncate from the front of the messages when there are more
-            // of them than can fit on screen (this will work weird with wrap btw)
-            let num_ui_lines = (chunks[1].bottom() - chunks[1].top()) as usize;
-            if num_ui_lines < app.num_messages() {
-                let num_messages_to_drop = app.num_messages() - num_ui_lines;
+            // the borders take up a line/column on each side
+            let message_view_width = chunks[1].width.saturating_sub(2) as usize;
+            let message_view_height = chunks[1].height.saturating_sub(2) as usize;
 
-                app.drain_messages(num_messages_to_drop);
-            }
+            let search = app.get_search();
 
             let message_list = app
-                .message_list()
-                .iter()
-                .cloned()
-                .map(|s| {
-                    let content = vec![Spans::from(Span::raw(s))];
-                    ListItem::new(content)
-                })
+                .visible_message_lines(message_view_width, message_view_height)
+                .into_iter()
+                .map(|line| ListItem::new(highlight_line(line, &search, client.name())))
                 .collect::<Vec<_>>();
 
+            let mut message_list_title = match app.input_target() {
+                Some(peer) => format!("Whispers with {}", peer),
+                None => "Messages".to_string(),
+            };
+            if app.is_scrolled() {
+                message_list_title.push_str(" (scrolled back, PgDn for newer)");
+            }
+
             let message_list = List::new(message_list)
-                .block(Block::default().borders(Borders::ALL).title("Messages"));
+                .block(Block::default().borders(Borders::ALL).title(message_list_title));
 
             let menu = menu_titles
-                .iter()
+                .into_iter()
                 .map(|t| {
                     let (first, rest) = t.split_at(1);
                     Spans::from(vec![
                         Span::styled(
-                            first,
+                            first.to_string(),
                             Style::default()
                                 .fg(Color::Yellow)
                                 .add_modifier(Modifier::UNDERLINED),
                         ),
-                        Span::styled(rest, Style::default().fg(Color::White)),
+                        Span::styled(rest.to_string(), Style::default().fg(Color::White)),
                     ])
                 })
                 .collect();
@@ -317,7 +556,7 @@ pub fn draw(app: &mut App, terminal: &mut Arc<Mutex<Terminal<CrosstermBackend<St
                 .divider(Span::raw("|"));
 
             match app.get_active_menu_item() {
-                MenuItem::Home => {
+                MenuItem::Home | MenuItem::Conversation(_) => {
                     rect.render_widget(message_list, chunks[1]);
                 }
                 MenuItem::Logs => {
@@ -331,22 +570,202 @@ pub fn draw(app: &mut App, terminal: &mut Arc<Mutex<Terminal<CrosstermBackend<St
                         .style(Style::default().fg(Color::White).bg(Color::Black));
                     rect.render_widget(tui_w, chunks[1]);
                 }
+                MenuItem::Network => {
+                    let network_chunks = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                        .split(chunks[1]);
+
+                    let widths = [
+                        Constraint::Length(2),
+                        Constraint::Percentage(18),
+                        Constraint::Percentage(32),
+                        Constraint::Percentage(18),
+                        Constraint::Percentage(10),
+                        Constraint::Percentage(15),
+                    ];
+                    let peer_table = get_peer_table(&topology, &routes, client.name(), &widths);
+                    rect.render_widget(peer_table, network_chunks[0]);
+
+                    let world_map = get_world_map(&topology, client.name());
+                    rect.render_widget(world_map, network_chunks[1]);
+                }
             }
 
             rect.render_widget(tabs, chunks[0]);
 
-            rect.render_widget(Paragraph::new("THIS IS CHUNK 3"), chunks[3]);
+            text.extend(Text::raw(format!(
+                "{} known peers, {} reachable from us",
+                topology.nodes.len(),
+                routes.len()
+            )));
+            rect.render_widget(Paragraph::new(text).wrap(Wrap { trim: true }), chunks[3]);
         })
         .unwrap();
 }
 
-fn get_input_box(input_string: String, input_mode: InputMode) -> Paragraph<'static> {
+/// Highlights the parts of `line` that mention us, and every (ASCII case insensitive)
+/// occurrence of `search` in it.
+fn highlight_line(line: String, search: &str, my_name: &str) -> Spans<'static> {
+    let mention_style = Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD);
+    let search_style = Style::default().fg(Color::Black).bg(Color::Yellow);
+
+    let mut highlights = find_mentions(&line, my_name)
+        .into_iter()
+        .map(|range| (range, mention_style))
+        .collect::<Vec<_>>();
+
+    if !search.is_empty() {
+        // lowercasing ASCII doesn't change any byte offsets, so indices into `haystack`
+        // are good for `line` too
+        let haystack = line.to_ascii_lowercase();
+        let needle = search.to_ascii_lowercase();
+
+        highlights.extend(
+            haystack
+                .match_indices(&needle)
+                .map(|(start, m)| (start..start + m.len(), search_style)),
+        );
+    }
+
+    if highlights.is_empty() {
+        return Spans::from(line);
+    }
+
+    highlights.sort_by_key(|(range, _)| range.start);
+
+    let mut spans = vec![];
+    let mut last = 0;
+    for (range, style) in highlights {
+        // where a mention and a search match overlap, the first one wins
+        if range.start < last {
+            continue;
+        }
+
+        spans.push(Span::raw(line[last..range.start].to_string()));
+        spans.push(Span::styled(line[range.clone()].to_string(), style));
+        last = range.end;
+    }
+    spans.push(Span::raw(line[last..].to_string()));
+
+    Spans::from(spans)
+}
+
+/// A table with a row for every peer we know about, showing its direct neighbors (and the
+/// latency of those links) and how we would route to it.
+fn get_peer_table<'a>(
+    topology: &Topology,
+    routes: &[Route],
+    my_name: &str,
+    widths: &'a [Constraint],
+) -> Table<'a> {
+    let header = Row::new(vec!["#", "Peer", "Neighbors (latency)", "Next hop", "Cost", "Location"])
+        .style(Style::default().fg(Color::Yellow))
+        .bottom_margin(1);
+
+    let rows = topology
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            let neighbors = node
+                .links
+                .iter()
+                .map(|link| format!("{} ({})", link.peer, link.latency))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let (next_hop, cost) = if node.name == my_name {
+                ("-".to_string(), "0".to_string())
+            } else {
+                match routes.iter().find(|route| route.destination == node.name) {
+                    Some(route) => (route.next_hop.clone(), route.cost.to_string()),
+                    None => ("unreachable".to_string(), "-".to_string()),
+                }
+            };
+
+            // we haven't heard from stale peers in a while, so what they told us is suspect
+            let style = if node.name == my_name {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else if node.stale {
+                Style::default().add_modifier(Modifier::DIM)
+            } else {
+                Style::default()
+            };
+
+            Row::new(vec![
+                Cell::from(map_marker(i)),
+                Cell::from(node.name.clone()),
+                Cell::from(neighbors),
+                Cell::from(next_hop),
+                Cell::from(cost),
+                Cell::from(node.city.clone()),
+            ])
+            .style(style)
+        })
+        .collect::<Vec<_>>();
+
+    Table::new(rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title("Peers"))
+        .widths(widths)
+        .column_spacing(1)
+}
+
+/// Markers used to label peers on the world map; the same marker shows up in the `#`
+/// column of the peer table.
+/// Canvas labels have to be `&'static str`s, which is why we can't just print peer names.
+const MAP_MARKERS: [&str; 36] = [
+    "1", "2", "3", "4", "5", "6", "7", "8", "9", "a", "b", "c", "d", "e", "f", "g", "h", "i",
+    "j", "k", "l", "m", "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z", "0",
+];
+
+fn map_marker(i: usize) -> &'static str {
+    MAP_MARKERS.get(i).copied().unwrap_or("*")
+}
+
+/// A world map with every peer we know about plotted at the lat/lng from its Announce.
+fn get_world_map(
+    topology: &Topology,
+    my_name: &str,
+) -> Canvas<'static, impl Fn(&mut tui::widgets::canvas::Context)> {
+    let peers = topology
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.lng, node.lat, map_marker(i), node.name == my_name))
+        .collect::<Vec<_>>();
+
+    Canvas::default()
+        .block(Block::default().borders(Borders::ALL).title("World"))
+        .x_bounds([-180.0, 180.0])
+        .y_bounds([-90.0, 90.0])
+        .paint(move |ctx| {
+            ctx.draw(&Map {
+                color: Color::DarkGray,
+                resolution: MapResolution::High,
+            });
+            ctx.layer();
+
+            for &(lng, lat, marker, is_me) in &peers {
+                let color = if is_me { Color::Yellow } else { Color::Cyan };
+
+                ctx.draw(&Points {
+                    coords: &[(lng, lat)],
+                    color,
+                });
+                ctx.print(lng, lat, marker, color);
+            }
+        })
+}
+
+fn get_input_box(input_string: String, title: String, input_mode: InputMode) -> Paragraph<'static> {
     Paragraph::new(input_string)
         .style(match input_mode {
             InputMode::Normal => Style::default(),
-            InputMode::Editing => Style::default().fg(Color::Yellow),
+            InputMode::Editing | InputMode::Search => Style::default().fg(Color::Yellow),
         })
-        .block(Block::default().borders(Borders::ALL).title("Input"))
+        .block(Block::default().borders(Borders::ALL).title(title))
 }
 
 fn get_input_mode_message(input_mode: InputMode) -> (Vec<Span<'static>>, Style) {
@@ -357,7 +776,15 @@ fn get_input_mode_message(input_mode: InputMode) -> (Vec<Span<'static>>, Style)
                 Span::styled("q", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" to exit, "),
                 Span::styled("e", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to start editing."),
+                Span::raw(" to start editing, "),
+                Span::styled("Tab", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to switch tabs, "),
+                Span::styled("x", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to close a conversation, "),
+                Span::styled("/", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to search, "),
+                Span::styled("PgUp/PgDn", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to scroll."),
             ],
             Style::default().add_modifier(Modifier::RAPID_BLINK),
         ),
@@ -367,7 +794,23 @@ fn get_input_mode_message(input_mode: InputMode) -> (Vec<Span<'static>>, Style)
                 Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" to stop editing, "),
                 Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to record the message"),
+                Span::raw(" to send the message or command ("),
+                Span::styled("/help", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" lists them, "),
+                Span::styled("Tab", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" completes commands and peers, "),
+                Span::styled("Up/Down", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" go through what you've sent)"),
+            ],
+            Style::default(),
+        ),
+        InputMode::Search => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to stop searching, "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to stop and clear the search"),
             ],
             Style::default(),
         ),
@@ -390,87 +833,163 @@ fn build_chunks(size: Rect) -> Vec<Rect> {
         .split(size)
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum MenuItem {
     Home,
     Logs,
+    Network,
+    /// The whisper conversation at this index (see [App::open_conversation()]).
+    Conversation(usize),
 }
 
+/// The number of tabs that come before the conversation tabs.
+const NUM_FIXED_MENU_ITEMS: usize = 3;
+
 impl From<MenuItem> for usize {
     fn from(input: MenuItem) -> usize {
         match input {
             MenuItem::Home => 0,
             MenuItem::Logs => 1,
+            MenuItem::Network => 2,
+            MenuItem::Conversation(i) => NUM_FIXED_MENU_ITEMS + i,
+        }
+    }
+}
+
+impl From<usize> for MenuItem {
+    fn from(input: usize) -> MenuItem {
+        match input {
+            0 => MenuItem::Home,
+            1 => MenuItem::Logs,
+            2 => MenuItem::Network,
+            i => MenuItem::Conversation(i - NUM_FIXED_MENU_ITEMS),
         }
     }
 }
 
 #[tokio::main]
+#[allow(clippy::too_many_arguments)]
 async fn start_peer(
     app: App,
     client: Client,
     server: Server,
-    tracker_ip_address: String,
-    tracker_port: u16,
+    tracker_addr: SocketAddr,
     max_incoming_connections: u64,
     mut ui_rx: UiClientRxChannel,
+    topology_file: Option<PathBuf>,
+    alerter: Alerter,
 ) {
     trace!("Starting peer...");
+    // this has to happen before the server starts, so that we don't miss anything
+    let mut events = client.events();
+
     tokio::spawn(async move {
         server
-            .start(
-                &tracker_ip_address,
-                &tracker_port.to_string(),
-                max_incoming_connections,
-            )
+            .start(tracker_addr, max_incoming_connections)
             .await
             .unwrap_or_else(|e| {
                 debug!("Server shut down: {}", e);
             });
     });
 
+    if let Some(topology_file) = topology_file {
+        spawn_topology_writer(client.clone(), topology_file);
+    }
+
     let moved_client = client.clone();
     tokio::spawn(async move {
         loop {
             if let Some(message_from_ui) = ui_rx.recv().await {
                 trace!("Received {:?} from Ui", message_from_ui);
-                match message_from_ui {
-                    UiClientMessage::Say(message) => {
-                        moved_client.say(message).await;
-                    },
-					UiClientMessage::Whisper(to, message) => {
-						moved_client.whisper(to, message).await;
-					},
-                }
+                command::dispatch(&moved_client, message_from_ui).await;
             }
         }
     });
 
     let x = tokio::spawn(async move {
-        loop {
-            trace!("Waiting for next message from client");
-            let from_server_message = client.next_message().await;
+        while let Some(event) = events.next().await {
+            trace!("Got {:?} from client", event);
+
+            let from_server_message = match event {
+                ClientEvent::Message(message) => message,
+                ClientEvent::TopologyChanged => {
+                    app.refresh_network(&client);
+                    continue;
+                }
+                ClientEvent::DeliveryFailed { ref to, .. } => {
+                    // the conversation with `to` is where the user will be looking for it
+                    if let Some(notice) = format_event(&event) {
+                        app.add_whisper(to, &notice);
+                    }
+                    continue;
+                }
+                event => {
+                    if let Some(notice) = format_event(&event) {
+                        app.add_message(&notice);
+                    }
+                    continue;
+                }
+            };
 
             match from_server_message {
-                ClientServerMessage::Say((from, msg)) => {
+                ClientServerMessage::Say((from, msg))
+                | ClientServerMessage::ScopedSay((from, _, msg)) => {
+                    let formatted_say = format!(
+                        "[{}] {}: {}",
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        from,
+                        msg
+                    );
+
+                    if find_mentions(&msg, client.name()).is_empty() {
+                        app.add_message(&formatted_say);
+                    } else {
+                        alerter.alert(AlertKind::Mention, &from, &msg);
+                        app.add_mention(&formatted_say);
+                    }
+                },
+                ClientServerMessage::ChannelSay((from, channel, msg)) => {
                     let formatted_say = format!(
-                        "[{}] {}: {}\n",
+                        "[{}] {} in {}: {}",
                         Local::now().format("%Y-%m-%d %H:%M:%S"),
                         from,
+                        channel,
                         msg
                     );
-                    app.add_message(&formatted_say);
+
+                    if find_mentions(&msg, client.name()).is_empty() {
+                        app.add_message(&formatted_say);
+                    } else {
+                        alerter.alert(AlertKind::Mention, &from, &msg);
+                        app.add_mention(&formatted_say);
+                    }
                 },
-				ClientServerMessage::Whisper((from, to, msg)) => {
+				ClientServerMessage::Whisper((from, to, msg))
+				| ClientServerMessage::ImportantWhisper((from, to, msg)) => {
 					let formatted_say = format!(
-						"[{}] {} whispered to {}: {}\n",
+						"[{}] {}: {}",
                         Local::now().format("%Y-%m-%d %H:%M:%S"),
                         from,
-						to,
                         msg
 					);
-                    app.add_message(&formatted_say);
+
+					if from != client.name() {
+						alerter.alert(AlertKind::Whisper, &from, &msg);
+					}
+
+					// the conversation is with whoever is on the other end
+					let peer = if from == client.name() { to } else { from };
+                    app.add_whisper(&peer, &formatted_say);
 				},
+                // deliveries arrive as ClientEvent::Delivered, not as messages
+                ClientServerMessage::Deliver(_) | ClientServerMessage::ImportantDeliver(_) => {},
+                ClientServerMessage::Throttled(who) => {
+                    app.add_message(&format!(
+                        "[{}] *** {} is sending too fast; dropping some of what it sends",
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        who
+                    ));
+                },
             }
         }
     });
@@ -495,37 +1014,181 @@ impl App {
         self.shared.state.lock().unwrap().input_mode
     }
 
-    pub fn input_string_push(&self, c: char) {
-        self.shared.state.lock().unwrap().input_string.push(c);
+    /// Do something with the [LineEditor] of the input box.
+    pub fn edit_input<R>(&self, f: impl FnOnce(&mut LineEditor) -> R) -> R {
+        f(&mut self.shared.state.lock().unwrap().input)
     }
 
-    pub fn input_string_pop(&self) -> Option<char> {
-        self.shared.state.lock().unwrap().input_string.pop()
-    }
+    /// The text in the input box, and how wide the part before the cursor is.
+    pub fn get_input(&self) -> (String, usize) {
+        let state = self.shared.state.lock().unwrap();
 
-    pub fn input_string_drain(&self) -> String {
-        self.shared
-            .state
-            .lock()
-            .unwrap()
-            .input_string
-            .drain(..)
-            .collect::<String>()
+        (state.input.line().to_string(), state.input.cursor_width())
     }
 
     /// Add a message that will be displayed in the main ui frame.
     pub fn add_message(&self, message: &str) {
+        let mut state = self.shared.state.lock().unwrap();
+        let width = state.view_width;
+
+        state.messages.push(message, width)
+    }
+
+    /// Add a message loaded from the history. Whispers go in the conversation they belong to,
+    /// but (since they're old news) don't count as unread.
+    pub fn add_from_history(&self, entry: &HistoryEntry, my_name: &str) {
+        let message = format_history_entry(entry);
+
+        let mut state = self.shared.state.lock().unwrap();
+        let width = state.view_width;
+
+        match &entry.to {
+            Some(to) => {
+                let peer = if entry.from == my_name { to } else { &entry.from };
+                let i = state.conversation_index(peer);
+                state.conversations[i].messages.push(&message, width);
+            }
+            None => state.messages.push(&message, width),
+        }
+    }
+
+    /// Add a message that mentions us to the Home tab.
+    /// Unless we're looking at Home, this counts as an unread mention.
+    pub fn add_mention(&self, message: &str) {
+        let mut state = self.shared.state.lock().unwrap();
+        let width = state.view_width;
+
+        state.messages.push(message, width);
+        if state.active_menu_item != MenuItem::Home {
+            state.unread_mentions += 1;
+        }
+    }
+
+    /// Add a message to the conversation with `peer`, starting the conversation if needed.
+    /// Unless the conversation is the active tab, this counts as unread.
+    pub fn add_whisper(&self, peer: &str, message: &str) {
+        let mut state = self.shared.state.lock().unwrap();
+
+        let i = state.conversation_index(peer);
+        let is_active = state.active_menu_item == MenuItem::Conversation(i);
+
+        let width = state.view_width;
+        let conversation = &mut state.conversations[i];
+        conversation.messages.push(message, width);
+        if !is_active {
+            conversation.unread += 1;
+        }
+    }
+
+    /// Switch to the conversation with `peer`, starting it if needed.
+    pub fn open_conversation(&self, peer: &str) {
+        let i = self.shared.state.lock().unwrap().conversation_index(peer);
+
+        self.set_active_menu_item(MenuItem::Conversation(i));
+    }
+
+    /// Closes the active tab if it's a conversation, going back to the Home tab.
+    pub fn close_active_conversation(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if let MenuItem::Conversation(i) = state.active_menu_item {
+            state.conversations.remove(i);
+            state.active_menu_item = MenuItem::Home;
+        }
+    }
+
+    /// Who messages typed into the active tab are whispered to, if anyone.
+    pub fn input_target(&self) -> Option<String> {
+        let state = self.shared.state.lock().unwrap();
+
+        match state.active_menu_item {
+            MenuItem::Conversation(i) => Some(state.conversations[i].peer.clone()),
+            _ => None,
+        }
+    }
+
+    /// Add a message (e.g., an error) to whichever of Home or a conversation is being looked at.
+    pub fn add_notice(&self, message: &str) {
+        let mut state = self.shared.state.lock().unwrap();
+        let width = state.view_width;
+
+        state.active_messages_mut().push(message, width)
+    }
+
+    /// The lines of the active tab's messages that fit in a `width` by `height` view.
+    /// This also remembers the size of the view, for scrolling and searching.
+    pub fn visible_message_lines(&self, width: usize, height: usize) -> Vec<String> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.view_width = width;
+        state.view_height = height;
+
+        state.active_messages_mut().visible_lines(width, height)
+    }
+
+    pub fn is_scrolled(&self) -> bool {
         self.shared
             .state
             .lock()
             .unwrap()
-            .messages
-            .push_back(message.to_string())
+            .active_messages_mut()
+            .is_scrolled()
     }
 
-    /// Get the number of messages we have in our buffer
-    pub fn num_messages(&self) -> usize {
-        self.shared.state.lock().unwrap().messages.len()
+    /// Scroll the active tab's messages up by (almost) a page.
+    pub fn scroll_messages_up(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        let page = state.page_size();
+
+        state.active_messages_mut().scroll_up(page)
+    }
+
+    /// Scroll the active tab's messages down by (almost) a page.
+    pub fn scroll_messages_down(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        let page = state.page_size();
+
+        state.active_messages_mut().scroll_down(page)
+    }
+
+    /// Start a new search of the active tab's messages.
+    pub(crate) fn start_search(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.search.clear();
+        state.input_mode = InputMode::Search;
+    }
+
+    pub fn get_search(&self) -> String {
+        self.shared.state.lock().unwrap().search.clone()
+    }
+
+    pub fn clear_search(&self) {
+        self.shared.state.lock().unwrap().search.clear()
+    }
+
+    /// Adds a character to the search, jumping to the newest match from where we are.
+    pub fn search_push(&self, c: char) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.search.push(c);
+        state.search(false);
+    }
+
+    pub fn search_pop(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.search.pop();
+        state.search(false);
+    }
+
+    /// Jump to the previous (older) match.
+    pub fn search_older(&self) {
+        self.shared.state.lock().unwrap().search(true);
+    }
+
+    /// Jump to the next (newer) match.
+    pub fn search_newer(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        let (search, width) = (state.search.clone(), state.view_width);
+
+        state.active_messages_mut().search_newer(&search, width);
     }
 
     /// Switch between input modes (edit and regular)
@@ -537,20 +1200,86 @@ impl App {
         self.shared.state.lock().unwrap().active_menu_item
     }
 
+    /// Switch tabs. Switching to a conversation marks it as read;
+    /// conversations that don't exist are ignored.
     pub(crate) fn set_active_menu_item(&self, menu_item: MenuItem) {
-        self.shared.state.lock().unwrap().active_menu_item = menu_item
+        let mut state = self.shared.state.lock().unwrap();
+
+        match menu_item {
+            MenuItem::Home => state.unread_mentions = 0,
+            MenuItem::Conversation(i) => match state.conversations.get_mut(i) {
+                Some(conversation) => conversation.unread = 0,
+                None => return,
+            },
+            _ => {}
+        }
+
+        state.active_menu_item = menu_item
+    }
+
+    pub(crate) fn next_menu_item(&self) {
+        let (active, num_items) = self.menu_position();
+
+        self.set_active_menu_item(MenuItem::from((active + 1) % num_items));
+    }
+
+    pub(crate) fn previous_menu_item(&self) {
+        let (active, num_items) = self.menu_position();
+
+        self.set_active_menu_item(MenuItem::from((active + num_items - 1) % num_items));
+    }
+
+    /// The index of the active tab, and the total number of tabs.
+    fn menu_position(&self) -> (usize, usize) {
+        let state = self.shared.state.lock().unwrap();
+
+        (
+            state.active_menu_item.into(),
+            NUM_FIXED_MENU_ITEMS + state.conversations.len(),
+        )
     }
 
-    pub fn drain_messages(&self, n: usize) {
-        drop(self.shared.state.lock().unwrap().messages.drain(..n))
+    /// The titles of every tab; conversations get a number and their unread count, and Home
+    /// gets the number of unread mentions.
+    pub fn menu_titles(&self) -> Vec<String> {
+        let state = self.shared.state.lock().unwrap();
+
+        let home = if state.unread_mentions > 0 {
+            format!("Home (@{})", state.unread_mentions)
+        } else {
+            "Home".to_string()
+        };
+
+        let mut titles = vec![home, "Logs".to_string(), "Network".to_string()];
+
+        for (i, conversation) in state.conversations.iter().enumerate() {
+            let title = if conversation.unread > 0 {
+                format!("{}:{} ({})", i + 1, conversation.peer, conversation.unread)
+            } else {
+                format!("{}:{}", i + 1, conversation.peer)
+            };
+
+            titles.push(title);
+        }
+
+        titles
     }
 
-    pub fn input_string_width(&self) -> usize {
-        self.shared.state.lock().unwrap().input_string.width()
+    /// Takes a new snapshot of the topology and routes for the Network tab (asking the
+    /// [Client] for them every time we draw is too expensive).
+    pub fn refresh_network(&self, client: &Client) {
+        let topology = client.topology();
+        let routes = client.routes();
+
+        let mut state = self.shared.state.lock().unwrap();
+        state.topology = topology;
+        state.routes = routes;
     }
 
-    pub fn clone_input_string(&self) -> String {
-        self.shared.state.lock().unwrap().input_string.clone()
+    /// The topology and routes as of the last [App::refresh_network()].
+    pub fn network(&self) -> (Topology, Vec<Route>) {
+        let state = self.shared.state.lock().unwrap();
+        (state.topology.clone(), state.routes.clone())
     }
 
     pub fn is_shutdown(&self) -> bool {
@@ -560,16 +1289,6 @@ impl App {
     pub fn shutdown(&self) {
         self.shared.state.lock().unwrap().shut_down = true;
     }
-
-    pub fn message_list(&self) -> Vec<String> {
-        let messages = &self.shared.state.lock().unwrap().messages;
-
-        messages
-            .iter()
-            .enumerate()
-            .map(|(i, v)| format!("{}: {}", i, v))
-            .collect()
-    }
 }
 
 impl Default for App {
@@ -595,21 +1314,84 @@ impl Shared {
 
 #[derive(Debug)]
 struct State {
-    messages: VecDeque<String>,
+    messages: Scrollback,
+    /// Messages in Home that mention us that we haven't looked at yet.
+    unread_mentions: usize,
+    conversations: Vec<Conversation>,
     input_mode: InputMode,
     active_menu_item: MenuItem,
-    input_string: String,
+    input: LineEditor,
+    search: String,
+    /// The size of the message view the last time it was drawn.
+    view_width: usize,
+    view_height: usize,
+    /// What the Network tab shows (see [App::refresh_network()]).
+    topology: Topology,
+    routes: Vec<Route>,
     shut_down: bool,
 }
 
 impl State {
     fn new() -> Self {
         State {
-            messages: VecDeque::new(),
+            messages: Scrollback::new(),
+            unread_mentions: 0,
+            conversations: vec![],
             input_mode: InputMode::Normal,
             active_menu_item: MenuItem::Home,
-            input_string: String::new(),
+            input: LineEditor::new(),
+            search: String::new(),
+            // just a guess until we've drawn something
+            view_width: 80,
+            view_height: 20,
+            topology: Topology::default(),
+            routes: vec![],
             shut_down: false,
         }
     }
+
+    /// Finds the conversation with `peer`, starting a new one if there isn't one yet.
+    fn conversation_index(&mut self, peer: &str) -> usize {
+        match self.conversations.iter().position(|c| c.peer == peer) {
+            Some(i) => i,
+            None => {
+                self.conversations.push(Conversation {
+                    peer: peer.to_string(),
+                    messages: Scrollback::new(),
+                    unread: 0,
+                });
+                self.conversations.len() - 1
+            }
+        }
+    }
+
+    /// The messages shown in the active tab. Tabs without messages of their own
+    /// (Logs and Network) get the Home messages.
+    fn active_messages_mut(&mut self) -> &mut Scrollback {
+        match self.active_menu_item {
+            MenuItem::Conversation(i) => &mut self.conversations[i].messages,
+            _ => &mut self.messages,
+        }
+    }
+
+    /// How far PgUp/PgDn scroll; we keep one line of the previous page for context.
+    fn page_size(&self) -> usize {
+        self.view_height.saturating_sub(1).max(1)
+    }
+
+    /// Looks for the current search in the active tab's messages, going back from the
+    /// bottom of the view (or from just above it, if `skip_current` is set).
+    fn search(&mut self, skip_current: bool) {
+        let (search, width) = (self.search.clone(), self.view_width);
+
+        self.active_messages_mut().search_older(&search, width, skip_current);
+    }
+}
+
+/// A whisper conversation with a single peer.
+#[derive(Debug)]
+struct Conversation {
+    peer: String,
+    messages: Scrollback,
+    unread: usize,
 }
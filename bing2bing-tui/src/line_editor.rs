@@ -0,0 +1,195 @@
+use unicode_width::UnicodeWidthStr;
+
+/// A single line of text being edited, with a cursor and a history of previously
+/// submitted lines (for this session only).
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    line: String,
+    /// Byte index into `line`; always on a char boundary.
+    cursor: usize,
+    history: Vec<String>,
+    /// Where we are in `history` while going through it with up/down.
+    history_position: Option<usize>,
+    /// What was being typed before we started going through the history.
+    draft: String,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// How many columns of the terminal the text before the cursor takes up.
+    pub fn cursor_width(&self) -> usize {
+        self.line[..self.cursor].width()
+    }
+
+    /// Takes the line out of the editor (leaving it empty) and adds it to the history.
+    pub fn submit(&mut self) -> String {
+        let line = std::mem::take(&mut self.line);
+        self.cursor = 0;
+        self.history_position = None;
+
+        // no point remembering blank lines, or the same line over and over
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+
+        line
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.line.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Deletes the character before the cursor.
+    pub fn backspace(&mut self) {
+        if let Some(c) = self.line[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+            self.line.remove(self.cursor);
+        }
+    }
+
+    /// Deletes the character at (i.e., after) the cursor.
+    pub fn delete(&mut self) {
+        if self.cursor < self.line.len() {
+            self.line.remove(self.cursor);
+        }
+    }
+
+    pub fn left(&mut self) {
+        if let Some(c) = self.line[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+        }
+    }
+
+    pub fn right(&mut self) {
+        if let Some(c) = self.line[self.cursor..].chars().next() {
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    /// Moves to the start of the word before the cursor.
+    pub fn word_left(&mut self) {
+        let before = self.line[..self.cursor].trim_end();
+
+        self.cursor = before.rfind(' ').map(|i| i + 1).unwrap_or(0);
+    }
+
+    /// Moves to the end of the word after the cursor.
+    pub fn word_right(&mut self) {
+        let after = &self.line[self.cursor..];
+        let word_start = after.len() - after.trim_start().len();
+
+        self.cursor += after[word_start..]
+            .find(' ')
+            .map(|i| word_start + i)
+            .unwrap_or(after.len());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.line.len();
+    }
+
+    /// Replaces the line with the previous (older) entry in the history.
+    pub fn history_previous(&mut self) {
+        let position = match self.history_position {
+            Some(0) => return,
+            Some(position) => position - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.line.clone();
+                self.history.len() - 1
+            }
+        };
+
+        self.history_position = Some(position);
+        self.set_line(self.history[position].clone());
+    }
+
+    /// Replaces the line with the next (newer) entry in the history, or whatever was being
+    /// typed before going back through the history.
+    pub fn history_next(&mut self) {
+        let position = match self.history_position {
+            Some(position) => position + 1,
+            None => return,
+        };
+
+        if position < self.history.len() {
+            self.history_position = Some(position);
+            self.set_line(self.history[position].clone());
+        } else {
+            self.history_position = None;
+            let draft = std::mem::take(&mut self.draft);
+            self.set_line(draft);
+        }
+    }
+
+    /// Tab completion of the word before the cursor.
+    /// If it's the first word and starts with `/` it is completed from `commands`,
+    /// otherwise from `peers`.
+    ///
+    /// A single match is completed (followed by a space); if there are several, we complete as
+    /// much as they have in common and return them all so they can be shown to the user.
+    pub fn complete(&mut self, commands: &[&str], peers: &[String]) -> Vec<String> {
+        let word_start = self.line[..self.cursor].rfind(' ').map(|i| i + 1).unwrap_or(0);
+        let word = &self.line[word_start..self.cursor];
+
+        let candidates = match word.strip_prefix('/') {
+            Some(command) if word_start == 0 => commands
+                .iter()
+                .filter(|name| name.starts_with(command))
+                .map(|name| format!("/{}", name))
+                .collect::<Vec<_>>(),
+            _ if word.is_empty() => return vec![],
+            _ => peers
+                .iter()
+                .filter(|peer| peer.starts_with(word))
+                .cloned()
+                .collect::<Vec<_>>(),
+        };
+
+        let completion = match candidates.as_slice() {
+            [] => return vec![],
+            [only] => format!("{} ", only),
+            [first, rest @ ..] => rest.iter().fold(first.clone(), |prefix, candidate| {
+                common_prefix(&prefix, candidate).to_string()
+            }),
+        };
+
+        self.line.replace_range(word_start..self.cursor, &completion);
+        self.cursor = word_start + completion.len();
+
+        if candidates.len() > 1 {
+            candidates
+        } else {
+            vec![]
+        }
+    }
+
+    fn set_line(&mut self, line: String) {
+        self.line = line;
+        self.cursor = self.line.len();
+    }
+}
+
+/// The longest prefix `a` and `b` have in common.
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let len = a
+        .char_indices()
+        .zip(b.chars())
+        .find(|((_, a), b)| a != b)
+        .map(|((i, _), _)| i)
+        .unwrap_or_else(|| a.len().min(b.len()));
+
+    &a[..len]
+}
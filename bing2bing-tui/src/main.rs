@@ -1,6 +1,20 @@
 use structopt::StructOpt;
 
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use libb2b::{Client, ClientEvent, HistoryEntry, Location, RateLimit, RoutingMode};
+use tracing::error;
+
+mod alert;
+
+mod command;
+
+mod line_editor;
+
+mod scrollback;
 
 mod simple_tui;
 
@@ -11,17 +25,17 @@ pub struct Cli {
     /// What name shoudl this server have?
     #[structopt(long = "name", short = "-N")]
     name: String,
-    /// server ip address (0.0.0.0 should be any ip the server can listen on)
+    /// server ip address (IPv4 or IPv6). This is sent to other peers, so it must be reachable by them.
     #[structopt(long = "host", short = "-S")]
-    ip_address: Ipv4Addr,
+    ip_address: IpAddr,
 
     /// server port address
     #[structopt(short, long)]
     port: u16,
 
-    /// tracker ip address
+    /// tracker ip address (IPv4 or IPv6)
     #[structopt(long = "tracker-host", short = "-T")]
-    tracker_ip_address: Ipv4Addr,
+    tracker_ip_address: IpAddr,
 
     /// tracker port
     #[structopt(short, long)]
@@ -31,9 +45,100 @@ pub struct Cli {
     #[structopt(default_value = "2")]
     max_connections: u64,
 
-    /// Use simple ui mode? (/say and /quit are the only things that work)
+    /// Use simple ui mode? (a plain stdin/stdout ui that understands the same /commands)
     #[structopt(short, long)]
     simple: bool,
+
+    /// The city to announce to the network. If --locations is given, the lat/lng is looked up there.
+    #[structopt(long)]
+    city: Option<String>,
+
+    /// Latitude to announce to the network.
+    #[structopt(long, allow_hyphen_values = true)]
+    lat: Option<f64>,
+
+    /// Longitude to announce to the network.
+    #[structopt(long, allow_hyphen_values = true)]
+    lng: Option<f64>,
+
+    /// A local file of `city,lat,lng` lines to look up --city in.
+    #[structopt(long, parse(from_os_str), requires = "city")]
+    locations: Option<PathBuf>,
+
+    /// Periodically write a snapshot of the network topology to this file
+    /// (Graphviz DOT if it ends in .dot or .gv, JSON otherwise).
+    #[structopt(long, parse(from_os_str))]
+    topology_file: Option<PathBuf>,
+
+    /// Run this shell command when we're mentioned or whispered to, instead of ringing the
+    /// terminal bell. It gets $B2B_ALERT (mention or whisper), $B2B_FROM and $B2B_MESSAGE.
+    #[structopt(long)]
+    alert_command: Option<String>,
+
+    /// Save every message we send and receive to this file, and show the most recent ones on startup.
+    #[structopt(long, parse(from_os_str))]
+    history_file: Option<PathBuf>,
+
+    /// Listen for JSON control requests (say, whisper, peers, routes, subscribe) on this Unix socket.
+    #[structopt(long, parse(from_os_str))]
+    control_socket: Option<PathBuf>,
+
+    /// How to find routes to other peers: link-state (flood Announces) or distance-vector
+    /// (only exchange routes with neighbors). Every peer in the network should use the same one.
+    #[structopt(long, default_value = "link-state")]
+    routing: RoutingMode,
+
+    /// How many hops our Says and Announces may travel before peers stop forwarding them
+    /// (e.g., 16). There is no limit by default, since older peers can't read frames with one.
+    #[structopt(long)]
+    hop_limit: Option<u64>,
+
+    /// Pass Says on to only this many random peers instead of all of them, and periodically
+    /// swap digests with a random peer to catch up on the ones we missed.
+    #[structopt(long)]
+    gossip_fanout: Option<usize>,
+
+    /// How fast we take frames from any one peer, as RATE (a second) or RATE/BURST.
+    /// Frames over the limit are dropped.
+    #[structopt(long, default_value = "20/50")]
+    source_rate_limit: RateLimit,
+
+    /// How fast we take frames over any one incoming connection, as RATE (a second) or RATE/BURST.
+    #[structopt(long, default_value = "200/500")]
+    link_rate_limit: RateLimit,
+
+    /// Save the peers we /ignore to this file (one per line), and ignore the ones in it on startup.
+    #[structopt(long, parse(from_os_str))]
+    ignore_file: Option<PathBuf>,
+
+    /// Don't pass on anything from ignored peers either (by default we still relay it for others).
+    #[structopt(long)]
+    strict_ignore: bool,
+
+    /// Keep track of our sessions in this file, so that our sequence numbers keep going up
+    /// across restarts even if the clock goes backwards.
+    #[structopt(long, parse(from_os_str))]
+    session_file: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Figures out where this peer should say it is.
+    /// An explicit lat/lng wins, then a lookup of the city in the locations file,
+    /// and if nothing is given at all we fall back to [Location::default()].
+    /// A city on its own is an error, since we'd have no idea where it is.
+    pub fn location(&self) -> Result<Location, libb2b::Bing2BingError> {
+        let city = self.city.as_deref().unwrap_or("Unknown");
+
+        match (self.lat, self.lng, &self.locations) {
+            (Some(lat), Some(lng), _) => Ok(Location::new(city, lat, lng)),
+            (None, None, Some(locations)) => Location::lookup(locations, city),
+            (None, None, None) if self.city.is_some() => {
+                Err("--city needs --lat and --lng, or --locations to look it up in".into())
+            }
+            (None, None, None) => Ok(Location::default()),
+            _ => Err("--lat and --lng must be given together".into()),
+        }
+    }
 }
 
 #[tokio::main]
@@ -49,8 +154,61 @@ pub async fn main() -> Result<(), libb2b::Bing2BingError> {
     }
 }
 
-#[derive(Debug)]
+/// Writes a snapshot of the topology that `client` knows about to `path` every few seconds.
+/// Must be called from within a tokio runtime.
+pub(crate) fn spawn_topology_writer(client: Client, path: PathBuf) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = client.topology().write_to_file(&path) {
+                error!("Couldn't write topology to {:?}: {}", path, err);
+            }
+
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    });
+}
+
+/// How many messages from the history the UIs show on startup.
+pub(crate) const HISTORY_ON_STARTUP: usize = 100;
+
+/// Formats a message from the [History](libb2b::History) for display.
+pub(crate) fn format_history_entry(entry: &HistoryEntry) -> String {
+    let time = DateTime::<Local>::from(entry.time()).format("%Y-%m-%d %H:%M:%S");
+
+    match (&entry.to, &entry.channel) {
+        (Some(to), _) => format!("[{}] {} whispered to {}: {}", time, entry.from, to, entry.message),
+        (None, Some(channel)) => format!("[{}] {} in {}: {}", time, entry.from, channel, entry.message),
+        (None, None) => format!("[{}] {}: {}", time, entry.from, entry.message),
+    }
+}
+
+/// Formats a [ClientEvent] that isn't a message (e.g., a peer going away) for display.
+/// Returns `None` for messages, and for events that aren't worth showing.
+pub(crate) fn format_event(event: &ClientEvent) -> Option<String> {
+    let notice = match event {
+        ClientEvent::PeerConnected(peer) => format!("connected to {}", peer),
+        ClientEvent::PeerDisconnected(peer) => format!("lost our connection to {}", peer),
+        ClientEvent::DeliveryFailed {
+            to,
+            message,
+            reason,
+        } => format!("couldn't deliver {:?} to {}: {}", message, to, reason),
+        ClientEvent::Delivered { from, data } => format!("{} delivered {} bytes of data", from, data.len()),
+        ClientEvent::ServerStopped => {
+            "the server stopped; messages can't be sent or received anymore".to_string()
+        }
+        // the Network tab and the topology file are redrawn from scratch regularly anyway
+        ClientEvent::Message(_) | ClientEvent::TopologyChanged => return None,
+    };
+
+    Some(format!("[{}] *** {}", Local::now().format("%Y-%m-%d %H:%M:%S"), notice))
+}
+
+/// Messages from a UI that need to be passed on to the [Client] (see [command::dispatch()]).
+#[derive(Debug, Clone, PartialEq)]
 pub enum UiClientMessage {
     Say(String),
-	Whisper(String, String),
+    /// Say something to just the peers within this many hops.
+    SayWithin(u64, String),
+    Whisper(String, String),
 }
@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// How many messages a [Scrollback] keeps before it starts forgetting the oldest ones.
+pub const MAX_SCROLLBACK: usize = 1000;
+
+/// A bounded list of messages that can be scrolled through and searched.
+///
+/// Scrolling is measured in *wrapped* lines up from the bottom, so it depends on how wide
+/// the view the messages are drawn into is; that's why most methods take a `width`.
+#[derive(Debug, Default)]
+pub struct Scrollback {
+    messages: VecDeque<String>,
+    /// How many lines up from the bottom the view is; 0 means we're following new messages.
+    scroll: usize,
+    /// Total number of messages ever pushed, used to number them.
+    num_pushed: usize,
+}
+
+impl Scrollback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a message to the bottom of the scrollback.
+    /// If we're scrolled up, the view stays where it is instead of following along.
+    pub fn push(&mut self, message: &str, width: usize) {
+        let message = format!("{}: {}", self.num_pushed, message);
+        self.num_pushed += 1;
+
+        if self.scroll > 0 {
+            self.scroll += wrap(&message, width).len();
+        }
+
+        self.messages.push_back(message);
+
+        while self.messages.len() > MAX_SCROLLBACK {
+            self.messages.pop_front();
+        }
+    }
+
+    /// Every message, wrapped to fit in `width` columns.
+    pub fn lines(&self, width: usize) -> Vec<String> {
+        self.messages
+            .iter()
+            .flat_map(|message| wrap(message, width))
+            .collect()
+    }
+
+    /// The lines that fit in a `width` by `height` view at the current scroll position.
+    pub fn visible_lines(&mut self, width: usize, height: usize) -> Vec<String> {
+        let lines = self.lines(width);
+
+        self.scroll = self.scroll.min(lines.len().saturating_sub(height));
+
+        let end = lines.len() - self.scroll;
+        let start = end.saturating_sub(height);
+
+        lines[start..end].to_vec()
+    }
+
+    pub fn is_scrolled(&self) -> bool {
+        self.scroll > 0
+    }
+
+    /// Scrolls up by `n` lines. This can go past the top; [Scrollback::visible_lines()] fixes that.
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll = self.scroll.saturating_add(n);
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll = self.scroll.saturating_sub(n);
+    }
+
+    /// Scrolls so that the newest line matching `query` that is at or above the bottom of
+    /// the view (strictly above if `skip_current` is set) is at the bottom of the view.
+    /// Returns whether there was such a line.
+    pub fn search_older(&mut self, query: &str, width: usize, skip_current: bool) -> bool {
+        let lines = self.lines(width);
+        let bottom = match lines.len().checked_sub(self.scroll + 1) {
+            Some(bottom) => bottom,
+            None => return false,
+        };
+        let end = if skip_current { bottom } else { bottom + 1 };
+
+        match lines[..end].iter().rposition(|line| matches(line, query)) {
+            Some(i) => {
+                self.scroll = lines.len() - 1 - i;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Like [Scrollback::search_older()], but looks below the bottom of the view.
+    pub fn search_newer(&mut self, query: &str, width: usize) -> bool {
+        let lines = self.lines(width);
+        let start = lines.len().saturating_sub(self.scroll);
+
+        match lines[start..].iter().position(|line| matches(line, query)) {
+            Some(i) => {
+                self.scroll = lines.len() - 1 - (start + i);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Case insensitive (for ASCII) substring match.
+fn matches(line: &str, query: &str) -> bool {
+    !query.is_empty() && line.to_ascii_lowercase().contains(&query.to_ascii_lowercase())
+}
+
+/// Breaks `message` up into lines that are at most `width` columns wide, breaking at spaces
+/// where possible. Widths come from `unicode-width`, so e.g., CJK characters and emoji take
+/// up the two columns they really do on the terminal.
+/// Newlines in the message start a new line, and other control characters are dropped since
+/// they would mess up the terminal.
+pub fn wrap(message: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = vec![];
+
+    for paragraph in message.lines() {
+        let paragraph = paragraph
+            .chars()
+            .map(|c| if c == '\t' { ' ' } else { c })
+            .filter(|c| !c.is_control())
+            .collect::<String>();
+
+        let mut line = String::new();
+        let mut line_width = 0;
+
+        for word in paragraph.split(' ') {
+            let separator_width = if line.is_empty() { 0 } else { 1 };
+
+            if line_width + separator_width + word.width() <= width {
+                if separator_width > 0 {
+                    line.push(' ');
+                }
+                line.push_str(word);
+                line_width += separator_width + word.width();
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+
+            // words that are too long for a line of their own get broken up wherever
+            for c in word.chars() {
+                let c_width = c.width().unwrap_or(0);
+
+                if line_width + c_width > width && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+
+                line.push(c);
+                line_width += c_width;
+            }
+        }
+
+        lines.push(line);
+    }
+
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+
+    lines
+}
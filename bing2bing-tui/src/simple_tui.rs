@@ -1,3 +1,6 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
 use tracing::{debug, instrument, trace};
 use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
 
@@ -5,15 +8,21 @@ use tokio::sync::mpsc;
 
 use libb2b::Server;
 
-use libb2b::ClientServerMessage;
+use futures::StreamExt;
+use libb2b::{ClientEvent, ClientServerMessage};
 
-use libb2b::Client;
+use libb2b::{Client, History};
 
 use tokio::io::AsyncWriteExt;
 
 use chrono::Local;
 
-use crate::Cli;
+use crate::alert::{find_mentions, AlertKind, Alerter};
+use crate::command::{self, Command};
+use crate::{
+    format_event, format_history_entry, spawn_topology_writer, Cli, UiClientMessage,
+    HISTORY_ON_STARTUP,
+};
 
 type UiClientTxChannel = mpsc::UnboundedSender<UiClientMessage>;
 type UiClientRxChannel = mpsc::UnboundedReceiver<UiClientMessage>;
@@ -27,108 +36,173 @@ pub async fn start(args: Cli) -> Result<(), libb2b::Bing2BingError> {
 
     println!("Starting simple ui with args: {:?}", args);
 
-    let ip_address = args.ip_address.to_string().clone();
+    let ip_address = args.ip_address;
     let port = args.port;
 
-    let tracker_ip_address = args.tracker_ip_address.to_string().clone();
-    let tracker_port = args.tracker_port;
+    let tracker_addr = SocketAddr::new(args.tracker_ip_address, args.tracker_port);
+
+    let location = args.location()?;
 
     let my_name = args.name;
 
     let max_connections = args.max_connections;
 
+    let topology_file = args.topology_file;
+
+    let alerter = Alerter::new(args.alert_command);
+
+    let history_file = args.history_file;
+
+    let control_socket = args.control_socket;
+
     // *POINTS AVAILABLE*
     // I think this stuff can be refactored to be nicer
     let (ui_client_tx, ui_client_rx) = mpsc::unbounded_channel();
 
-    let (client, server) = libb2b::init(&my_name, &ip_address, port).await;
+    let (client, mut server) = libb2b::init(&my_name, ip_address, port).await;
+    server.set_location(location);
+    server.set_routing_mode(args.routing);
+    server.set_hop_limit(args.hop_limit);
+    server.set_gossip_fanout(args.gossip_fanout);
+    server.set_source_rate_limit(Some(args.source_rate_limit));
+    server.set_link_rate_limit(Some(args.link_rate_limit));
+
+    if let Some(session_file) = args.session_file {
+        server.set_session_file(session_file)?;
+    }
+
+    if let Some(ignore_file) = args.ignore_file {
+        client.load_ignore_list(ignore_file)?;
+    }
+    client.set_strict_ignore(args.strict_ignore);
+
+    if let Some(history_file) = history_file {
+        let history = History::open(history_file)?;
+
+        for entry in history.recent(HISTORY_ON_STARTUP)? {
+            println!("{}", format_history_entry(&entry));
+        }
+
+        client.set_history(history);
+    }
+
+    if let Some(control_socket) = control_socket {
+        libb2b::control::spawn_control_server(control_socket, client.clone());
+    }
 
     let network_client = client.clone();
     std::thread::spawn(move || {
         start_peer(
-            my_name,
             network_client,
             server,
-            tracker_ip_address,
-            tracker_port,
+            tracker_addr,
             max_connections,
             ui_client_rx,
+            topology_file,
+            alerter,
         )
     });
 
-    start_ui(App {}, ui_client_tx.clone()).await;
+    start_ui(App {}, client, ui_client_tx.clone()).await;
 
     Ok(())
 }
 
-#[derive(Debug, Clone)]
-pub enum UiClientMessage {
-    Say(String),
-	Whisper(String, String),
-}
-
 #[tokio::main]
 #[instrument(level = "trace")]
 async fn start_peer(
-    my_name: String,
     client: Client,
     server: Server,
-    tracker_ip_address: String,
-    tracker_port: u16,
+    tracker_addr: SocketAddr,
     max_incoming_connections: u64,
     mut ui_rx: UiClientRxChannel,
+    topology_file: Option<PathBuf>,
+    alerter: Alerter,
 ) {
     trace!("Starting peer...");
+    // this has to happen before the server starts, so that we don't miss anything
+    let mut events = client.events();
+
     tokio::spawn(async move {
         server
-            .start(
-                &tracker_ip_address,
-                &tracker_port.to_string(),
-                max_incoming_connections,
-            )
+            .start(tracker_addr, max_incoming_connections)
             .await
             .unwrap_or_else(|e| {
                 debug!("Server shut down: {}", e);
             });
     });
 
+    if let Some(topology_file) = topology_file {
+        spawn_topology_writer(client.clone(), topology_file);
+    }
+
     let moved_client = client.clone();
 
     tokio::spawn(async move {
         loop {
             if let Some(message_from_ui) = ui_rx.recv().await {
                 trace!("Received {:?} from Ui", message_from_ui);
-                match message_from_ui {
-                    UiClientMessage::Say(message) => {
-                        moved_client.say(message).await;
-                    },
-					UiClientMessage::Whisper(to, message) => {
-						moved_client.whisper(to, message).await;
-					},
-                }
+                command::dispatch(&moved_client, message_from_ui).await;
             }
         }
     });
 
     let x = tokio::spawn(async move {
-        loop {
-            trace!("Waiting for next message from client");
-            let from_server_message = client.next_message().await;
+        while let Some(event) = events.next().await {
+            trace!("Got {:?} from client", event);
 
             let mut stdout = tokio::io::stdout();
 
+            let from_server_message = match event {
+                ClientEvent::Message(message) => message,
+                event => {
+                    if let Some(notice) = format_event(&event) {
+                        stdout.write_all(format!("{}\n", notice).as_bytes()).await.unwrap();
+                        stdout.flush().await.unwrap();
+                    }
+                    continue;
+                }
+            };
+
             match from_server_message {
-                ClientServerMessage::Say((from, msg)) => {
+                ClientServerMessage::Say((from, msg))
+                | ClientServerMessage::ScopedSay((from, _, msg)) => {
+                    let mentions = find_mentions(&msg, client.name());
+                    if !mentions.is_empty() {
+                        alerter.alert(AlertKind::Mention, &from, &msg);
+                    }
+
                     let formatted_say = format!(
                         "[{}] {}: {}\n",
                         Local::now().format("%Y-%m-%d %H:%M:%S"),
                         from,
-                        msg
+                        highlight_mentions(&msg, &mentions)
                     );
                     stdout.write_all(formatted_say.as_bytes()).await.unwrap();
                     stdout.flush().await.unwrap();
                 },
-				ClientServerMessage::Whisper((from, to, msg)) => {
+                ClientServerMessage::ChannelSay((from, channel, msg)) => {
+                    let mentions = find_mentions(&msg, client.name());
+                    if !mentions.is_empty() {
+                        alerter.alert(AlertKind::Mention, &from, &msg);
+                    }
+
+                    let formatted_say = format!(
+                        "[{}] {} in {}: {}\n",
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        from,
+                        channel,
+                        highlight_mentions(&msg, &mentions)
+                    );
+                    stdout.write_all(formatted_say.as_bytes()).await.unwrap();
+                    stdout.flush().await.unwrap();
+                },
+				ClientServerMessage::Whisper((from, to, msg))
+				| ClientServerMessage::ImportantWhisper((from, to, msg)) => {
+					if from != client.name() {
+						alerter.alert(AlertKind::Whisper, &from, &msg);
+					}
+
 					let formatted_say = format!(
 						"[{}] {} whispered to {}: {}\n",
                         Local::now().format("%Y-%m-%d %H:%M:%S"),
@@ -139,6 +213,17 @@ async fn start_peer(
                     stdout.write_all(formatted_say.as_bytes()).await.unwrap();
                     stdout.flush().await.unwrap();
 				},
+                // deliveries arrive as ClientEvent::Delivered, not as messages
+                ClientServerMessage::Deliver(_) | ClientServerMessage::ImportantDeliver(_) => {},
+                ClientServerMessage::Throttled(who) => {
+                    let notice = format!(
+                        "[{}] *** {} is sending too fast; dropping some of what it sends\n",
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        who
+                    );
+                    stdout.write_all(notice.as_bytes()).await.unwrap();
+                    stdout.flush().await.unwrap();
+                },
             }
         }
     });
@@ -146,8 +231,25 @@ async fn start_peer(
     x.await.unwrap();
 }
 
+/// Makes the parts of `msg` that mention us stand out (bold and reversed).
+fn highlight_mentions(msg: &str, mentions: &[std::ops::Range<usize>]) -> String {
+    let mut highlighted = String::new();
+    let mut last = 0;
+
+    for mention in mentions {
+        highlighted.push_str(&msg[last..mention.start]);
+        highlighted.push_str("\x1b[1;7m");
+        highlighted.push_str(&msg[mention.clone()]);
+        highlighted.push_str("\x1b[0m");
+        last = mention.end;
+    }
+    highlighted.push_str(&msg[last..]);
+
+    highlighted
+}
+
 #[instrument(level = "trace")]
-async fn start_ui(app: App, client_tx: UiClientTxChannel) {
+async fn start_ui(app: App, client: Client, client_tx: UiClientTxChannel) {
     let ui_input = tokio::spawn(async move {
         let stdin = std::io::stdin();
 
@@ -157,31 +259,38 @@ async fn start_ui(app: App, client_tx: UiClientTxChannel) {
             let line = line.unwrap();
             trace!("ui had {:?} entered by user!", line);
 
-            if line == *"/quit" {
-                break;
+            if line.trim().is_empty() {
+                continue;
             }
 
-            if line.starts_with("/say ") {
-                trace!("input line started with /say !");
-                let line = line.clone().strip_prefix("/say ").unwrap().to_string();
-
-                let client_tx = client_tx.clone();
-
-                let msg = UiClientMessage::Say(line);
-	
-                trace!("Ui thread sending {:?} over client channle", msg);
-                client_tx.send(msg).unwrap();
+            match command::parse(&line) {
+                Ok(Command::Send(msg)) => {
+                    trace!("Ui thread sending {:?} over client channle", msg);
+                    client_tx.send(msg).unwrap();
+                }
+                Ok(Command::Help) => {
+                    for help_line in command::help_lines() {
+                        println!("{}", help_line);
+                    }
+                }
+                Ok(Command::History(query)) => {
+                    for history_line in command::history_lines(&client, &query) {
+                        println!("{}", history_line);
+                    }
+                }
+                Ok(Command::Ignore(peer)) => {
+                    for ignore_line in command::ignore_lines(&client, peer.as_deref()) {
+                        println!("{}", ignore_line);
+                    }
+                }
+                Ok(Command::Unignore(peer)) => {
+                    for ignore_line in command::unignore_lines(&client, &peer) {
+                        println!("{}", ignore_line);
+                    }
+                }
+                Ok(Command::Quit) => break,
+                Err(err) => println!("{}", err),
             }
-			if line.starts_with("/whisper ") {
-				trace!("input line started with /whisper !");
-				let string = line.clone().strip_prefix("/whisper ").unwrap().to_string();
-				let splitter: Vec<&str> = string.splitn(2, ' ').collect();
-				let client_tx = client_tx.clone();
-				if splitter.len() == 2{
-					let msg = UiClientMessage::Whisper(splitter[0].to_string(), splitter[1].to_string());
-					client_tx.send(msg).unwrap();
-				}
-			}
         }
     });
 
@@ -0,0 +1,101 @@
+//! How many frames a second can be checked for duplicates (and marked as processed) with
+//! [SequenceWindows], compared to the `"{source}-{seq}"` keys with a 30 second expiry that
+//! were used before.
+//!
+//! The workload looks like flooding does: a few dozen sources, and every frame showing up
+//! over three links, a little out of order.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use libb2b::SequenceWindows;
+
+const SOURCES: usize = 50;
+const FRAMES_PER_SOURCE: u64 = 20_000;
+const COPIES: usize = 3;
+
+/// What `TtlMap<bool>` did for every frame: format a key, look it up, and if it was new,
+/// insert it along with its expiration. (The background task that purged expired keys
+/// isn't included, which only flatters this approach.)
+#[derive(Default)]
+struct StringKeys {
+    entries: HashMap<String, (u64, Instant)>,
+    expirations: BTreeMap<(Instant, u64), String>,
+    next_id: u64,
+}
+
+impl StringKeys {
+    fn check_duplicate(&self, source: &str, sequence_number: u64) -> bool {
+        self.entries
+            .contains_key(&format!("{}-{}", source, sequence_number))
+    }
+
+    fn set_processed(&mut self, source: &str, sequence_number: u64) {
+        let key = format!("{}-{}", source, sequence_number);
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let when = Instant::now() + Duration::from_secs(30);
+        self.expirations.insert((when, id), key.clone());
+
+        if let Some((prev_id, prev_when)) = self.entries.insert(key, (id, when)) {
+            self.expirations.remove(&(prev_when, prev_id));
+        }
+    }
+}
+
+/// Every copy of every frame, in the order they arrive.
+fn workload(sources: &[String]) -> Vec<(&str, u64)> {
+    let mut frames = Vec::with_capacity(sources.len() * FRAMES_PER_SOURCE as usize * COPIES);
+
+    for sequence_number in 0..FRAMES_PER_SOURCE {
+        for (i, source) in sources.iter().enumerate() {
+            for copy in 0..COPIES as u64 {
+                // later copies are a few frames behind the first
+                let delayed = sequence_number.saturating_sub(copy * (i as u64 % 4));
+                frames.push((source.as_str(), delayed));
+            }
+        }
+    }
+
+    frames
+}
+
+fn report(name: &str, frames: usize, fresh: usize, elapsed: Duration) {
+    println!(
+        "{:<18} {:>12.0} frames/s ({} frames, {} new, {:?})",
+        name,
+        frames as f64 / elapsed.as_secs_f64(),
+        frames,
+        fresh,
+        elapsed
+    );
+}
+
+fn main() {
+    let sources = (0..SOURCES).map(|i| format!("peer{}", i)).collect::<Vec<_>>();
+    let frames = workload(&sources);
+
+    let mut string_keys = StringKeys::default();
+    let start = Instant::now();
+    let mut fresh = 0;
+    for &(source, sequence_number) in &frames {
+        if !string_keys.check_duplicate(black_box(source), black_box(sequence_number)) {
+            string_keys.set_processed(source, sequence_number);
+            fresh += 1;
+        }
+    }
+    report("string keys", frames.len(), fresh, start.elapsed());
+
+    let windows = SequenceWindows::new();
+    let start = Instant::now();
+    let mut fresh = 0;
+    for &(source, sequence_number) in &frames {
+        if !windows.contains(black_box(source), black_box(sequence_number)) {
+            windows.insert(source, sequence_number);
+            fresh += 1;
+        }
+    }
+    report("sequence windows", frames.len(), fresh, start.elapsed());
+}
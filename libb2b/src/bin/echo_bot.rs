@@ -0,0 +1,84 @@
+use std::net::{IpAddr, SocketAddr};
+use structopt::StructOpt;
+
+use tracing::debug;
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+use libb2b::{Bot, COMMAND_PREFIX};
+
+/// A bot that runs without a UI, and answers `!echo`, `!peers` and `!help`.
+#[derive(Debug, StructOpt, Clone)]
+struct Cli {
+    /// What name should the bot have?
+    #[structopt(long = "name", short = "-N", default_value = "echobot")]
+    name: String,
+
+    /// The bot's ip address (IPv4 or IPv6). This is sent to other peers, so it must be reachable by them.
+    #[structopt(long = "host", short = "-S")]
+    ip_address: IpAddr,
+
+    /// The bot's port
+    #[structopt(short, long)]
+    port: u16,
+
+    /// tracker ip address (IPv4 or IPv6)
+    #[structopt(long = "tracker-host", short = "-T")]
+    tracker_ip_address: IpAddr,
+
+    /// tracker port
+    #[structopt(short, long)]
+    tracker_port: u16,
+
+    /// maximum number of incomming connections that will be advertised when Announcing to the network.
+    #[structopt(default_value = "2")]
+    max_connections: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), libb2b::Bing2BingError> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::FULL)
+        .init();
+
+    let args = Cli::from_args();
+
+    println!("Bot starting with args: {:?}", args);
+
+    let tracker_addr = SocketAddr::new(args.tracker_ip_address, args.tracker_port);
+
+    let (client, server) = libb2b::init(&args.name, args.ip_address, args.port).await;
+
+    let max_connections = args.max_connections;
+    tokio::spawn(async move {
+        if let Err(err) = server.start(tracker_addr, max_connections).await {
+            debug!("Server shut down: {}", err);
+        }
+    });
+
+    Bot::new(client)
+        .command("echo", "repeats what you say", |ctx, _from, args| async move {
+            ctx.reply(args).await;
+        })
+        .command("peers", "lists the peers I know about", |ctx, _from, _args| async move {
+            let peers = ctx
+                .client()
+                .topology()
+                .nodes
+                .into_iter()
+                .map(|node| node.name)
+                .collect::<Vec<_>>();
+
+            ctx.reply(format!("I know about: {}", peers.join(", "))).await;
+        })
+        .on_whisper(|ctx, _from, msg| async move {
+            if !msg.starts_with(COMMAND_PREFIX) {
+                ctx.reply(format!("I'm just a bot; try {}help", COMMAND_PREFIX)).await;
+            }
+        })
+        .run()
+        .await;
+
+    Ok(())
+}
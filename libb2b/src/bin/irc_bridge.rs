@@ -0,0 +1,466 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use structopt::StructOpt;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, trace};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+use libb2b::{Client, ClientServerMessage};
+
+/// The name the bridge uses for itself when talking to IRC clients.
+const SERVER_NAME: &str = "b2b";
+
+/// The IRC channel for messages that aren't tagged with a channel (i.e., plain `Say`s).
+const DEFAULT_CHANNEL: &str = "#b2b";
+
+/// Lets IRC clients join the network: each IRC connection gets its own peer, named after its nick.
+///
+/// Messages to `#b2b` are said to everyone, messages to any other channel are said tagged with
+/// that channel (so only people in the channel see them), and messages to a nick are whispered.
+#[derive(Debug, StructOpt, Clone)]
+struct Cli {
+    /// The peers' ip address (IPv4 or IPv6). This is sent to other peers, so it must be reachable by them.
+    #[structopt(long = "host", short = "-S")]
+    ip_address: IpAddr,
+
+    /// The port of the first peer; every new peer gets the next port up.
+    #[structopt(short, long)]
+    port: u16,
+
+    /// tracker ip address (IPv4 or IPv6)
+    #[structopt(long = "tracker-host", short = "-T")]
+    tracker_ip_address: IpAddr,
+
+    /// tracker port
+    #[structopt(short, long)]
+    tracker_port: u16,
+
+    /// Where to listen for IRC connections. Keep this on localhost; there are no passwords.
+    #[structopt(long, default_value = "127.0.0.1:6667")]
+    listen: SocketAddr,
+
+    /// maximum number of incomming connections that will be advertised when Announcing to the network.
+    #[structopt(default_value = "2")]
+    max_connections: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), libb2b::Bing2BingError> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::FULL)
+        .init();
+
+    let args = Cli::from_args();
+
+    println!("IRC bridge starting with args: {:?}", args);
+
+    let listener = TcpListener::bind(args.listen).await?;
+    info!("Listening for IRC connections on {}", args.listen);
+
+    let bridge = Arc::new(Bridge::new(args));
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        debug!("IRC connection from {}", addr);
+
+        let bridge = bridge.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, bridge).await {
+                debug!("IRC connection from {} closed: {}", addr, err);
+            }
+        });
+    }
+}
+
+/// The peers the bridge has started, by nick.
+///
+/// There is no way to take a peer off the network once it has started, so when an IRC client
+/// disconnects its peer stays up, and is handed back to it if it reconnects with the same nick.
+#[derive(Debug)]
+struct Bridge {
+    args: Cli,
+    next_port: Mutex<u16>,
+    peers: Mutex<HashMap<String, Client>>,
+    connected: Mutex<HashSet<String>>,
+}
+
+impl Bridge {
+    fn new(args: Cli) -> Self {
+        Self {
+            next_port: Mutex::new(args.port),
+            args,
+            peers: Mutex::new(HashMap::new()),
+            connected: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Claims `nick` for a connection, returning `false` if some other connection already has it.
+    fn claim(&self, nick: &str) -> bool {
+        self.connected.lock().unwrap().insert(nick.to_string())
+    }
+
+    fn release(&self, nick: &str) {
+        self.connected.lock().unwrap().remove(nick);
+    }
+
+    /// The peer for `nick`, starting one if there isn't one yet.
+    async fn peer(&self, nick: &str) -> Client {
+        if let Some(client) = self.peers.lock().unwrap().get(nick) {
+            return client.clone();
+        }
+
+        let port = {
+            let mut next_port = self.next_port.lock().unwrap();
+            let port = *next_port;
+            *next_port += 1;
+            port
+        };
+
+        info!("Starting peer {} on port {}", nick, port);
+
+        let (client, server) = libb2b::init(nick, self.args.ip_address, port).await;
+
+        let tracker_addr = SocketAddr::new(self.args.tracker_ip_address, self.args.tracker_port);
+        let max_connections = self.args.max_connections;
+        tokio::spawn(async move {
+            if let Err(err) = server.start(tracker_addr, max_connections).await {
+                debug!("Server shut down: {}", err);
+            }
+        });
+
+        self.peers
+            .lock()
+            .unwrap()
+            .insert(nick.to_string(), client.clone());
+
+        client
+    }
+}
+
+/// A single line from an IRC client, e.g., `PRIVMSG #b2b :hello everyone`.
+#[derive(Debug)]
+struct IrcMessage {
+    command: String,
+    params: Vec<String>,
+}
+
+impl IrcMessage {
+    /// Parses a line, ignoring the prefix (clients aren't supposed to send one anyway).
+    fn parse(line: &str) -> Option<Self> {
+        let mut line = line.trim_end_matches(['\r', '\n']);
+
+        if line.starts_with(':') {
+            line = line.split_once(' ')?.1;
+        }
+
+        let (head, trailing) = match line.split_once(" :") {
+            Some((head, trailing)) => (head, Some(trailing)),
+            None => (line, None),
+        };
+
+        let mut words = head.split_whitespace();
+        let command = words.next()?.to_ascii_uppercase();
+
+        let mut params: Vec<String> = words.map(|word| word.to_string()).collect();
+        if let Some(trailing) = trailing {
+            params.push(trailing.to_string());
+        }
+
+        Some(Self { command, params })
+    }
+
+    fn param(&self, i: usize) -> Option<&str> {
+        self.params.get(i).map(|param| param.as_str())
+    }
+}
+
+/// The writing half of an IRC connection.
+struct IrcWriter {
+    writer: OwnedWriteHalf,
+    nick: String,
+}
+
+impl IrcWriter {
+    async fn send(&mut self, line: &str) -> Result<(), libb2b::Bing2BingError> {
+        trace!("Sending IRC line {:?}", line);
+        self.writer.write_all(line.as_bytes()).await?;
+        self.writer.write_all(b"\r\n").await?;
+        Ok(())
+    }
+
+    /// Sends a numeric reply, e.g., `:b2b 001 alice :Welcome`.
+    async fn reply(&mut self, numeric: &str, params: &str) -> Result<(), libb2b::Bing2BingError> {
+        let nick = if self.nick.is_empty() { "*" } else { &self.nick };
+        let line = format!(":{} {} {} {}", SERVER_NAME, numeric, nick, params);
+        self.send(&line).await
+    }
+
+    async fn pong(&mut self, token: &str) -> Result<(), libb2b::Bing2BingError> {
+        let line = format!(":{} PONG {} :{}", SERVER_NAME, SERVER_NAME, token);
+        self.send(&line).await
+    }
+
+    /// Sends `message` to `target` as if it came from `from`.
+    /// IRC messages can't contain newlines, so multi-line messages turn into multiple lines.
+    async fn privmsg(&mut self, from: &str, target: &str, message: &str) -> Result<(), libb2b::Bing2BingError> {
+        for line in message.lines() {
+            let line = format!(":{} PRIVMSG {} :{}", user_prefix(from), target, line);
+            self.send(&line).await?;
+        }
+        Ok(())
+    }
+}
+
+fn user_prefix(nick: &str) -> String {
+    format!("{}!{}@{}", nick, nick, SERVER_NAME)
+}
+
+/// IRC channel names are case insensitive, so channels are always tagged in lower case.
+fn channel_tag(channel: &str) -> String {
+    channel.to_ascii_lowercase()
+}
+
+fn is_channel(target: &str) -> bool {
+    target.starts_with('#') || target.starts_with('&')
+}
+
+async fn handle_connection(stream: TcpStream, bridge: Arc<Bridge>) -> Result<(), libb2b::Bing2BingError> {
+    let (reader, writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let mut writer = IrcWriter {
+        writer,
+        nick: String::new(),
+    };
+
+    let result = match register(&mut lines, &mut writer, &bridge).await {
+        Ok(true) => bridge_connection(&mut lines, &mut writer, &bridge).await,
+        Ok(false) => Ok(()),
+        Err(err) => Err(err),
+    };
+
+    if !writer.nick.is_empty() {
+        bridge.release(&writer.nick);
+    }
+
+    result
+}
+
+/// Waits for the client to register (i.e., send a `NICK` and a `USER`).
+/// Returns `false` if the client left before registering.
+async fn register(
+    lines: &mut Lines<BufReader<OwnedReadHalf>>,
+    writer: &mut IrcWriter,
+    bridge: &Bridge,
+) -> Result<bool, libb2b::Bing2BingError> {
+    let mut user = false;
+
+    while writer.nick.is_empty() || !user {
+        let line = match lines.next_line().await? {
+            Some(line) => line,
+            None => return Ok(false),
+        };
+
+        let message = match IrcMessage::parse(&line) {
+            Some(message) => message,
+            None => continue,
+        };
+
+        match message.command.as_str() {
+            "NICK" => match message.param(0) {
+                Some(nick) if bridge.claim(nick) => {
+                    if !writer.nick.is_empty() {
+                        bridge.release(&writer.nick);
+                    }
+                    writer.nick = nick.to_string();
+                }
+                Some(nick) => {
+                    let params = format!("{} :Nickname is already in use", nick);
+                    writer.reply("433", &params).await?;
+                }
+                None => writer.reply("431", ":No nickname given").await?,
+            },
+            "USER" => user = true,
+            "PING" => writer.pong(message.param(0).unwrap_or("")).await?,
+            // we don't support any capabilities, but clients that ask will wait for an answer
+            "CAP" if message.param(0) == Some("LS") => {
+                writer.send(&format!(":{} CAP * LS :", SERVER_NAME)).await?
+            }
+            "QUIT" => return Ok(false),
+            _ => {}
+        }
+    }
+
+    Ok(true)
+}
+
+/// Passes messages back and forth between a registered IRC connection and its peer.
+async fn bridge_connection(
+    lines: &mut Lines<BufReader<OwnedReadHalf>>,
+    writer: &mut IrcWriter,
+    bridge: &Bridge,
+) -> Result<(), libb2b::Bing2BingError> {
+    let nick = writer.nick.clone();
+    let client = bridge.peer(&nick).await;
+
+    writer
+        .reply("001", &format!(":Welcome to the bing2bing network, {}", nick))
+        .await?;
+    writer
+        .reply("002", &format!(":Messages to {} are said to everyone, and messages to other channels are tagged with the channel", DEFAULT_CHANNEL))
+        .await?;
+    writer.reply("422", ":MOTD File is missing").await?;
+
+    // the channels joined, as tags
+    let mut channels: HashSet<String> = HashSet::new();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = match line? {
+                    Some(line) => line,
+                    None => return Ok(()),
+                };
+
+                let message = match IrcMessage::parse(&line) {
+                    Some(message) => message,
+                    None => continue,
+                };
+                trace!("IRC message from {}: {:?}", nick, message);
+
+                if !handle_irc_message(message, writer, &client, &mut channels).await? {
+                    return Ok(());
+                }
+            },
+            message = client.next_message() => match message {
+                Some(message) => handle_b2b_message(message, writer, &channels).await?,
+                None => {
+                    // reconnecting should start a new peer rather than getting this one back
+                    bridge.peers.lock().unwrap().remove(&nick);
+                    writer.send("ERROR :The peer's server stopped").await?;
+                    return Ok(());
+                }
+            },
+        }
+    }
+}
+
+/// Carries out a message from the IRC client. Returns `false` if the client quit.
+async fn handle_irc_message(
+    message: IrcMessage,
+    writer: &mut IrcWriter,
+    client: &Client,
+    channels: &mut HashSet<String>,
+) -> Result<bool, libb2b::Bing2BingError> {
+    let nick = writer.nick.clone();
+
+    match message.command.as_str() {
+        "PRIVMSG" => {
+            let (target, text) = match (message.param(0), message.param(1)) {
+                (Some(target), Some(text)) => (target, text.to_string()),
+                _ => {
+                    writer.reply("461", "PRIVMSG :Not enough parameters").await?;
+                    return Ok(true);
+                }
+            };
+
+            if target.eq_ignore_ascii_case(DEFAULT_CHANNEL) {
+                client.say(text).await;
+            } else if is_channel(target) {
+                client.say_in_channel(channel_tag(target), text).await;
+            } else {
+                client.whisper(target.to_string(), text).await;
+            }
+        }
+        "JOIN" => {
+            let targets = message.param(0).unwrap_or("").to_string();
+
+            for channel in targets.split(',').filter(|channel| !channel.is_empty()) {
+                if !is_channel(channel) {
+                    writer.reply("403", &format!("{} :No such channel", channel)).await?;
+                    continue;
+                }
+
+                channels.insert(channel_tag(channel));
+                writer.send(&format!(":{} JOIN {}", user_prefix(&nick), channel)).await?;
+
+                // tagged messages go to everyone, so anyone might be listening in any channel
+                let mut names: Vec<String> = client
+                    .topology()
+                    .nodes
+                    .into_iter()
+                    .map(|node| node.name)
+                    .filter(|name| name != &nick)
+                    .collect();
+                names.insert(0, nick.clone());
+
+                writer.reply("353", &format!("= {} :{}", channel, names.join(" "))).await?;
+                writer.reply("366", &format!("{} :End of /NAMES list", channel)).await?;
+            }
+        }
+        "PART" => {
+            let targets = message.param(0).unwrap_or("").to_string();
+
+            for channel in targets.split(',').filter(|channel| !channel.is_empty()) {
+                if channels.remove(&channel_tag(channel)) {
+                    writer.send(&format!(":{} PART {}", user_prefix(&nick), channel)).await?;
+                } else {
+                    writer.reply("442", &format!("{} :You're not on that channel", channel)).await?;
+                }
+            }
+        }
+        "PING" => writer.pong(message.param(0).unwrap_or("")).await?,
+        "NICK" => {
+            writer.reply("484", ":Your nick is your peer's name, and can't be changed").await?;
+        }
+        "QUIT" => return Ok(false),
+        // things clients send on their own that we have nothing to say about
+        "NOTICE" | "PONG" | "MODE" | "WHO" | "USER" | "CAP" => {}
+        command => {
+            writer.reply("421", &format!("{} :Unknown command", command)).await?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Turns a message from the network into IRC lines, if the IRC client cares about it.
+async fn handle_b2b_message(
+    message: ClientServerMessage,
+    writer: &mut IrcWriter,
+    channels: &HashSet<String>,
+) -> Result<(), libb2b::Bing2BingError> {
+    let nick = writer.nick.clone();
+
+    match message {
+        ClientServerMessage::Say((from, msg))
+        | ClientServerMessage::ScopedSay((from, _, msg)) => {
+            if from != nick && channels.contains(DEFAULT_CHANNEL) {
+                writer.privmsg(&from, DEFAULT_CHANNEL, &msg).await?;
+            }
+        }
+        ClientServerMessage::ChannelSay((from, channel, msg)) => {
+            if from != nick && channels.contains(&channel_tag(&channel)) {
+                writer.privmsg(&from, &channel, &msg).await?;
+            }
+        }
+        ClientServerMessage::Whisper((from, to, msg))
+        | ClientServerMessage::ImportantWhisper((from, to, msg)) => {
+            if from != nick && to == nick {
+                writer.privmsg(&from, &nick, &msg).await?;
+            }
+        }
+        // raw data has nowhere to go on IRC
+        ClientServerMessage::Deliver(_) | ClientServerMessage::ImportantDeliver(_) => {}
+        // that's for whoever runs the bridge, not for IRC users
+        ClientServerMessage::Throttled(who) => debug!("Our peer is throttling {}", who),
+    }
+
+    Ok(())
+}
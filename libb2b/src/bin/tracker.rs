@@ -1,4 +1,4 @@
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, SocketAddr};
 use structopt::StructOpt;
 
 use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
@@ -7,9 +7,10 @@ use libb2b::Tracker;
 
 #[derive(Debug, StructOpt, Clone)]
 struct Cli {
-    /// ip address to bind to (0.0.0.0 should be any ip the tracker can listen on)
+    /// ip address to bind to (0.0.0.0 should be any IPv4 address the tracker can listen on,
+    /// :: will listen on every IPv6 and IPv4 address)
     #[structopt(long = "host", short = "-S")]
-    ip_address: Ipv4Addr,
+    ip_address: IpAddr,
 
     /// The port the tracker should listen on.
     #[structopt(short, long)]
@@ -29,10 +30,9 @@ async fn main() -> Result<(), libb2b::Bing2BingError> {
     println!("Tracker starting with args: {:?}", args);
     // let's start up a tracker  and listen.
 
-    let ip_address = &args.ip_address.to_string();
-    let port = &args.port.to_string();
+    let addr = SocketAddr::new(args.ip_address, args.port);
 
-    let tracker = Tracker::new(&ip_address, &port).await.unwrap();
+    let tracker = Tracker::new(addr).await.unwrap();
 
     tracker.listen().await?;
 
@@ -0,0 +1,84 @@
+use std::net::{IpAddr, SocketAddr};
+use structopt::StructOpt;
+
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+use libb2b::control::serve_websocket;
+
+/// Runs a peer and lets WebSocket clients (e.g., a web page) drive it.
+///
+/// Every WebSocket connection gets every message the peer receives, and can send the same JSON
+/// requests as the control socket (see `libb2b::control`), e.g.,
+/// `{"request": "say", "message": "hi"}` or `{"request": "whisper", "to": "bob", "message": "hi"}`.
+#[derive(Debug, StructOpt, Clone)]
+struct Cli {
+    /// What name should the peer have?
+    #[structopt(long = "name", short = "-N")]
+    name: String,
+
+    /// The peer's ip address (IPv4 or IPv6). This is sent to other peers, so it must be reachable by them.
+    #[structopt(long = "host", short = "-S")]
+    ip_address: IpAddr,
+
+    /// The peer's port
+    #[structopt(short, long)]
+    port: u16,
+
+    /// tracker ip address (IPv4 or IPv6)
+    #[structopt(long = "tracker-host", short = "-T")]
+    tracker_ip_address: IpAddr,
+
+    /// tracker port
+    #[structopt(short, long)]
+    tracker_port: u16,
+
+    /// Where to listen for WebSocket connections. Keep this on localhost; anyone who can
+    /// connect can talk as this peer.
+    #[structopt(long, default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+
+    /// maximum number of incomming connections that will be advertised when Announcing to the network.
+    #[structopt(default_value = "2")]
+    max_connections: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), libb2b::Bing2BingError> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::FULL)
+        .init();
+
+    let args = Cli::from_args();
+
+    println!("WebSocket gateway starting with args: {:?}", args);
+
+    let tracker_addr = SocketAddr::new(args.tracker_ip_address, args.tracker_port);
+
+    let (client, server) = libb2b::init(&args.name, args.ip_address, args.port).await;
+
+    let max_connections = args.max_connections;
+    tokio::spawn(async move {
+        if let Err(err) = server.start(tracker_addr, max_connections).await {
+            debug!("Server shut down: {}", err);
+        }
+    });
+
+    let listener = TcpListener::bind(args.listen).await?;
+    info!("Listening for WebSocket connections on {}", args.listen);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        debug!("WebSocket connection from {}", addr);
+
+        let client = client.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve_websocket(stream, client).await {
+                debug!("WebSocket connection from {} closed: {}", addr, err);
+            }
+        });
+    }
+}
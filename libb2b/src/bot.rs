@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tracing::{debug, trace};
+
+use crate::{Client, ClientServerMessage};
+
+/// Handles a message: gets a [BotContext], who the message is from, and the message itself
+/// (or, for commands, everything after the command name).
+type MessageHandler = Box<dyn Fn(BotContext, String, String) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Handles a timer going off.
+type TimerHandler = Arc<dyn Fn(BotContext) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// The prefix that marks a message as a bot command, e.g., `!help`.
+pub const COMMAND_PREFIX: char = '!';
+
+/// A small runtime for writing bots (i.e., peers without a human behind them) on top of a [Client].
+///
+/// Handlers are registered for the things the bot cares about, and then [Bot::run()] takes care of
+/// pulling messages off the client and calling the right handlers (each in its own task, so a slow
+/// handler doesn't hold up the rest of the bot).
+///
+/// ```ignore
+/// Bot::new(client)
+///     .command("echo", "repeats what you say", |ctx, _from, args| async move {
+///         ctx.reply(args).await;
+///     })
+///     .on_whisper(|ctx, from, msg| async move {
+///         ctx.reply(format!("{}, I'm just a bot!", from)).await;
+///     })
+///     .run()
+///     .await;
+/// ```
+///
+/// Bots answer `!help` with the list of their commands, unless they register a `help` command themselves.
+/// Messages that the bot itself sent are ignored.
+pub struct Bot {
+    client: Client,
+    say_handlers: Vec<MessageHandler>,
+    whisper_handlers: Vec<MessageHandler>,
+    commands: BTreeMap<String, (String, MessageHandler)>,
+    timers: Vec<(Duration, TimerHandler)>,
+}
+
+impl Bot {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            say_handlers: vec![],
+            whisper_handlers: vec![],
+            commands: BTreeMap::new(),
+            timers: vec![],
+        }
+    }
+
+    /// Call `handler` for every `Say` the bot receives (whatever channel it was said in).
+    pub fn on_say<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(BotContext, String, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.say_handlers.push(box_handler(handler));
+        self
+    }
+
+    /// Call `handler` for every `Whisper` sent to the bot.
+    pub fn on_whisper<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(BotContext, String, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.whisper_handlers.push(box_handler(handler));
+        self
+    }
+
+    /// Call `handler` for messages (said or whispered) that start with `!name`.
+    /// The handler gets everything after the command name, trimmed.
+    /// `description` shows up in the `!help` output.
+    pub fn command<F, Fut>(mut self, name: &str, description: &str, handler: F) -> Self
+    where
+        F: Fn(BotContext, String, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.commands.insert(
+            name.to_string(),
+            (description.to_string(), box_handler(handler)),
+        );
+        self
+    }
+
+    /// Call `handler` every `period`, starting one `period` after the bot starts running.
+    pub fn every<F, Fut>(mut self, period: Duration, handler: F) -> Self
+    where
+        F: Fn(BotContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.timers
+            .push((period, Arc::new(move |ctx| Box::pin(handler(ctx)))));
+        self
+    }
+
+    /// Runs the bot until its [Server](crate::Server) stops.
+    /// Must be called from within a tokio runtime, and the [Server](crate::Server) the client
+    /// belongs to should be started.
+    pub async fn run(self) {
+        for (period, handler) in self.timers.iter().cloned() {
+            let ctx = BotContext::new(self.client.clone(), self.client.name().to_string(), None, false);
+
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(period);
+                // the first tick of an interval completes right away
+                interval.tick().await;
+
+                loop {
+                    interval.tick().await;
+                    tokio::spawn(handler(ctx.clone()));
+                }
+            });
+        }
+
+        let bot = Arc::new(self);
+
+        while let Some(message) = bot.client.next_message().await {
+            trace!("Bot got {:?}", message);
+
+            let (from, channel, msg, whispered) = match message {
+                ClientServerMessage::Say((from, msg))
+                | ClientServerMessage::ScopedSay((from, _, msg)) => (from, None, msg, false),
+                ClientServerMessage::ChannelSay((from, channel, msg)) => (from, Some(channel), msg, false),
+                ClientServerMessage::Whisper((from, _to, msg))
+                | ClientServerMessage::ImportantWhisper((from, _to, msg)) => (from, None, msg, true),
+                ClientServerMessage::Deliver(_)
+                | ClientServerMessage::ImportantDeliver(_)
+                | ClientServerMessage::Throttled(_) => continue,
+            };
+
+            if from == bot.client.name() {
+                continue;
+            }
+
+            bot.dispatch(from, channel, msg, whispered).await;
+        }
+
+        debug!("{}'s server stopped; the bot is done", bot.client.name());
+    }
+
+    async fn dispatch(&self, from: String, channel: Option<String>, msg: String, whispered: bool) {
+        let ctx = BotContext::new(self.client.clone(), from.clone(), channel, whispered);
+
+        if let Some((name, args)) = parse_command(&msg) {
+            match self.commands.get(name) {
+                Some((_, handler)) => {
+                    debug!("{} ran command {}", from, name);
+                    tokio::spawn(handler(ctx.clone(), from.clone(), args.to_string()));
+                }
+                None if name == "help" => ctx.reply(self.help()).await,
+                None => {}
+            }
+        }
+
+        let handlers = if whispered {
+            &self.whisper_handlers
+        } else {
+            &self.say_handlers
+        };
+
+        for handler in handlers {
+            tokio::spawn(handler(ctx.clone(), from.clone(), msg.clone()));
+        }
+    }
+
+    /// The built in answer to `!help`.
+    fn help(&self) -> String {
+        let mut help = format!("{} knows:", self.client.name());
+
+        for (name, (description, _)) in &self.commands {
+            help.push_str(&format!(" {}{} ({});", COMMAND_PREFIX, name, description));
+        }
+        help.push_str(&format!(" {}help (this message)", COMMAND_PREFIX));
+
+        help
+    }
+}
+
+fn box_handler<F, Fut>(handler: F) -> MessageHandler
+where
+    F: Fn(BotContext, String, String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    Box::new(move |ctx, from, msg| Box::pin(handler(ctx, from, msg)))
+}
+
+/// Splits `!name args` into `("name", "args")`.
+fn parse_command(msg: &str) -> Option<(&str, &str)> {
+    let rest = msg.trim().strip_prefix(COMMAND_PREFIX)?;
+
+    match rest.split_once(char::is_whitespace) {
+        Some((name, args)) => Some((name, args.trim())),
+        None => Some((rest, "")),
+    }
+}
+
+/// What a [Bot] handler gets to work with: a way to talk back to the network.
+#[derive(Debug, Clone)]
+pub struct BotContext {
+    client: Client,
+    from: String,
+    channel: Option<String>,
+    whispered: bool,
+}
+
+impl BotContext {
+    fn new(client: Client, from: String, channel: Option<String>, whispered: bool) -> Self {
+        Self {
+            client,
+            from,
+            channel,
+            whispered,
+        }
+    }
+
+    /// The [Client] the bot is running on, for anything this context doesn't cover.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Say something to everyone.
+    pub async fn say(&self, msg: impl Into<String>) {
+        self.client.say(msg.into()).await
+    }
+
+    /// Whisper something to `to`.
+    pub async fn whisper(&self, to: impl Into<String>, msg: impl Into<String>) {
+        self.client.whisper(to.into(), msg.into()).await
+    }
+
+    /// Answer the message being handled the same way it came in: whispers get whispered back
+    /// to whoever sent them, messages said in a channel are answered in that channel, and
+    /// everything else is said to everyone.
+    /// For timers, this is the same as [BotContext::say()].
+    pub async fn reply(&self, msg: impl Into<String>) {
+        if self.whispered {
+            self.whisper(self.from.clone(), msg).await
+        } else if let Some(channel) = &self.channel {
+            self.client.say_in_channel(channel.clone(), msg.into()).await
+        } else {
+            self.say(msg).await
+        }
+    }
+}
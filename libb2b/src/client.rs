@@ -1,9 +1,12 @@
-use std::sync::Arc;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-use tracing::{instrument, trace};
+use futures::Stream;
+use tracing::{debug, instrument, trace};
 
-use crate::ClientServerMessage;
-use crate::{ClientRxChannel, ServerTxChannel};
+use crate::{peer::PeerData, util::TtlMap, ClientEvent, ClientServerMessage, History, Topology};
+use crate::{Bing2BingError, IgnoreList, Route, RoutingError, RoutingTable, Server};
+use crate::{ClientRxChannel, ClientTxChannel, EventRxChannel, EventTxChannel, ServerTxChannel};
 
 /// A `Client` is the way that a user (i.e., a user of our crate) interacts with a [Server](crate::Server),
 /// and thus the rest of the network..
@@ -13,11 +16,78 @@ pub struct Client {
 }
 
 impl Client {
-    #[instrument(level = "trace")]
-    pub fn new(name: String, server_tx: ServerTxChannel, rx: ClientRxChannel) -> Client {
-        Self {
-            shared: Arc::new(Shared::new(name, server_tx, rx)),
+    /// Creates the `Client` for `server`, which sends to it over `server_tx` and hears back
+    /// from it over `rx` (the other ends of the channels `server` was created with).
+    /// [init()](crate::init()) does all of this for you.
+    #[instrument(level = "trace", skip(server))]
+    pub fn new(name: String, server_tx: ServerTxChannel, rx: EventRxChannel, server: &Server) -> Client {
+        let (messages_tx, messages_rx) = async_channel::unbounded();
+
+        let client = Self {
+            shared: Arc::new(Shared::new(
+                name,
+                server_tx,
+                messages_rx,
+                server.adjacency_list(),
+                server.routing_table(),
+                server.ignore_list(),
+            )),
+        };
+
+        let pump = client.clone();
+        tokio::spawn(async move { pump.pump(rx, messages_tx).await });
+
+        client
+    }
+
+    /// Passes every event from the server on to everyone listening to [Client::events()].
+    /// Messages also go to [Client::next_message()] and to every subscriber (see [Client::subscribe()]),
+    /// and are recorded in the history. Messages (and deliveries) from peers we
+    /// [ignore](Client::ignore()) don't go anywhere.
+    ///
+    /// Once the server stops, every one of those channels is closed, so that readers find out.
+    async fn pump(&self, rx: EventRxChannel, messages_tx: ClientTxChannel) {
+        while let Ok(event) = rx.recv().await {
+            trace!("Received a ClientEvent: {:?}", event);
+
+            if let ClientEvent::Delivered { from, .. } = &event {
+                if self.shared.ignore_list.contains(from) {
+                    trace!("Ignoring a delivery from {}", from);
+                    continue;
+                }
+            }
+
+            if let ClientEvent::Message(msg) = &event {
+                if msg.sender().is_some_and(|sender| self.shared.ignore_list.contains(sender)) {
+                    trace!("Ignoring {:?}", msg);
+                    continue;
+                }
+
+                self.record_history(msg);
+
+                // subscribers that have gone away are dropped
+                if let Some(subscribers) = self.shared.subscribers.lock().unwrap().as_mut() {
+                    subscribers.retain(|subscriber| subscriber.try_send(msg.clone()).is_ok());
+                }
+
+                // the channel is unbounded (and we hold on to the receiver), so this can't fail
+                let _ = messages_tx.try_send(msg.clone());
+            }
+
+            if let Some(subscribers) = self.shared.event_subscribers.lock().unwrap().as_mut() {
+                subscribers.retain(|subscriber| subscriber.try_send(event.clone()).is_ok());
+            }
+
+            if event == ClientEvent::ServerStopped {
+                break;
+            }
         }
+
+        debug!("Server stopped; closing client channels");
+
+        // dropping the senders (including `messages_tx`) is what closes the channels
+        *self.shared.subscribers.lock().unwrap() = None;
+        *self.shared.event_subscribers.lock().unwrap() = None;
     }
 
     /// A method for use by users of `Client` to say a message that will be
@@ -25,31 +95,179 @@ impl Client {
     #[instrument(level = "trace")]
     pub async fn say(&self, msg: String) {
         let message = ClientServerMessage::Say((self.shared.name.clone(), msg));
+        self.record_history(&message);
 
         // pass the message on to the server
         self.shared.server_tx.send(message).await.unwrap();
     }
 
+    /// Like [Client::say()], but the message only reaches peers within `hops` hops of us
+    /// (e.g., 1 for just our neighbors).
+    #[instrument(level = "trace")]
+    pub async fn say_within(&self, hops: u64, msg: String) {
+        let message = ClientServerMessage::ScopedSay((self.shared.name.clone(), hops, msg));
+        self.record_history(&message);
+
+        self.shared.server_tx.send(message).await.unwrap();
+    }
+
+    /// Like [Client::say()], but tags the message with `channel`, so that only those following
+    /// the channel need to pay attention to it.
+    #[instrument(level = "trace")]
+    pub async fn say_in_channel(&self, channel: String, msg: String) {
+        let message = ClientServerMessage::ChannelSay((self.shared.name.clone(), channel, msg));
+        self.record_history(&message);
+
+        self.shared.server_tx.send(message).await.unwrap();
+    }
+
     #[instrument(level = "trace")]
     pub async fn whisper(&self, to: String, msg: String) {
         let message = ClientServerMessage::Whisper((self.shared.name.clone(), to, msg));
+        self.record_history(&message);
         // pass the message on to the server
         self.shared.server_tx.send(message).await.unwrap();
     }
 
+    /// Like [Client::whisper()], but a copy is sent along each of several paths to `to` that
+    /// don't go through the same peers (see [RoutingTable::disjoint_routes()](crate::RoutingTable::disjoint_routes())),
+    /// so it gets there even if a peer on the way goes down. `to` only sees it once.
+    #[instrument(level = "trace")]
+    pub async fn whisper_important(&self, to: String, msg: String) {
+        let message = ClientServerMessage::ImportantWhisper((self.shared.name.clone(), to, msg));
+        self.record_history(&message);
+        self.shared.server_tx.send(message).await.unwrap();
+    }
+
+    /// Sends `data` to `to` in a [Deliver](crate::cmd::Deliver), along the same route a
+    /// [Client::whisper()] would take. It shows up as a [ClientEvent::Delivered] on their end.
+    #[instrument(level = "trace", skip(data))]
+    pub async fn deliver(&self, to: String, data: Vec<u8>) {
+        let message = ClientServerMessage::Deliver((self.shared.name.clone(), to, data));
+        self.shared.server_tx.send(message).await.unwrap();
+    }
+
+    /// Like [Client::deliver()], but sent over several paths at once, the way
+    /// [Client::whisper_important()] is.
+    #[instrument(level = "trace", skip(data))]
+    pub async fn deliver_important(&self, to: String, data: Vec<u8>) {
+        let message = ClientServerMessage::ImportantDeliver((self.shared.name.clone(), to, data));
+        self.shared.server_tx.send(message).await.unwrap();
+    }
+
+    /// The name of the peer this `Client` belongs to.
+    pub fn name(&self) -> &str {
+        &self.shared.name
+    }
+
+    /// Returns a snapshot of the network topology as our [Server](crate::Server) currently sees it.
+    pub fn topology(&self) -> Topology {
+        Topology::from_adjacency_list(&self.shared.adjacency_list)
+    }
+
+    /// How our [Server](crate::Server) would route a whisper to `destination` right now.
+    pub fn route(&self, destination: &str) -> Result<Route, RoutingError> {
+        self.shared.routing_table.route(destination)
+    }
+
+    /// Every route our [Server](crate::Server) knows, sorted by destination.
+    pub fn routes(&self) -> Vec<Route> {
+        self.shared.routing_table.routes()
+    }
+
+    /// Start recording every `Say` and `Whisper` this client sends or receives in `history`.
+    pub fn set_history(&self, history: History) {
+        *self.shared.history.lock().unwrap() = Some(history);
+    }
+
+    /// The [History] messages are being recorded in, if any.
+    pub fn history(&self) -> Option<History> {
+        self.shared.history.lock().unwrap().clone()
+    }
+
+    /// Stop showing us anything `peer` says or whispers. Our [Server](crate::Server) still passes
+    /// their messages on to other peers, unless we are strict about it (see [Client::set_strict_ignore()]).
+    /// Returns whether `peer` wasn't already ignored.
+    pub fn ignore(&self, peer: &str) -> Result<bool, Bing2BingError> {
+        if peer == self.shared.name {
+            return Err("we can't ignore ourselves".into());
+        }
+
+        self.shared.ignore_list.add(peer)
+    }
+
+    /// Undoes [Client::ignore()]. Returns whether `peer` was ignored.
+    pub fn unignore(&self, peer: &str) -> Result<bool, Bing2BingError> {
+        self.shared.ignore_list.remove(peer)
+    }
+
+    /// Every peer we are ignoring, sorted.
+    pub fn ignored(&self) -> Vec<String> {
+        self.shared.ignore_list.peers()
+    }
+
+    /// Ignore the peers saved in the file at `path` (if it exists), and save the ignore list
+    /// there whenever it changes.
+    pub fn load_ignore_list(&self, path: impl AsRef<Path>) -> Result<(), Bing2BingError> {
+        self.shared.ignore_list.load(path)
+    }
+
+    /// If `strict`, our [Server](crate::Server) also drops every frame from peers we ignore,
+    /// instead of passing them on. This keeps them from using us to reach anyone, but also
+    /// keeps other peers from routing through them via us.
+    pub fn set_strict_ignore(&self, strict: bool) {
+        self.shared.ignore_list.set_strict(strict);
+    }
+
+    fn record_history(&self, message: &ClientServerMessage) {
+        if let Some(history) = self.shared.history.lock().unwrap().as_ref() {
+            history.record_message(message);
+        }
+    }
+
+    /// Get a copy of every message that comes from the server from now on.
+    /// This doesn't affect [Client::next_message()], so e.g., a UI and a script can both see
+    /// every message.
+    /// The channel is closed once the server stops.
+    pub fn subscribe(&self) -> ClientRxChannel {
+        let (tx, rx) = async_channel::unbounded();
+
+        // if the server has already stopped, `tx` is dropped right away and `rx` starts out closed
+        if let Some(subscribers) = self.shared.subscribers.lock().unwrap().as_mut() {
+            subscribers.push(tx);
+        }
+
+        rx
+    }
+
+    /// A stream of every [ClientEvent] from now on: messages, peers coming and going, changes to
+    /// the topology, messages that couldn't be delivered, etc.
+    /// The stream ends (after a [ClientEvent::ServerStopped]) when the server stops.
+    ///
+    /// Every call gets its own stream, so e.g., a UI and a script can both see every event.
+    pub fn events(&self) -> impl Stream<Item = ClientEvent> + Send + Unpin + 'static {
+        let (tx, rx) = async_channel::unbounded();
+
+        if let Some(subscribers) = self.shared.event_subscribers.lock().unwrap().as_mut() {
+            subscribers.push(tx);
+        }
+
+        rx
+    }
+
     /// Get the next message that came from the server.
     /// I.e., an already processed message that the user of
     /// the client might be interested in looking at.
     /// E.g., a message that came from another user that should be displayed
     /// in a UI.
+    ///
+    /// Every message waits here until it is asked for, so nothing is lost, however far behind
+    /// the caller is.
+    ///
+    /// Returns `None` once the server has stopped and every message it sent has been handed out.
     #[instrument(level = "trace")]
-    pub async fn next_message(&self) -> ClientServerMessage {
-        loop {
-            if let Ok(msg) = self.shared.rx.recv().await {
-                trace!("Received a ClientServerMessage: {:?}", msg);
-                return msg;
-            }
-        }
+    pub async fn next_message(&self) -> Option<ClientServerMessage> {
+        self.shared.messages.recv().await.ok()
     }
 }
 
@@ -57,16 +275,38 @@ impl Client {
 struct Shared {
     name: String,
     server_tx: ServerTxChannel,
-    rx: ClientRxChannel,
+    /// Messages from the server, waiting for [Client::next_message()].
+    messages: ClientRxChannel,
+    /// `None` once the server has stopped.
+    subscribers: Mutex<Option<Vec<ClientTxChannel>>>,
+    /// `None` once the server has stopped.
+    event_subscribers: Mutex<Option<Vec<EventTxChannel>>>,
+    adjacency_list: TtlMap<PeerData>,
+    routing_table: RoutingTable,
+    history: Mutex<Option<History>>,
+    ignore_list: IgnoreList,
 }
 
 impl Shared {
     #[instrument(level = "trace")]
-    pub fn new(name: String, server_tx: ServerTxChannel, rx: ClientRxChannel) -> Self {
+    pub fn new(
+        name: String,
+        server_tx: ServerTxChannel,
+        messages: ClientRxChannel,
+        adjacency_list: TtlMap<PeerData>,
+        routing_table: RoutingTable,
+        ignore_list: IgnoreList,
+    ) -> Self {
         Self {
             name,
             server_tx,
-            rx,
+            messages,
+            subscribers: Mutex::new(Some(vec![])),
+            event_subscribers: Mutex::new(Some(vec![])),
+            adjacency_list,
+            routing_table,
+            history: Mutex::new(None),
+            ignore_list,
         }
     }
 }
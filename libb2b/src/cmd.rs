@@ -1,6 +1,4 @@
-use std::time::Duration;
-
-use crate::{util::TtlMap, Bing2BingError, Bing2BingFrame, Parse};
+use crate::{Bing2BingError, Bing2BingFrame, Parse, SequenceWindows};
 
 mod ping;
 
@@ -8,6 +6,7 @@ pub use ping::Ping;
 
 mod say;
 pub use say::Say;
+pub(crate) use say::RECENT_SAYS_WINDOW;
 
 mod register;
 pub use register::Register;
@@ -27,6 +26,13 @@ pub use deliver::Deliver;
 mod extension;
 pub use extension::Extension;
 
+mod digest;
+pub use digest::Digest;
+
+mod route_vector;
+pub(crate) use route_vector::VECTOR_TIMEOUT;
+pub use route_vector::RouteVector;
+
 #[derive(Debug)]
 pub enum Bing2BingCommand {
     Broadcast(Broadcast),
@@ -37,6 +43,8 @@ pub enum Bing2BingCommand {
     Announce(Announce),
     Whisper(Whisper),
     Extension(Extension),
+    RouteVector(RouteVector),
+    Digest(Digest),
     Unknown,
 }
 
@@ -70,6 +78,8 @@ impl Bing2BingCommand {
             }
             "whisper" => Bing2BingCommand::Whisper(Whisper::parse_frames(&mut parse)?),
             "extension" => Bing2BingCommand::Extension(Extension::parse_frames(&mut parse)?),
+            "route_vector" => Bing2BingCommand::RouteVector(RouteVector::parse_frames(&mut parse)?),
+            "digest" => Bing2BingCommand::Digest(Digest::parse_frames(&mut parse)?),
             _ => return Ok(Bing2BingCommand::Unknown),
         };
 
@@ -91,9 +101,26 @@ impl Bing2BingCommand {
         }
     }
 
+    /// The peer this command came from in the first place, if it says.
+    pub(crate) fn source(&self) -> Option<&str> {
+        match self {
+            Bing2BingCommand::Announce(announce) => Some(&announce.source),
+            Bing2BingCommand::Broadcast(broadcast) => Some(&broadcast.source),
+            Bing2BingCommand::Deliver(deliver) => Some(&deliver.source),
+            Bing2BingCommand::Register(register) => Some(&register.peer_name),
+            Bing2BingCommand::Ping(ping) => Some(&ping.source),
+            Bing2BingCommand::Say(say) => Some(&say.source),
+            Bing2BingCommand::Whisper(whisper) => Some(&whisper.source),
+            Bing2BingCommand::Extension(extension) => Some(&extension.source),
+            Bing2BingCommand::RouteVector(vector) => Some(&vector.source),
+            Bing2BingCommand::Digest(digest) => Some(&digest.source),
+            Bing2BingCommand::Unknown => None,
+        }
+    }
+
     /// Checks to make sure that this `Bing2BingCommand` hasn't already been processed.
     /// This helps us ensure that we don't start an infinite loop.
-    pub(crate) fn check_duplicate(&self, processed_commands: &TtlMap<bool>) -> bool {
+    pub(crate) fn check_duplicate(&self, processed_commands: &SequenceWindows) -> bool {
         let (source, sequence_number) = match self {
             Bing2BingCommand::Announce(announce) => (&announce.source, announce.sequence_number),
             Bing2BingCommand::Broadcast(broadcast) => {
@@ -101,21 +128,21 @@ impl Bing2BingCommand {
             }
             Bing2BingCommand::Deliver(deliver) => (&deliver.source, deliver.sequence_number),
             Bing2BingCommand::Register(register) => (&register.peer_name, register.sequence_number),
-            Bing2BingCommand::Unknown => return false,
+            // digests only go to neighbors, and replies reuse the sequence number they answer
+            Bing2BingCommand::Unknown | Bing2BingCommand::Digest(_) => return false,
             Bing2BingCommand::Ping(ping) => (&ping.source, ping.sequence_number),
             Bing2BingCommand::Say(say) => (&say.source, say.sequence_number),
             Bing2BingCommand::Whisper(whisper) => (&whisper.source, whisper.sequence_number),
             Bing2BingCommand::Extension(extension) => {
                 (&extension.source, extension.sequence_number)
             }
+            Bing2BingCommand::RouteVector(vector) => (&vector.source, vector.sequence_number),
         };
 
-        processed_commands
-            .get(&format!("{}-{}", source, sequence_number))
-            .is_some()
+        processed_commands.contains(source, sequence_number)
     }
 
-    pub(crate) fn set_processed(&self, processed_commands: &TtlMap<bool>) {
+    pub(crate) fn set_processed(&self, processed_commands: &SequenceWindows) {
         let (source, sequence_number) = match self {
             Bing2BingCommand::Announce(announce) => (&announce.source, announce.sequence_number),
             Bing2BingCommand::Broadcast(broadcast) => {
@@ -123,19 +150,16 @@ impl Bing2BingCommand {
             }
             Bing2BingCommand::Deliver(deliver) => (&deliver.source, deliver.sequence_number),
             Bing2BingCommand::Register(register) => (&register.peer_name, register.sequence_number),
-            Bing2BingCommand::Unknown => return,
+            Bing2BingCommand::Unknown | Bing2BingCommand::Digest(_) => return,
             Bing2BingCommand::Ping(ping) => (&ping.source, ping.sequence_number),
             Bing2BingCommand::Say(say) => (&say.source, say.sequence_number),
             Bing2BingCommand::Whisper(whisper) => (&whisper.source, whisper.sequence_number),
             Bing2BingCommand::Extension(extension) => {
                 (&extension.source, extension.sequence_number)
             }
+            Bing2BingCommand::RouteVector(vector) => (&vector.source, vector.sequence_number),
         };
 
-        processed_commands.set(
-            format!("{}-{}", source, sequence_number),
-            true,
-            Some(Duration::from_secs(30)),
-        );
+        processed_commands.insert(source, sequence_number);
     }
 }
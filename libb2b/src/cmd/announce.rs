@@ -1,11 +1,20 @@
 use crate::{
-    peer::PeerData, peer_map::PeerMap, util::TtlMap, Bing2BingError, Bing2BingFrame, Parse, Server,
+    peer::{Location, PeerData, STALE_AFTER},
+    peer_map::PeerMap,
+    util::{parse_socket_addr, TtlMap},
+    Bing2BingError, Bing2BingFrame, Parse, Server,
 };
 
 use rand::Rng;
 
+use tracing::debug;
+
 use std::time::Duration;
 
+/// How long we remember the newest sequence number we have seen in an `Announce` from a source.
+/// This needs to be a lot longer than anything could be held up in the network for.
+const LATEST_ANNOUNCE_MEMORY: Duration = Duration::from_secs(60 * 60);
+
 /// The `Announce` command is propagated through the network to provide peers knowledge about the network topography.
 /// I.e., this is how peers let each other know who they are connected to.
 #[derive(Debug)]
@@ -19,9 +28,12 @@ pub struct Announce {
     lat: f64,
     lng: f64,
     peers: Vec<(String, u32)>,
+    /// How many more hops this `Announce` may travel (optional on the wire; see [PeerMap::take_hop()]).
+    hop_limit: Option<u64>,
 }
 
 impl Announce {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         source: String,
         sequence_number: u64,
@@ -43,9 +55,16 @@ impl Announce {
             lat,
             lng,
             peers,
+            hop_limit: None,
         }
     }
 
+    /// Only lets this `Announce` travel `hops` hops away from here.
+    pub fn with_hop_limit(mut self, hops: u64) -> Self {
+        self.hop_limit = Some(hops);
+        self
+    }
+
     pub(crate) fn parse_frames(
         source: String,
         sequence_number: u64,
@@ -61,6 +80,8 @@ impl Announce {
 
         let peers = Announce::parse_peer_info_frames(parse)?;
 
+        let hop_limit = parse.next_optional_number()?;
+
         parse.finish()?;
 
         Ok(Self {
@@ -73,6 +94,7 @@ impl Announce {
             lat,
             lng,
             peers,
+            hop_limit,
         })
     }
 
@@ -102,10 +124,15 @@ impl Announce {
         Ok(ret)
     }
 
+    /// Records the source's neighborhood in `adjacency_list`, forwards the `Announce` on,
+    /// and possibly makes an opportunistic connection to the source.
+    /// `name` is the name of the peer applying the command (i.e., us).
     pub(crate) async fn apply(
         &self,
+        name: &str,
         peer_map: &PeerMap,
         adjacency_list: &TtlMap<PeerData>,
+        latest_announces: &TtlMap<u64>,
     ) -> Result<(), Bing2BingError> {
         // let mut peer_map = peer_map.clone();
 
@@ -118,30 +145,68 @@ impl Announce {
         let lat = self.lat;
         let lng = self.lng;
         let peers = self.peers.clone();
+        let mut hop_limit = self.hop_limit;
+
+        // an announce that was held up somewhere can show up after a newer one from the same
+        // source (and after it stopped counting as a duplicate). What it says is out of date, so
+        // we neither record it nor pass it on.
+        // The newest sequence number is remembered for much longer than the source's entry in
+        // the adjacency list, so that a late announce can't bring back a peer that has gone.
+        // (our own entry counts too, so our own announces making their way back are dropped)
+        // A source that restarted is in a new session, so its sequence numbers are higher than
+        // anything from before (see SESSION_COUNTER_BITS).
+        let latest = latest_announces
+            .get(&source)
+            .into_iter()
+            .chain(adjacency_list.get(&source).map(|known| known.sequence_number()))
+            .max();
+
+        if let Some(latest) = latest.filter(|&latest| sequence_number <= latest) {
+            debug!(
+                "Dropping announce {} from {}; we already have announce {}",
+                sequence_number, source, latest
+            );
+            return Ok(());
+        }
+
+        latest_announces.set(source.clone(), sequence_number, Some(LATEST_ANNOUNCE_MEMORY));
+
+        let was_stale = adjacency_list
+            .age(&source)
+            .is_some_and(|age| age >= STALE_AFTER);
 
         // add the source's neighbors to our local knowledge
-        adjacency_list.set(
+        // (if they changed, the routing table will pick up on it)
+        let changed = adjacency_list.set_if_changed(
             self.source.clone(),
-            PeerData::new(&city, lat, lng, self.peers.clone()),
+            PeerData::new(&city, lat, lng, self.peers.clone(), sequence_number),
             Some(Duration::from_secs(30)),
         );
 
-        // now broadcast the message on to our neigbhors.
-
-        let frame = Self {
-            source,
-            sequence_number,
-            ip_address,
-            port,
-            available_incoming,
-            city,
-            lat,
-            lng,
-            peers,
+        // a stale peer coming back changes the routes too, even if its neighbors didn't change
+        if was_stale && !changed {
+            adjacency_list.notify_changed(self.source.clone());
         }
-        .into_frame();
 
-        peer_map.broadcast(self.source.clone(), frame);
+        // now broadcast the message on to our neigbhors (if it may go any further).
+
+        if peer_map.take_hop(&mut hop_limit) {
+            let frame = Self {
+                source,
+                sequence_number,
+                ip_address,
+                port,
+                available_incoming,
+                city,
+                lat,
+                lng,
+                peers,
+                hop_limit,
+            }
+            .into_frame();
+
+            peer_map.broadcast(self.source.clone(), frame);
+        }
 
 		if available_incoming < 1 {
 			return Ok(());
@@ -154,16 +219,18 @@ impl Announce {
         // there will always be a route to every peer in the network
 
         // we don't want to connect if we already have an outgoing connection to this
-        // peer tho!
-        if peer_map.contains_peer(self.source.clone()) {
+        // peer tho! (or if this is our own announce making its way back to us)
+        if self.source == name || peer_map.contains_peer(self.source.clone()) {
             return Ok(());
         }
 
         // we should also flip an `available_incoming` sided coin and
         // connect to this Peer if we get a hit.
+        // If there is another peer we could connect to that is just as well connected, but
+        // closer to us, we leave the connection to that one (when its coin comes up).
         let mut rng = rand::thread_rng();
 		let roll = rng.gen_range(0..available_incoming);
-		if roll == 0 {
+		if roll == 0 && !self.loses_tie_on_distance(name, peer_map, adjacency_list) {
 			// EXTRA CREDIT
 			// this should be refactored into somewhere else
 			// not sure where :3
@@ -176,13 +243,54 @@ impl Announce {
 			// there is a better way to deal many of them I suspect
 			// especially when it comes to strings vs &str
 			let source = self.source.clone();
-			let ip_address = self.ip_address.clone();
 
-			Server::connect_to_peer(&peer_map, source, ip_address, port.to_string());
+			// the ip address and port come across separately, so this works for
+			// both IPv4 and IPv6 addresses.
+			match parse_socket_addr(&self.ip_address, &port.to_string()) {
+				Ok(addr) => Server::connect_to_peer(peer_map, source, addr),
+				Err(err) => debug!("Not connecting to {}; bad address in announce: {}", source, err),
+			}
 		}
         Ok(())
     }
 
+    /// Is there another peer we could make an opportunistic connection to, that has as many
+    /// incoming connections as the source of this `Announce`, but is closer to us?
+    /// Distance only breaks ties: a peer with fewer connections never loses to a nearer one.
+    /// Connections are counted from the adjacency list, and if we don't know where we are,
+    /// nobody is closer than anyone else.
+    fn loses_tie_on_distance(
+        &self,
+        name: &str,
+        peer_map: &PeerMap,
+        adjacency_list: &TtlMap<PeerData>,
+    ) -> bool {
+        let here = match adjacency_list.get(name) {
+            Some(peer_data) => peer_data.location().clone(),
+            None => return false,
+        };
+
+        let known = adjacency_list.snapshot();
+        let incoming = |peer: &str| {
+            known
+                .iter()
+                .filter(|(_, peer_data, _)| peer_data.get_peers().iter().any(|(neighbor, _)| neighbor == peer))
+                .count()
+        };
+
+        let source_incoming = incoming(&self.source);
+        let source_distance = here.distance_km(&Location::new(&self.city, self.lat, self.lng));
+
+        known.iter().any(|(peer, peer_data, age)| {
+            peer != name
+                && *peer != self.source
+                && *age < STALE_AFTER
+                && !peer_map.contains_peer(peer.clone())
+                && incoming(peer) == source_incoming
+                && here.distance_km(peer_data.location()) < source_distance
+        })
+    }
+
     /// Turns this `Announce` into a [Bing2BingFrame].
     pub fn into_frame(self) -> Bing2BingFrame {
         // note that using the vec! macro like this is more
@@ -209,6 +317,57 @@ impl Announce {
 
         cmd.push(Bing2BingFrame::Array(peers));
 
+        if let Some(hop_limit) = self.hop_limit {
+            cmd.push(Bing2BingFrame::Number(hop_limit));
+        }
+
         Bing2BingFrame::Array(cmd)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::sync::mpsc;
+
+    fn announce_from(source: &str, lat: f64) -> Announce {
+        Announce::new(source.to_string(), 1, "::1".to_string(), 7000, 1, String::new(), lat, 0.0, vec![])
+    }
+
+    #[tokio::test]
+    async fn distance_breaks_ties_between_equally_connected_peers() {
+        // we are at 0, our neighbor is at 5, and near and far are at 1 and 10
+        let adjacency_list = TtlMap::new();
+        adjacency_list.set("us".to_string(), PeerData::new("", 0.0, 0.0, vec![("neighbor".to_string(), 1)], 1), None);
+        adjacency_list.set("neighbor".to_string(), PeerData::new("", 5.0, 0.0, vec![], 1), None);
+        adjacency_list.set("near".to_string(), PeerData::new("", 1.0, 0.0, vec![], 1), None);
+        adjacency_list.set("far".to_string(), PeerData::new("", 10.0, 0.0, vec![], 1), None);
+
+        let mut peer_map = PeerMap::new();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        peer_map.insert("neighbor".to_string(), tx);
+
+        let near = announce_from("near", 1.0);
+        let far = announce_from("far", 10.0);
+
+        // nobody links to near or far
+        assert!(far.loses_tie_on_distance("us", &peer_map, &adjacency_list));
+        assert!(!near.loses_tie_on_distance("us", &peer_map, &adjacency_list));
+
+        // now near has more connections than far, so far wins no matter the distance
+        // (other has as few as far does, but it's further away still)
+        adjacency_list.set("other".to_string(), PeerData::new("", 20.0, 0.0, vec![("near".to_string(), 1)], 1), None);
+        assert!(!far.loses_tie_on_distance("us", &peer_map, &adjacency_list));
+        assert!(!near.loses_tie_on_distance("us", &peer_map, &adjacency_list));
+    }
+
+    #[tokio::test]
+    async fn no_ties_without_a_location() {
+        let adjacency_list = TtlMap::new();
+        adjacency_list.set("near".to_string(), PeerData::new("", 1.0, 0.0, vec![], 1), None);
+
+        let far = announce_from("far", 10.0);
+        assert!(!far.loses_tie_on_distance("us", &PeerMap::new(), &adjacency_list));
+    }
+}
@@ -3,33 +3,56 @@ use crate::{parse::Parse, peer_map::PeerMap, Bing2BingError, Bing2BingFrame};
 use bytes::Bytes;
 
 /// The `Broadcast` command delivers data (a [Bing2BingFrame::Bulk]) to all connected peers.
+/// With a hop limit, it only goes that many hops (e.g., a hop limit of 2 reaches our neighbors
+/// and theirs); see [PeerMap::take_hop()].
 #[derive(Debug, Clone)]
 pub struct Broadcast {
     pub(crate) source: String,
     pub(crate) sequence_number: u64,
     data: Bytes,
+    hop_limit: Option<u64>,
 }
 
 impl Broadcast {
+    pub fn new(source: String, sequence_number: u64, data: Bytes) -> Self {
+        Self {
+            source,
+            sequence_number,
+            data,
+            hop_limit: None,
+        }
+    }
+
+    /// Only lets this `Broadcast` travel `hops` hops away from here.
+    pub fn with_hop_limit(mut self, hops: u64) -> Self {
+        self.hop_limit = Some(hops);
+        self
+    }
+
     pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Self, Bing2BingError> {
         let source = parse.next_string()?;
 
         let sequence_number = parse.next_number()?;
 
         let data = parse.next_bytes()?;
+        let hop_limit = parse.next_optional_number()?;
         parse.finish()?;
 
         Ok(Self {
             source,
             sequence_number,
             data,
+            hop_limit,
         })
     }
 
-    /// Forwards this command out to all connected peers.
+    /// Forwards this command out to all connected peers (if it may go any further).
     pub(crate) async fn apply(&self, peer_map: &PeerMap) -> Result<(), Bing2BingError> {
-        let frame = self.clone().into_frame();
-        peer_map.broadcast(self.source.clone(), frame);
+        let mut broadcast = self.clone();
+
+        if peer_map.take_hop(&mut broadcast.hop_limit) {
+            peer_map.broadcast(self.source.clone(), broadcast.into_frame());
+        }
 
         Ok(())
     }
@@ -40,13 +63,17 @@ impl Broadcast {
         // performant than creating a new vector and then
         // pushing into it according to clippy:
         // https://rust-lang.github.io/rust-clippy/master/index.html#vec_init_then_push
-        let cmd = vec![
+        let mut cmd = vec![
             Bing2BingFrame::Text("broadcast".to_string()),
             Bing2BingFrame::Text(self.source),
             Bing2BingFrame::Number(self.sequence_number),
             Bing2BingFrame::Bulk(self.data.to_vec()),
         ];
 
+        if let Some(hop_limit) = self.hop_limit {
+            cmd.push(Bing2BingFrame::Number(hop_limit));
+        }
+
         // cmd.push(Bing2BingFrame::Text("broadcast".to_string()));
         // cmd.push(Bing2BingFrame::Text(self.source));
         // cmd.push(Bing2BingFrame::Number(self.sequence_number));
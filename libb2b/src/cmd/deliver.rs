@@ -1,21 +1,34 @@
-use crate::{parse::Parse, peer_map::PeerMap, Bing2BingError, Bing2BingFrame};
+use crate::{parse::Parse, peer_map::PeerMap, Bing2BingError, Bing2BingFrame, RoutingTable};
+
+use tracing::debug;
 
 use bytes::Bytes;
 
 /// `Deliver` data [Bing2BingFrame::Bulk] to a specific destination (peer).
-/// # Points available
-/// The current implementation of [`Deliver::apply()`] forwards to all connected peers.
-/// To receive additional points, you should make an attempt to determine the shortest path
-/// and forward _only_ to the next hop in that path.
+/// Like a [Whisper](crate::cmd::Whisper), it follows the path the sender put on it if there
+/// is one, and otherwise each peer along the way forwards it to the next hop on its own best
+/// route to the destination.
 #[derive(Debug, Clone)]
 pub struct Deliver {
     pub(crate) source: String,
     pub(crate) sequence_number: u64,
-    destination: String,
-    data: Bytes,
+    pub(crate) destination: String,
+    pub(crate) data: Bytes,
+    /// The rest of the way to the destination (optional on the wire).
+    path: Vec<String>,
 }
 
 impl Deliver {
+    pub fn new(source: String, sequence_number: u64, destination: &str, data: Bytes, path: Vec<String>) -> Self {
+        Self {
+            source,
+            sequence_number,
+            destination: destination.to_string(),
+            data,
+            path,
+        }
+    }
+
     pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Self, Bing2BingError> {
         let source = parse.next_string()?;
 
@@ -25,6 +38,20 @@ impl Deliver {
 
         let data = parse.next_bytes()?;
 
+        let mut path = vec![];
+        for peer_name in parse.next_optional_array()?.unwrap_or_default() {
+            match peer_name {
+                Bing2BingFrame::Text(peer_name) => path.push(peer_name),
+                frame => {
+                    return Err(format!(
+                        "protocol error; expected text frame when parsing deliver path, got {:?}",
+                        frame
+                    )
+                    .into())
+                }
+            }
+        }
+
         parse.finish()?;
 
         Ok(Self {
@@ -32,16 +59,44 @@ impl Deliver {
             sequence_number,
             destination,
             data,
+            path,
         })
     }
 
-    /// Forward the data on to the next peer in the path.
-    /// # Points available
-    /// Current implementation just broadcasts the command out to all connected peers.
-    pub(crate) async fn apply(&self, peer_map: &PeerMap) -> Result<(), Bing2BingError> {
+    /// Forward the data on to the next peer on the path, or if there isn't one, the next hop on
+    /// our `routing_table`'s route to the destination
+    /// (`name` is us; if the data is for us, there is nothing to pass on, and the
+    /// [Server](crate::Server) hands it to our [Client](crate::Client) instead).
+    /// If we don't have a route (or the next hop is gone), the command is broadcast out to all
+    /// connected peers instead, in the hope that one of them knows the way.
+    pub(crate) async fn apply(&self, name: &str, peer_map: &PeerMap, routing_table: &RoutingTable) -> Result<(), Bing2BingError> {
+        if self.destination == name {
+            return Ok(());
+        }
+
+        if !self.path.is_empty() {
+            // the next peer gets the rest of the path
+            let mut deliver = self.clone();
+            let next_step = deliver.path.remove(0);
+
+            if let Err(err) = peer_map.send_to_peer(self.source.clone(), next_step, deliver.into_frame()) {
+                debug!("Couldn't pass on deliver from {} to {}: {}", self.source, self.destination, err);
+            }
+
+            return Ok(());
+        }
+
         let frame = self.clone().into_frame();
 
-        peer_map.broadcast(self.source.clone(), frame);
+        let sent = routing_table
+            .next_hop(&self.destination)
+            .map_err(Bing2BingError::from)
+            .and_then(|next_hop| peer_map.send_to_peer(self.source.clone(), next_hop, frame.clone()));
+
+        if let Err(err) = sent {
+            debug!("No route for deliver to {} ({}); broadcasting it", self.destination, err);
+            peer_map.broadcast(self.source.clone(), frame);
+        }
 
         Ok(())
     }
@@ -52,7 +107,7 @@ impl Deliver {
         // performant than creating a new vector and then
         // pushing into it according to clippy:
         // https://rust-lang.github.io/rust-clippy/master/index.html#vec_init_then_push
-        let cmd = vec![
+        let mut cmd = vec![
             Bing2BingFrame::Text("deliver".to_string()),
             Bing2BingFrame::Text(self.source),
             Bing2BingFrame::Number(self.sequence_number),
@@ -60,6 +115,13 @@ impl Deliver {
             Bing2BingFrame::Bulk(self.data.to_vec()),
         ];
 
+        // peers that don't know about paths can still read delivers without one
+        if !self.path.is_empty() {
+            cmd.push(Bing2BingFrame::Array(
+                self.path.into_iter().map(Bing2BingFrame::Text).collect(),
+            ));
+        }
+
         // cmd.push(Bing2BingFrame::Text("deliver".to_string()));
         // cmd.push(Bing2BingFrame::Text(self.source));
         // cmd.push(Bing2BingFrame::Number(self.sequence_number));
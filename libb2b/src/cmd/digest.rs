@@ -0,0 +1,413 @@
+use crate::{cmd::Say, parse::Parse, peer_map::PeerMap, util::TtlMap, Bing2BingError, Bing2BingFrame};
+
+use std::collections::HashSet;
+
+use tracing::{debug, trace};
+
+/// The `Digest` command is how peers that [gossip](crate::Server::set_gossip_fanout()) catch up
+/// on `Say`s that the random forwarding didn't get to them (anti-entropy).
+///
+/// Every so often, a peer sends a neighbor the ids of the `Say`s it has seen recently.
+/// The neighbor sends back any `Say`s that aren't in the digest, and (if `reply` is set) a
+/// digest of its own, so that it gets sent the `Say`s it is missing in turn.
+/// Both need the neighbor to have a connection back to the sender; if it doesn't, the digest
+/// is ignored.
+///
+/// Digests only ever go to a direct neighbor, so they aren't checked for duplicates like
+/// other commands are (a reply reuses the sequence number of the digest it answers).
+#[derive(Debug, Clone)]
+pub struct Digest {
+    pub(crate) source: String,
+    pub(crate) sequence_number: u64,
+    /// Should the receiver answer with a digest of its own?
+    reply: bool,
+    /// The ids (see [Say::id()]) of the `Say`s the source has seen recently.
+    ids: Vec<String>,
+}
+
+impl Digest {
+    pub fn new(source: String, sequence_number: u64, reply: bool, ids: Vec<String>) -> Self {
+        Self {
+            source,
+            sequence_number,
+            reply,
+            ids,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Self, Bing2BingError> {
+        let source = parse.next_string()?;
+
+        let sequence_number = parse.next_number()?;
+
+        let reply = match parse.next()? {
+            Bing2BingFrame::Bool(reply) => reply,
+            frame => {
+                return Err(format!("protocol error; expected bool frame in digest, got {:?}", frame).into())
+            }
+        };
+
+        let mut ids = vec![];
+        for id in parse.next_array()? {
+            match id {
+                Bing2BingFrame::Text(id) => ids.push(id),
+                frame => {
+                    return Err(format!(
+                        "protocol error; expected text frame when parsing digest, got {:?}",
+                        frame
+                    )
+                    .into())
+                }
+            }
+        }
+
+        parse.finish()?;
+
+        Ok(Self::new(source, sequence_number, reply, ids))
+    }
+
+    /// Sends the source every `Say` in `recent_says` that it is missing, and asks for the ones
+    /// we are missing by replying with our own digest (if the source wants one).
+    /// `name` is the name of the peer applying the command (i.e., us).
+    pub(crate) async fn apply(
+        &self,
+        name: &str,
+        peer_map: &PeerMap,
+        recent_says: &TtlMap<Say>,
+    ) -> Result<(), Bing2BingError> {
+        trace!("Applying Digest command: {:?}", self);
+
+        if self.source == name || !peer_map.contains_peer(self.source.clone()) {
+            return Ok(());
+        }
+
+        let theirs = self.ids.iter().collect::<HashSet<_>>();
+        let ours = recent_says.snapshot();
+
+        let missing_there = ours
+            .iter()
+            .filter(|(id, _, _)| !theirs.contains(id))
+            .collect::<Vec<_>>();
+
+        if !missing_there.is_empty() {
+            debug!("Sending {} Says that {} missed", missing_there.len(), self.source);
+        }
+
+        for (_, say, _) in missing_there {
+            let frame = say.clone().into_frame();
+
+            // the connection went away in the meantime; nothing to be done about it
+            if let Err(err) = peer_map.send_to_peer(name.to_string(), self.source.clone(), frame) {
+                debug!("Couldn't answer digest from {}: {}", self.source, err);
+                return Ok(());
+            }
+        }
+
+        let missing_here = self.ids.iter().any(|id| recent_says.get(id).is_none());
+
+        if self.reply && missing_here {
+            let ids = ours.into_iter().map(|(id, _, _)| id).collect();
+            let digest = Digest::new(name.to_string(), self.sequence_number, false, ids);
+
+            if let Err(err) = peer_map.send_to_peer(name.to_string(), self.source.clone(), digest.into_frame()) {
+                debug!("Couldn't answer digest from {}: {}", self.source, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Turns this `Digest` into a [Bing2BingFrame].
+    pub fn into_frame(self) -> Bing2BingFrame {
+        let cmd = vec![
+            Bing2BingFrame::Text("digest".to_string()),
+            Bing2BingFrame::Text(self.source),
+            Bing2BingFrame::Number(self.sequence_number),
+            Bing2BingFrame::Bool(self.reply),
+            Bing2BingFrame::Array(self.ids.into_iter().map(Bing2BingFrame::Text).collect()),
+        ];
+
+        Bing2BingFrame::Array(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Bing2BingCommand, PeerControlMessage, SequenceWindows, Server};
+
+    use tokio::sync::mpsc;
+
+    fn say(source: &str, sequence_number: u64) -> Say {
+        Say::new(source.to_string(), sequence_number, "hi")
+    }
+
+    /// A `PeerMap` with a connection to `peer`, and what gets sent over it.
+    fn connected_to(peer: &str) -> (PeerMap, mpsc::UnboundedReceiver<PeerControlMessage>) {
+        let mut peer_map = PeerMap::new();
+        let (tx, rx) = mpsc::unbounded_channel();
+        peer_map.insert(peer.to_string(), tx);
+
+        (peer_map, rx)
+    }
+
+    fn sent(rx: &mut mpsc::UnboundedReceiver<PeerControlMessage>) -> Vec<Bing2BingCommand> {
+        let mut commands = vec![];
+
+        while let Ok(PeerControlMessage::Frame(frame)) = rx.try_recv() {
+            commands.push(Bing2BingCommand::from_frame(frame).unwrap());
+        }
+
+        commands
+    }
+
+    fn recent(says: &[Say]) -> TtlMap<Say> {
+        let recent_says = TtlMap::new();
+        for say in says {
+            recent_says.set(say.id(), say.clone(), None);
+        }
+
+        recent_says
+    }
+
+    #[tokio::test]
+    async fn sends_missing_says_and_asks_for_ours() {
+        let (peer_map, mut rx) = connected_to("them");
+        let recent_says = recent(&[say("a", 1), say("a", 2).with_hop_limit(3), say("c", 5)]);
+
+        let digest = Digest::new("them".to_string(), 7, true, vec!["a-1".to_string(), "x-9".to_string()]);
+        digest.apply("us", &peer_map, &recent_says).await.unwrap();
+
+        let mut resent = vec![];
+        let mut replies = vec![];
+        for command in sent(&mut rx) {
+            match command {
+                Bing2BingCommand::Say(say) => resent.push((say.id(), say.hop_limit)),
+                Bing2BingCommand::Digest(digest) => replies.push(digest),
+                command => panic!("unexpected {:?}", command),
+            }
+        }
+
+        resent.sort();
+        assert_eq!(resent, vec![("a-2".to_string(), Some(3)), ("c-5".to_string(), None)]);
+
+        // they have x-9 and we don't, so we ask with a digest of our own
+        assert_eq!(replies.len(), 1);
+        let reply = &replies[0];
+        assert_eq!(reply.source, "us");
+        assert_eq!(reply.sequence_number, 7);
+        assert!(!reply.reply);
+
+        let mut ids = reply.ids.clone();
+        ids.sort();
+        assert_eq!(ids, vec!["a-1", "a-2", "c-5"]);
+    }
+
+    #[tokio::test]
+    async fn only_replies_when_asked_and_missing_something() {
+        let (peer_map, mut rx) = connected_to("them");
+        let recent_says = recent(&[say("a", 1)]);
+
+        // they have everything we have, and we have everything they have
+        let digest = Digest::new("them".to_string(), 1, true, vec!["a-1".to_string()]);
+        digest.apply("us", &peer_map, &recent_says).await.unwrap();
+        assert!(sent(&mut rx).is_empty());
+
+        // we are missing x-9, but they didn't ask for a reply
+        let digest = Digest::new("them".to_string(), 2, false, vec!["a-1".to_string(), "x-9".to_string()]);
+        digest.apply("us", &peer_map, &recent_says).await.unwrap();
+        assert!(sent(&mut rx).is_empty());
+    }
+
+    #[tokio::test]
+    async fn ignores_digests_from_peers_we_cant_answer() {
+        let (peer_map, mut rx) = connected_to("them");
+        let recent_says = recent(&[say("a", 1)]);
+
+        let digest = Digest::new("someone else".to_string(), 1, true, vec![]);
+        digest.apply("us", &peer_map, &recent_says).await.unwrap();
+
+        assert!(sent(&mut rx).is_empty());
+    }
+
+    /// A peer in a simulated network.
+    struct Node {
+        name: String,
+        peer_map: PeerMap,
+        recent_says: TtlMap<Say>,
+        processed_commands: SequenceWindows,
+        /// Everything its neighbors send it.
+        inbox: mpsc::UnboundedReceiver<PeerControlMessage>,
+        /// The ids of the `Say`s from someone else that reached it.
+        heard: HashSet<String>,
+    }
+
+    /// Frames sent over the course of a simulation.
+    #[derive(Debug, Default)]
+    struct Traffic {
+        says: usize,
+        digests: usize,
+    }
+
+    /// How a simulation went.
+    #[derive(Debug)]
+    struct Outcome {
+        /// The share of peers each `Say` reached (not counting whoever said it), before any
+        /// digests went around and after.
+        delivered_before_digests: f64,
+        delivered: f64,
+        digest_rounds: usize,
+        traffic: Traffic,
+    }
+
+    /// `peers` peers in a circle, each linked to the `degree / 2` peers on either side of it,
+    /// and gossiping to `fanout` of them (or flooding, if `None`).
+    fn network(peers: usize, degree: usize, fanout: Option<usize>) -> Vec<Node> {
+        let (txs, rxs): (Vec<_>, Vec<_>) = (0..peers).map(|_| mpsc::unbounded_channel()).unzip();
+
+        rxs.into_iter()
+            .enumerate()
+            .map(|(i, inbox)| {
+                let mut peer_map = PeerMap::new();
+                peer_map.set_gossip_fanout(fanout);
+
+                for offset in 1..=degree / 2 {
+                    for j in [(i + offset) % peers, (i + peers - offset) % peers] {
+                        peer_map.insert(format!("p{}", j), txs[j].clone());
+                    }
+                }
+
+                Node {
+                    name: format!("p{}", i),
+                    peer_map,
+                    recent_says: TtlMap::new(),
+                    processed_commands: SequenceWindows::new(),
+                    inbox,
+                    heard: HashSet::new(),
+                }
+            })
+            .collect()
+    }
+
+    /// Hands every frame to whoever it was sent to (like `Server::handle_connection()` would),
+    /// until nobody has anything left to send.
+    async fn settle(nodes: &mut [Node], traffic: &mut Traffic) {
+        let mut quiet = false;
+
+        while !quiet {
+            quiet = true;
+
+            for node in nodes.iter_mut() {
+                while let Ok(PeerControlMessage::Frame(frame)) = node.inbox.try_recv() {
+                    quiet = false;
+
+                    let command = Bing2BingCommand::from_frame(frame).unwrap();
+                    match command {
+                        Bing2BingCommand::Say(_) => traffic.says += 1,
+                        Bing2BingCommand::Digest(_) => traffic.digests += 1,
+                        _ => {}
+                    }
+
+                    if command.check_duplicate(&node.processed_commands) {
+                        continue;
+                    }
+                    command.set_processed(&node.processed_commands);
+
+                    match command {
+                        Bing2BingCommand::Say(say) => {
+                            if say.source != node.name {
+                                node.heard.insert(say.id());
+                            }
+                            say.apply(&node.peer_map, &node.recent_says).await.unwrap();
+                        }
+                        Bing2BingCommand::Digest(digest) => {
+                            digest
+                                .apply(&node.name, &node.peer_map, &node.recent_says)
+                                .await
+                                .unwrap();
+                        }
+                        command => panic!("unexpected {:?}", command),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Has the peers of a [network()] take turns saying `says` things, then sends digests
+    /// around (as `start_digests()` does) until everyone has heard everything, or
+    /// `max_digest_rounds` have gone by.
+    async fn simulate(fanout: Option<usize>, says: usize, max_digest_rounds: usize) -> Outcome {
+        let mut nodes = network(12, 6, fanout);
+        let mut traffic = Traffic::default();
+
+        let delivered = |nodes: &[Node]| {
+            let heard = nodes.iter().map(|node| node.heard.len()).sum::<usize>();
+            heard as f64 / (says * (nodes.len() - 1)) as f64
+        };
+
+        for sequence_number in 0..says {
+            let node = &nodes[sequence_number % nodes.len()];
+            Server::say(
+                &node.peer_map,
+                &node.recent_says,
+                node.name.clone(),
+                None,
+                "hi".to_string(),
+                sequence_number as u64,
+                None,
+            )
+            .await;
+
+            settle(&mut nodes, &mut traffic).await;
+        }
+
+        let delivered_before_digests = delivered(&nodes);
+
+        let mut digest_rounds = 0;
+        while delivered(&nodes) < 1.0 && digest_rounds < max_digest_rounds {
+            digest_rounds += 1;
+
+            for node in &nodes {
+                let peer = node.peer_map.random_peer().unwrap();
+                let ids = node.recent_says.snapshot().into_iter().map(|(id, _, _)| id).collect();
+                let frame = Digest::new(node.name.clone(), digest_rounds as u64, true, ids).into_frame();
+                node.peer_map.send_to_peer(node.name.clone(), peer, frame).unwrap();
+            }
+
+            settle(&mut nodes, &mut traffic).await;
+        }
+
+        Outcome {
+            delivered_before_digests,
+            delivered: delivered(&nodes),
+            digest_rounds,
+            traffic,
+        }
+    }
+
+    #[tokio::test]
+    async fn gossip_with_digests_delivers_everything_for_less_than_flooding() {
+        let flooding = simulate(None, 20, 0).await;
+        let gossip = simulate(Some(2), 20, 20).await;
+
+        for (mode, outcome) in [("flooding", &flooding), ("gossip", &gossip)] {
+            println!(
+                "{:>8}: {:.0}% delivered ({:.0}% before {} rounds of digests), {} says and {} digests sent",
+                mode,
+                outcome.delivered * 100.0,
+                outcome.delivered_before_digests * 100.0,
+                outcome.digest_rounds,
+                outcome.traffic.says,
+                outcome.traffic.digests
+            );
+        }
+
+        assert_eq!(flooding.delivered_before_digests, 1.0);
+        assert_eq!(gossip.delivered, 1.0, "{:?}", gossip);
+
+        let total = |traffic: &Traffic| traffic.says + traffic.digests;
+        assert!(gossip.traffic.says < flooding.traffic.says, "{:?} vs {:?}", gossip, flooding);
+        assert!(total(&gossip.traffic) < total(&flooding.traffic), "{:?} vs {:?}", gossip, flooding);
+    }
+}
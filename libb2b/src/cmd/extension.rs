@@ -7,7 +7,7 @@ use tracing::{instrument, trace, warn};
 ///
 /// 1. An extension id, which uniquely represents the given extension.
 /// 2. A payload [Bing2BingFrame] that is used for whatever the extension is
-/// is supposed to do.
+///    is supposed to do.
 ///
 /// # Points available
 ///
@@ -18,6 +18,8 @@ pub struct Extension {
     pub(crate) sequence_number: u64,
     pub(crate) extension_id: u64,
     pub(crate) payload: Bing2BingFrame,
+    /// How many more hops this `Extension` may travel (optional on the wire; see [PeerMap::take_hop()]).
+    pub(crate) hop_limit: Option<u64>,
 }
 
 impl Extension {
@@ -32,9 +34,16 @@ impl Extension {
             sequence_number,
             extension_id,
             payload,
+            hop_limit: None,
         }
     }
 
+    /// Only lets this `Extension` travel `hops` hops away from here.
+    pub fn with_hop_limit(mut self, hops: u64) -> Self {
+        self.hop_limit = Some(hops);
+        self
+    }
+
     pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Self, Bing2BingError> {
         let source = parse.next_string()?;
 
@@ -43,9 +52,14 @@ impl Extension {
 
         let payload = parse.next()?;
 
+        let hop_limit = parse.next_optional_number()?;
+
         parse.finish()?;
 
-        Ok(Self::new(source, sequence_number, extension_id, payload))
+        let mut extension = Self::new(source, sequence_number, extension_id, payload);
+        extension.hop_limit = hop_limit;
+
+        Ok(extension)
     }
 
     /// Currently just broadcasts the message back out to everyone else
@@ -55,20 +69,24 @@ impl Extension {
     pub(crate) async fn apply(&self, peer_map: &PeerMap) -> Result<(), Bing2BingError> {
         trace!("Applying Extension command: {:?}", self);
 
-        let frame = self.clone().into_frame();
+        let mut extension = self.clone();
+
+        if !peer_map.take_hop(&mut extension.hop_limit) {
+            return Ok(());
+        }
 
         warn!(
             "Unimplemented command ({:?}); broadcasting for propagation",
             self
         );
-        peer_map.broadcast(self.source.clone(), frame);
+        peer_map.broadcast(self.source.clone(), extension.into_frame());
 
         Ok(())
     }
 
     /// Turns this `Extension` into a [Bing2BingFrame].
     pub fn into_frame(self) -> Bing2BingFrame {
-        let cmd = vec![
+        let mut cmd = vec![
             Bing2BingFrame::Text("extension".to_string()),
             Bing2BingFrame::Text(self.source),
             Bing2BingFrame::Number(self.sequence_number),
@@ -76,6 +94,10 @@ impl Extension {
             self.payload,
         ];
 
+        if let Some(hop_limit) = self.hop_limit {
+            cmd.push(Bing2BingFrame::Number(hop_limit));
+        }
+
         Bing2BingFrame::Array(cmd)
     }
 }
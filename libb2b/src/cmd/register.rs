@@ -1,4 +1,7 @@
-use crate::{util::TtlMap, Bing2BingError, Bing2BingFrame, Connection, Parse};
+use crate::{
+    util::{parse_socket_addr, TtlMap},
+    Bing2BingError, Bing2BingFrame, Connection, Parse,
+};
 
 use std::net::SocketAddr;
 
@@ -62,7 +65,7 @@ impl Register {
         dst: &mut Connection,
     ) -> Result<(), Bing2BingError> {
         trace!("Applying Register command");
-        let socket_addr = format!("{}:{}", self.ip_address, self.port).parse::<SocketAddr>()?;
+        let socket_addr = parse_socket_addr(&self.ip_address, &self.port)?;
 
         // see if we already know about this peer name.
         // if we _do_ know, then we then need to check to see if this
@@ -86,6 +89,7 @@ impl Register {
 
         // construct the random peer list and write it back over the connection
         // the peer list is an array frame that contains arrays that have the name, ip, and port of the peer
+        // the ip and port are kept as separate entries so that IPv6 addresses don't need any special handling.
         let random_peers = known_peers.random_keys_vals(5);
         let mut frame = vec![];
 
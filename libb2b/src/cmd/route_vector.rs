@@ -0,0 +1,125 @@
+use crate::{
+    parse::Parse,
+    peer_map::PeerMap,
+    util::{parse_socket_addr, TtlMap},
+    Bing2BingError, Bing2BingFrame, Server,
+};
+
+use tracing::{debug, trace};
+
+use std::time::Duration;
+
+/// How long we keep using a neighbor's vector after it stops sending them.
+/// Vectors are sent every 5 seconds, so this is three in a row that didn't make it to us.
+pub(crate) const VECTOR_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The `RouteVector` command is how peers in [distance-vector](crate::RoutingMode::DistanceVector)
+/// mode tell their direct neighbors which peers they can reach, and in how many hops.
+/// Unlike an [Announce](crate::cmd::Announce), it is never forwarded.
+#[derive(Debug, Clone)]
+pub struct RouteVector {
+    pub(crate) source: String,
+    pub(crate) sequence_number: u64,
+    ip_address: String,
+    port: u64,
+    /// `(destination, hops)` for every peer the source can reach.
+    routes: Vec<(String, u32)>,
+}
+
+impl RouteVector {
+    pub fn new(
+        source: String,
+        sequence_number: u64,
+        ip_address: String,
+        port: u64,
+        routes: Vec<(String, u32)>,
+    ) -> Self {
+        Self {
+            source,
+            sequence_number,
+            ip_address,
+            port,
+            routes,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Self, Bing2BingError> {
+        let source = parse.next_string()?;
+
+        let sequence_number = parse.next_number()?;
+
+        let ip_address = parse.next_text()?;
+        let port = parse.next_number()?;
+
+        // the routes come across the same way an Announce's peers do
+        let mut routes = vec![];
+        for route in parse.next_array()? {
+            match route {
+                Bing2BingFrame::Latency(destination, hops) => routes.push((destination, hops)),
+                frame => {
+                    return Err(format!(
+                        "protocol error; expected latency frame when parsing route vector, got {:?}",
+                        frame
+                    )
+                    .into())
+                }
+            }
+        }
+
+        parse.finish()?;
+
+        Ok(Self::new(source, sequence_number, ip_address, port, routes))
+    }
+
+    /// Remembers the source's routes in `vectors` (the routing table picks them up from there).
+    /// If we don't have a connection to the source yet, we make one, since we can't use
+    /// routes through a peer we can't send to.
+    /// `name` is the name of the peer applying the command (i.e., us).
+    pub(crate) async fn apply(
+        &self,
+        name: &str,
+        peer_map: &PeerMap,
+        vectors: &TtlMap<Vec<(String, u32)>>,
+    ) -> Result<(), Bing2BingError> {
+        trace!("Applying RouteVector command: {:?}", self);
+
+        if self.source == name {
+            return Ok(());
+        }
+
+        vectors.set_if_changed(self.source.clone(), self.routes.clone(), Some(VECTOR_TIMEOUT));
+
+        if !peer_map.contains_peer(self.source.clone()) {
+            match parse_socket_addr(&self.ip_address, &self.port.to_string()) {
+                Ok(addr) => Server::connect_to_peer(peer_map, self.source.clone(), addr),
+                Err(err) => debug!(
+                    "Not connecting to {}; bad address in route vector: {}",
+                    self.source, err
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Turns this `RouteVector` into a [Bing2BingFrame].
+    pub fn into_frame(self) -> Bing2BingFrame {
+        let mut cmd = vec![
+            Bing2BingFrame::Text("route_vector".to_string()),
+            Bing2BingFrame::Text(self.source),
+            Bing2BingFrame::Number(self.sequence_number),
+            Bing2BingFrame::Text(self.ip_address),
+            Bing2BingFrame::Number(self.port),
+        ];
+
+        let routes = self
+            .routes
+            .into_iter()
+            .map(|(destination, hops)| Bing2BingFrame::Latency(destination, hops))
+            .collect();
+
+        cmd.push(Bing2BingFrame::Array(routes));
+
+        Bing2BingFrame::Array(cmd)
+    }
+}
@@ -1,13 +1,32 @@
-use crate::{peer_map::PeerMap, Bing2BingError, Bing2BingFrame, Parse};
+use crate::{peer_map::PeerMap, util::TtlMap, Bing2BingError, Bing2BingFrame, Parse};
+
+use std::time::Duration;
 
 use tracing::{instrument, trace};
 
+/// How long we hold on to the `Say`s we have seen, so that we can pass them on to peers whose
+/// [Digest](crate::cmd::Digest)s say they missed them.
+pub(crate) const RECENT_SAYS_WINDOW: Duration = Duration::from_secs(20);
+
 /// This command allows for propagation of normal chat messages through out the network.
+///
+/// A `Say` can be tagged with a channel (e.g., an IRC channel coming in over a bridge), so that
+/// conversations in different channels can be kept apart. The channel goes at the end of the
+/// frame, and is left off entirely for untagged messages.
+///
+/// A `Say` can also have a hop limit (after the channel), which is how many more hops it may
+/// travel before peers stop forwarding it (see [PeerMap::take_hop()]). It is left off when
+/// there is no limit.
+///
+/// Only a `Say` with neither looks exactly like it always has on the wire; peers from before
+/// channels and hop limits existed reject the others.
 #[derive(Debug, Clone)]
 pub struct Say {
     pub(crate) source: String,
     pub(crate) sequence_number: u64,
     pub(crate) message: String,
+    pub(crate) channel: Option<String>,
+    pub(crate) hop_limit: Option<u64>,
 }
 
 impl Say {
@@ -18,9 +37,28 @@ impl Say {
             source,
             sequence_number,
             message,
+            channel: None,
+            hop_limit: None,
         }
     }
 
+    /// Tags this `Say` with `channel`.
+    pub fn with_channel(mut self, channel: &str) -> Self {
+        self.channel = Some(channel.to_string());
+        self
+    }
+
+    /// Identifies this `Say` (as `source-sequence_number`).
+    pub(crate) fn id(&self) -> String {
+        format!("{}-{}", self.source, self.sequence_number)
+    }
+
+    /// Only lets this `Say` travel `hops` hops away from here.
+    pub fn with_hop_limit(mut self, hops: u64) -> Self {
+        self.hop_limit = Some(hops);
+        self
+    }
+
     pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Self, Bing2BingError> {
         let source = parse.next_string()?;
 
@@ -28,33 +66,55 @@ impl Say {
 
         let message = parse.next_text()?;
 
+        let channel = parse.next_optional_text()?;
+
+        let hop_limit = parse.next_optional_number()?;
+
         parse.finish()?;
 
-        Ok(Self::new(source, sequence_number, &message))
+        let mut say = Self::new(source, sequence_number, &message);
+        say.channel = channel;
+        say.hop_limit = hop_limit;
+
+        Ok(say)
     }
 
+    /// Passes this `Say` on (with a hop taken off its hop limit), and remembers it in
+    /// `recent_says` the way it was passed on, so that a [Digest](crate::cmd::Digest) can't
+    /// send it any further than that. A `Say` that has come as far as it may is neither.
     #[instrument(level = "trace")]
-    pub(crate) async fn apply(&self, peer_map: &PeerMap) -> Result<(), Bing2BingError> {
+    pub(crate) async fn apply(&self, peer_map: &PeerMap, recent_says: &TtlMap<Say>) -> Result<(), Bing2BingError> {
         trace!("Applying Say command: {:?}", self);
         // let's check to see if we have already covered the sequence number.
         // if we haven't, we will print this message out, if we have, we won't print it
 
-        let frame = self.clone().into_frame();
+        let mut say = self.clone();
 
-        peer_map.broadcast(self.source.clone(), frame);
+        if peer_map.take_hop(&mut say.hop_limit) {
+            recent_says.set(say.id(), say.clone(), Some(RECENT_SAYS_WINDOW));
+            peer_map.gossip(self.source.clone(), say.into_frame());
+        }
 
         Ok(())
     }
 
     /// Turns this `Say` into a [Bing2BingFrame].
     pub fn into_frame(self) -> Bing2BingFrame {
-        let cmd = vec![
+        let mut cmd = vec![
             Bing2BingFrame::Text("say".to_string()),
             Bing2BingFrame::Text(self.source),
             Bing2BingFrame::Number(self.sequence_number),
             Bing2BingFrame::Text(self.message),
         ];
 
+        if let Some(channel) = self.channel {
+            cmd.push(Bing2BingFrame::Text(channel));
+        }
+
+        if let Some(hop_limit) = self.hop_limit {
+            cmd.push(Bing2BingFrame::Number(hop_limit));
+        }
+
         // cmd.push(Bing2BingFrame::Text("say".to_string()));
         // cmd.push(Bing2BingFrame::Text(self.source));
         // cmd.push(Bing2BingFrame::Number(self.sequence_number));
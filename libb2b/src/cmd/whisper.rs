@@ -1,16 +1,10 @@
-use crate::{parse::Parse, peer_map::PeerMap, Bing2BingError, Bing2BingFrame, peer::PeerData, util::TtlMap};
+use crate::{parse::Parse, peer_map::PeerMap, Bing2BingError, Bing2BingFrame, RoutingTable};
 
-use tracing::{instrument, trace};
+use tracing::{debug, instrument, trace};
 
 /// This command allows for direct messaging between two peers.
-/// The idea is that peers should forward this message via the shortest path to the target.
-///
-/// # Points available.
-///
-/// Currently, [Whisper::apply()] just treats things as a [Say](crate::cmd::Say), and thus
-/// the message is broadcast to all outgoing peers.
-/// For extra points, make it only send the data out over the next hop in the shortest path
-/// to the destination.
+/// Peers forward this message via the shortest path to the target: either the `path` the sender
+/// put on it, or (if that's empty) the next hop in their own routing table.
 #[derive(Debug, Clone)]
 pub struct Whisper {
     pub(crate) source: String,
@@ -77,23 +71,40 @@ impl Whisper {
     }
 
 
-    /// Currently just broadcasts the message back out to everyone else
-    /// This will (eventually) mean that the whisper will arrive at its
-    /// destination.
+    /// Passes the whisper on to the next peer on its way to the destination
+    /// (`name` is us; if the whisper is for us, there is nothing to pass on).
+    /// If the sender gave us a path, we follow it, otherwise we ask our own `routing_table`
+    /// (e.g., when the network routes by [distance vector](crate::RoutingMode::DistanceVector)).
     #[instrument(level = "trace")]
 //    #[instrument]
-    pub(crate) async fn apply(&self, peer_map: &PeerMap) -> Result<(), Bing2BingError> {
+    pub(crate) async fn apply(&self, name: &str, peer_map: &PeerMap, routing_table: &RoutingTable) -> Result<(), Bing2BingError> {
         trace!("Applying Whisper command: {:?}", self);
 
-        if(self.path.clone().is_empty()){
+        if self.destination == name {
 			return Ok(());
 		}
-		
-		let next_step = self.path.clone().remove(0);
 
-		let frame = self.clone().into_frame();
-
-        peer_map.send_to_peer(self.source.clone(), next_step, frame);
+		let mut whisper = self.clone();
+
+		let next_step = if whisper.path.is_empty() {
+			match routing_table.next_hop(&self.destination) {
+				Ok(next_hop) => next_hop,
+				Err(err) => {
+					debug!("Couldn't pass on whisper from {} to {}: {}", self.source, self.destination, err);
+					return Ok(());
+				}
+			}
+		} else {
+			// the next peer gets the rest of the path
+			whisper.path.remove(0)
+		};
+
+		let frame = whisper.into_frame();
+
+        // this whisper isn't ours, so there is nobody to tell if we can't pass it on
+        if let Err(err) = peer_map.send_to_peer(self.source.clone(), next_step, frame) {
+            debug!("Couldn't pass on whisper from {} to {}: {}", self.source, self.destination, err);
+        }
 
         Ok(())
     }
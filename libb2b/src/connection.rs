@@ -32,9 +32,9 @@ impl Connection {
     /// Returns the next [Bing2BingFrame] from the wire.
     pub async fn read_frame(&mut self) -> Result<Option<Frame>, Bing2BingError> {
         match self.frames.next().await {
-            Some(Ok(frame)) => return Ok(Some(frame)),
-            Some(Err(err)) => return Err(Box::new(err)),
-            None => return Ok(None),
+            Some(Ok(frame)) => Ok(Some(frame)),
+            Some(Err(err)) => Err(Box::new(err)),
+            None => Ok(None),
         }
     }
 
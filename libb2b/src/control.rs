@@ -0,0 +1,319 @@
+//! A local control endpoint for a running peer, so that scripts and editor plugins can drive it
+//! without linking against this crate.
+//!
+//! The endpoint is a Unix socket that speaks newline delimited JSON. Every line sent to it is a
+//! [ControlRequest], and every line that comes back is a [ControlResponse]; both carry an
+//! optional `id` that responses echo back, so requests can be matched up with their answers.
+//!
+//! ```text
+//! > {"id": 1, "request": "say", "message": "hello everyone"}
+//! < {"id": 1, "response": "ok"}
+//! > {"request": "say", "channel": "#rust", "message": "anyone around?"}
+//! < {"id": null, "response": "ok"}
+//! > {"request": "whisper", "to": "bob", "message": "psst"}
+//! < {"id": null, "response": "ok"}
+//! > {"request": "subscribe"}
+//! < {"id": null, "response": "subscribed"}
+//! < {"id": null, "response": "message", "message": {"Say": ["bob", "hi!"]}}
+//! ```
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{debug, error, trace};
+
+use crate::{Bing2BingError, Client, ClientRxChannel, ClientServerMessage, Route, TopologyNode};
+
+/// Something a control connection asks the [Client] to do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// [Client::say()], or [Client::say_in_channel()] if there is a `channel`,
+    /// or [Client::say_within()] if there is a number of `hops` (but not both).
+    Say {
+        message: String,
+        #[serde(default)]
+        channel: Option<String>,
+        #[serde(default)]
+        hops: Option<u64>,
+    },
+    /// [Client::whisper()], or [Client::whisper_important()] if `important` is set.
+    Whisper {
+        to: String,
+        message: String,
+        #[serde(default)]
+        important: bool,
+    },
+    /// Every peer we know about, along with its links (see [Topology](crate::Topology)).
+    Peers,
+    /// How we would route to every peer we can reach.
+    Routes,
+    /// Start getting a [ControlResponse::Message] for every message the client receives.
+    Subscribe,
+}
+
+/// The answer to a [ControlRequest] (or, after subscribing, an incoming message).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum ControlResponse {
+    Ok,
+    Peers { peers: Vec<TopologyNode> },
+    Routes { routes: Vec<Route> },
+    Subscribed,
+    Message { message: ClientServerMessage },
+    Error { error: String },
+}
+
+/// A [ControlRequest] along with its (optional) id.
+#[derive(Debug, Clone, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    request: ControlRequest,
+}
+
+/// A [ControlResponse] along with the id of the request it answers.
+#[derive(Debug, Clone, Serialize)]
+struct Response {
+    id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    response: ControlResponse,
+}
+
+/// Listens for control connections on a Unix socket and answers them with a [Client].
+#[derive(Debug)]
+pub struct ControlServer {
+    path: PathBuf,
+    listener: UnixListener,
+    client: Client,
+}
+
+impl ControlServer {
+    /// Listens on `path`. If there is already something at `path` (e.g., a socket left
+    /// behind by a peer that crashed) it is removed first.
+    pub fn bind(path: impl AsRef<Path>, client: Client) -> Result<Self, Bing2BingError> {
+        let path = path.as_ref().to_path_buf();
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+
+        debug!("Control server listening on {:?}", path);
+
+        Ok(Self {
+            path,
+            listener,
+            client,
+        })
+    }
+
+    /// Accepts control connections forever, handling each in its own task.
+    pub async fn serve(self) -> Result<(), Bing2BingError> {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            trace!("New control connection on {:?}", self.path);
+
+            let client = self.client.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handle_connection(stream, client).await {
+                    debug!("Control connection closed: {}", err);
+                }
+            });
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn handle_connection(stream: UnixStream, client: Client) -> Result<(), Bing2BingError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let mut session = ControlSession::new(client);
+
+    loop {
+        let mut response = tokio::select! {
+            line = lines.next_line() => match line? {
+                Some(line) if line.trim().is_empty() => continue,
+                Some(line) => session.handle(&line).await,
+                None => return Ok(()),
+            },
+            Some(message) = session.next_message() => message,
+        };
+
+        response.push('\n');
+        writer.write_all(response.as_bytes()).await?;
+    }
+}
+
+/// Speaks the control protocol over a WebSocket `stream`: a request in each text frame, and a
+/// response in each text frame that comes back. Incoming messages are subscribed to from the
+/// start, since a web page is going to want to see them as they come in.
+///
+/// This is what the `ws_gateway` binary runs for each connection.
+#[cfg(feature = "websocket")]
+pub async fn serve_websocket(stream: tokio::net::TcpStream, client: Client) -> Result<(), Bing2BingError> {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    let mut ws = tokio_tungstenite::accept_async(stream).await?;
+
+    let mut session = ControlSession::new(client);
+    session.subscribe();
+
+    loop {
+        let response = tokio::select! {
+            frame = ws.next() => match frame {
+                Some(Ok(Message::Text(request))) => session.handle(&request).await,
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                // pings are answered for us, and we don't do anything with binary frames
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => return Err(err.into()),
+            },
+            Some(message) = session.next_message() => message,
+        };
+
+        ws.send(Message::text(response)).await?;
+    }
+}
+
+/// One connection's worth of the control protocol, independent of how the requests get to us:
+/// JSON requests go in, and JSON responses come out.
+///
+/// This is what the [ControlServer] uses for each connection, and it can be used the same way
+/// to offer the control protocol over other transports (e.g., WebSockets).
+#[derive(Debug)]
+pub struct ControlSession {
+    client: Client,
+    subscription: Option<ClientRxChannel>,
+}
+
+impl ControlSession {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            subscription: None,
+        }
+    }
+
+    /// Subscribes to incoming messages, just like a `subscribe` request does.
+    pub fn subscribe(&mut self) {
+        if self.subscription.is_none() {
+            self.subscription = Some(self.client.subscribe());
+        }
+    }
+
+    /// Waits for the next incoming message, as a JSON [ControlResponse::Message].
+    /// If we haven't subscribed this never returns, which makes it handy in a `select!`.
+    /// Returns `None` if the client stopped sending messages.
+    pub async fn next_message(&self) -> Option<String> {
+        let message = match &self.subscription {
+            Some(subscription) => subscription.recv().await.ok()?,
+            None => std::future::pending().await,
+        };
+
+        Some(to_json(&Response {
+            id: None,
+            response: ControlResponse::Message { message },
+        }))
+    }
+
+    /// Carries out a single JSON [ControlRequest], returning the JSON [ControlResponse].
+    pub async fn handle(&mut self, request: &str) -> String {
+        let response = match serde_json::from_str::<Request>(request) {
+            Ok(request) => {
+                trace!("Control request: {:?}", request);
+
+                Response {
+                    id: request.id,
+                    response: self.carry_out(request.request).await,
+                }
+            }
+            Err(err) => Response {
+                id: None,
+                response: ControlResponse::Error {
+                    error: format!("bad request: {}", err),
+                },
+            },
+        };
+
+        to_json(&response)
+    }
+
+    async fn carry_out(&mut self, request: ControlRequest) -> ControlResponse {
+        let client = &self.client;
+
+        match request {
+            ControlRequest::Say {
+                message,
+                channel,
+                hops,
+            } => {
+                match (channel, hops) {
+                    (Some(_), Some(_)) => {
+                        return ControlResponse::Error {
+                            error: "a say can have a channel or hops, but not both".to_string(),
+                        }
+                    }
+                    (Some(channel), None) => client.say_in_channel(channel, message).await,
+                    (None, Some(hops)) => client.say_within(hops, message).await,
+                    (None, None) => client.say(message).await,
+                }
+                ControlResponse::Ok
+            }
+            ControlRequest::Whisper {
+                to,
+                message,
+                important,
+            } => {
+                if important {
+                    client.whisper_important(to, message).await;
+                } else {
+                    client.whisper(to, message).await;
+                }
+                ControlResponse::Ok
+            }
+            ControlRequest::Peers => ControlResponse::Peers {
+                peers: client.topology().nodes,
+            },
+            ControlRequest::Routes => ControlResponse::Routes {
+                routes: client.routes(),
+            },
+            ControlRequest::Subscribe => {
+                self.subscribe();
+                ControlResponse::Subscribed
+            }
+        }
+    }
+}
+
+fn to_json(response: &Response) -> String {
+    // everything in a response is plain data that serde_json can always serialize
+    serde_json::to_string(response).expect("couldn't serialize control response")
+}
+
+/// Runs a [ControlServer] on `path` in the background, logging (rather than returning) errors.
+/// Must be called from within a tokio runtime.
+pub fn spawn_control_server(path: impl AsRef<Path>, client: Client) {
+    let server = match ControlServer::bind(&path, client) {
+        Ok(server) => server,
+        Err(err) => {
+            error!("Couldn't start control server on {:?}: {}", path.as_ref(), err);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        if let Err(err) = server.serve().await {
+            error!("Control server shut down: {}", err);
+        }
+    });
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ClientServerMessage;
+
+/// Something that happened on the network that a user of a [Client](crate::Client) might care
+/// about. See [Client::events()](crate::Client::events()).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ClientEvent {
+    /// A `Say`, `ChannelSay` or `Whisper` arrived for us.
+    Message(ClientServerMessage),
+    /// We opened a connection to this peer.
+    PeerConnected(String),
+    /// Our connection to this peer went away.
+    PeerDisconnected(String),
+    /// Our picture of the network changed: an `Announce` told us something new, or we
+    /// stopped hearing from a peer and forgot about it.
+    /// See [Client::topology()](crate::Client::topology()) for the new picture.
+    TopologyChanged,
+    /// A peer sent us `data` with a `Deliver`.
+    Delivered { from: String, data: Vec<u8> },
+    /// A message we sent couldn't be passed on to the next peer on its way to `to`.
+    DeliveryFailed {
+        to: String,
+        message: String,
+        reason: String,
+    },
+    /// The [Server](crate::Server) stopped. This is always the last event.
+    ServerStopped,
+}
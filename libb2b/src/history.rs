@@ -0,0 +1,182 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::{Bing2BingError, ClientServerMessage};
+
+/// A single message in a [History].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the message was sent or received, in seconds since the unix epoch.
+    pub timestamp: u64,
+    pub from: String,
+    /// Who the message was whispered to; `None` for a `Say`.
+    pub to: Option<String>,
+    /// The channel a `Say` was tagged with, if any.
+    /// (Missing in histories written before channels existed.)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    pub message: String,
+}
+
+impl HistoryEntry {
+    /// `None` if `message` isn't something anyone said (i.e., it is a warning).
+    pub(crate) fn new(message: &ClientServerMessage) -> Option<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or(0);
+
+        let (from, to, channel, message) = match message {
+            ClientServerMessage::Say((from, message))
+            | ClientServerMessage::ScopedSay((from, _, message)) => {
+                (from.clone(), None, None, message.clone())
+            }
+            ClientServerMessage::ChannelSay((from, channel, message)) => {
+                (from.clone(), None, Some(channel.clone()), message.clone())
+            }
+            ClientServerMessage::Whisper((from, to, message))
+            | ClientServerMessage::ImportantWhisper((from, to, message)) => {
+                (from.clone(), Some(to.clone()), None, message.clone())
+            }
+            // the history is for text
+            ClientServerMessage::Deliver(_)
+            | ClientServerMessage::ImportantDeliver(_)
+            | ClientServerMessage::Throttled(_) => return None,
+        };
+
+        Some(Self {
+            timestamp,
+            from,
+            to,
+            channel,
+            message,
+        })
+    }
+
+    pub fn is_whisper(&self) -> bool {
+        self.to.is_some()
+    }
+
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.timestamp)
+    }
+
+    /// Did `peer` send or receive this message?
+    pub fn involves(&self, peer: &str) -> bool {
+        self.from == peer || self.to.as_deref() == Some(peer)
+    }
+}
+
+/// What to look for with [History::query()]. The default matches everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryQuery {
+    /// Only messages sent by or whispered to this peer.
+    pub peer: Option<String>,
+    /// Only messages from this time or later.
+    pub since: Option<SystemTime>,
+    /// Only messages from before this time.
+    pub until: Option<SystemTime>,
+    /// Only the most recent `limit` messages that match.
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        let time = entry.time();
+
+        self.peer.as_deref().is_none_or(|peer| entry.involves(peer))
+            && self.since.is_none_or(|since| time >= since)
+            && self.until.is_none_or(|until| time < until)
+    }
+}
+
+/// An append-only log of the `Say`s and `Whisper`s a [Client](crate::Client) has sent and received,
+/// stored on disk as one JSON [HistoryEntry] per line.
+///
+/// Give one to a client with [Client::set_history()](crate::Client::set_history()).
+/// Cloning a `History` gives another handle to the same file.
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+impl History {
+    /// Opens the history stored at `path`, creating it if it doesn't exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Bing2BingError> {
+        let path = path.as_ref().to_path_buf();
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        debug!("Opened history at {:?}", path);
+
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Appends `entry` to the history.
+    pub fn record(&self, entry: &HistoryEntry) -> Result<(), Bing2BingError> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        // a single write (with the file opened in append mode) keeps lines from different
+        // handles from getting mixed up
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Like [History::record()], but errors are logged instead of returned.
+    pub(crate) fn record_message(&self, message: &ClientServerMessage) {
+        let entry = match HistoryEntry::new(message) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        if let Err(err) = self.record(&entry) {
+            error!("Couldn't write to history at {:?}: {}", self.path, err);
+        }
+    }
+
+    /// Every entry that matches `query`, oldest first.
+    /// Lines that can't be parsed (e.g., one that was cut short by a crash) are skipped.
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, Bing2BingError> {
+        let file = File::open(&self.path)?;
+
+        let mut entries = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+
+            match serde_json::from_str::<HistoryEntry>(&line) {
+                Ok(entry) if query.matches(&entry) => entries.push(entry),
+                Ok(_) => {}
+                Err(err) => debug!("Skipping bad history line {:?}: {}", line, err),
+            }
+        }
+
+        if let Some(limit) = query.limit {
+            let skip = entries.len().saturating_sub(limit);
+            entries.drain(..skip);
+        }
+
+        Ok(entries)
+    }
+
+    /// The `n` most recent entries, oldest first.
+    pub fn recent(&self, n: usize) -> Result<Vec<HistoryEntry>, Bing2BingError> {
+        self.query(&HistoryQuery {
+            limit: Some(n),
+            ..Default::default()
+        })
+    }
+}
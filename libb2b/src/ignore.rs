@@ -0,0 +1,123 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tracing::debug;
+
+use crate::Bing2BingError;
+
+/// The peers our user doesn't want to hear from, shared between a [Client](crate::Client)
+/// (which keeps their messages from the user) and its [Server](crate::Server) (which, in
+/// strict mode, doesn't pass their frames on either).
+///
+/// If it has a file, the list is saved there (one peer per line) every time it changes.
+#[derive(Debug, Clone)]
+pub struct IgnoreList {
+    shared: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    peers: BTreeSet<String>,
+    path: Option<PathBuf>,
+    strict: bool,
+}
+
+impl IgnoreList {
+    /// An empty list that isn't saved anywhere (yet).
+    pub(crate) fn new() -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Adds the peers saved at `path` (if there is anything there yet), and saves the list
+    /// there from now on.
+    pub(crate) fn load(&self, path: impl AsRef<Path>) -> Result<(), Bing2BingError> {
+        let path = path.as_ref().to_path_buf();
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut state = self.shared.lock().unwrap();
+        state.peers.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string),
+        );
+        state.path = Some(path);
+
+        debug!("Loaded ignore list {:?}: {:?}", state.path, state.peers);
+
+        state.save()
+    }
+
+    /// Returns whether `peer` wasn't already ignored.
+    pub(crate) fn add(&self, peer: &str) -> Result<bool, Bing2BingError> {
+        let mut state = self.shared.lock().unwrap();
+
+        if !state.peers.insert(peer.to_string()) {
+            return Ok(false);
+        }
+
+        state.save()?;
+        Ok(true)
+    }
+
+    /// Returns whether `peer` was ignored.
+    pub(crate) fn remove(&self, peer: &str) -> Result<bool, Bing2BingError> {
+        let mut state = self.shared.lock().unwrap();
+
+        if !state.peers.remove(peer) {
+            return Ok(false);
+        }
+
+        state.save()?;
+        Ok(true)
+    }
+
+    pub(crate) fn contains(&self, peer: &str) -> bool {
+        self.shared.lock().unwrap().peers.contains(peer)
+    }
+
+    /// Every ignored peer, sorted.
+    pub(crate) fn peers(&self) -> Vec<String> {
+        self.shared.lock().unwrap().peers.iter().cloned().collect()
+    }
+
+    pub(crate) fn set_strict(&self, strict: bool) {
+        self.shared.lock().unwrap().strict = strict;
+    }
+
+    /// Should the frames `peer` sends be dropped instead of passed on?
+    pub(crate) fn refuses(&self, peer: &str) -> bool {
+        let state = self.shared.lock().unwrap();
+        state.strict && state.peers.contains(peer)
+    }
+}
+
+impl State {
+    fn save(&self) -> Result<(), Bing2BingError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let mut contents = String::new();
+        for peer in &self.peers {
+            contents.push_str(peer);
+            contents.push('\n');
+        }
+
+        fs::write(path, contents)?;
+
+        Ok(())
+    }
+}
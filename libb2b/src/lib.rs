@@ -9,6 +9,7 @@
 //!
 //!
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio_serde::formats::SymmetricalJson;
@@ -17,6 +18,7 @@ use tracing::instrument;
 // Exports
 mod peer;
 use peer::Peer;
+pub use peer::{Location, STALE_AFTER};
 
 mod connection;
 pub use connection::Connection;
@@ -24,14 +26,38 @@ pub use connection::Connection;
 mod peer_map;
 
 mod server;
-pub use server::Server;
+pub use server::{Server, DEFAULT_HOP_LIMIT};
 
 mod client;
 pub use client::Client;
 
+mod event;
+pub use event::ClientEvent;
+
 pub mod tracker;
 pub use tracker::Tracker;
 
+pub mod bot;
+pub use bot::{Bot, BotContext, COMMAND_PREFIX};
+
+pub mod control;
+pub use control::{ControlRequest, ControlResponse, ControlServer, ControlSession};
+
+mod ignore;
+use ignore::IgnoreList;
+
+mod history;
+pub use history::{History, HistoryEntry, HistoryQuery};
+
+mod routing;
+pub use routing::{RoutingError, RoutingMode, RoutingTable, INFINITE_HOPS};
+
+mod rate_limit;
+pub use rate_limit::{RateLimit, RateLimiter, DEFAULT_LINK_RATE_LIMIT, DEFAULT_SOURCE_RATE_LIMIT};
+
+mod topology;
+pub use topology::{Disjointness, Route, Topology, TopologyLink, TopologyNode};
+
 mod parse;
 use parse::Parse;
 
@@ -39,23 +65,58 @@ pub mod cmd;
 pub use cmd::Bing2BingCommand;
 
 mod util;
+pub use util::SequenceWindows;
 
 pub type Bing2BingError = Box<dyn std::error::Error + Send + Sync>;
 pub type ClientTxChannel = async_channel::Sender<ClientServerMessage>;
 pub type ClientRxChannel = async_channel::Receiver<ClientServerMessage>;
+pub type EventTxChannel = async_channel::Sender<ClientEvent>;
+pub type EventRxChannel = async_channel::Receiver<ClientEvent>;
 pub type ServerTxChannel = async_channel::Sender<ClientServerMessage>;
 pub type ServerRxChannel = async_channel::Receiver<ClientServerMessage>;
 type PeerTxChannel = mpsc::UnboundedSender<PeerControlMessage>;
 type PeerRxChannel = mpsc::UnboundedReceiver<PeerControlMessage>;
 
-#[derive(Debug)]
+/// Messages passed between a [Client] and its [Server]: `Say((from, message))`,
+/// `ChannelSay((from, channel, message))` (a `Say` tagged with a channel),
+/// `ScopedSay((from, hops, message))` (a `Say` that only goes `hops` hops; it arrives as a plain `Say`),
+/// `Whisper((from, to, message))` and `ImportantWhisper((from, to, message))`
+/// (a `Whisper` that is sent over several paths at once; it arrives as a plain `Whisper`),
+/// and `Deliver((from, to, data))` and `ImportantDeliver((from, to, data))`, which do the same
+/// for raw bytes (they arrive as a [ClientEvent::Delivered]).
+/// `Throttled(who)` only goes from a [Server] to its [Client]: we started dropping frames from
+/// `who` (a source's name, or the address of an incoming connection) for coming in too fast.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ClientServerMessage {
     Say((String, String)),
+    ScopedSay((String, u64, String)),
+    ChannelSay((String, String, String)),
 	Whisper((String, String, String)),
+	ImportantWhisper((String, String, String)),
+    Deliver((String, String, Vec<u8>)),
+    ImportantDeliver((String, String, Vec<u8>)),
+    Throttled(String),
+}
+
+impl ClientServerMessage {
+    /// The peer that sent this message (`None` for a warning like `Throttled`).
+    pub fn sender(&self) -> Option<&str> {
+        match self {
+            ClientServerMessage::Say((from, _))
+            | ClientServerMessage::ScopedSay((from, _, _))
+            | ClientServerMessage::ChannelSay((from, _, _))
+            | ClientServerMessage::Whisper((from, _, _))
+            | ClientServerMessage::ImportantWhisper((from, _, _))
+            | ClientServerMessage::Deliver((from, _, _))
+            | ClientServerMessage::ImportantDeliver((from, _, _)) => Some(from),
+            ClientServerMessage::Throttled(_) => None,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub(crate) enum PeerControlMessage {
+    #[allow(dead_code)]
     ShutDown,
     Frame(Bing2BingFrame),
 }
@@ -93,15 +154,20 @@ pub enum Bing2BingFrame {
 
 /// Initializes a new "peer." I.e., creates a [Client] and a [Server] pair.
 /// Upon return, the [Server] will be ready to be [Server::start()]ed.
+///
+/// `ip_address` can be either an IPv4 or an IPv6 address.
+/// Note that it is sent out as part of protocol messages, so it should be an
+/// address that other peers can actually reach us on (i.e., not `0.0.0.0` or `::`).
 #[instrument(level = "trace")]
-pub async fn init(name: &str, ip_address: &str, port: u16) -> (Client, Server) {
+pub async fn init(name: &str, ip_address: IpAddr, port: u16) -> (Client, Server) {
     let (server_tx, server_rx) = async_channel::unbounded();
     // let client = Client::new()
-    let (client_tx, rx2) = async_channel::unbounded();
-    let client = Client::new(name.to_string(), server_tx.clone(), rx2);
-    let server = Server::new(&name, &ip_address, &port.to_string(), client_tx, server_rx)
+    let (events_tx, events_rx) = async_channel::unbounded();
+    let addr = SocketAddr::new(ip_address, port);
+    let server = Server::new(name, addr, events_tx, server_rx)
         .await
         .unwrap();
+    let client = Client::new(name.to_string(), server_tx.clone(), events_rx, &server);
 
     (client, server)
 }
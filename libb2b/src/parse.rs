@@ -2,7 +2,7 @@ use crate::Bing2BingError;
 use crate::Bing2BingFrame;
 
 use bytes::Bytes;
-use std::{fmt, str, vec};
+use std::{fmt, iter::Peekable, str, vec};
 
 /// This struct helps in the construction of commands by
 /// providing convience methods for individual frame elements out of a
@@ -10,7 +10,7 @@ use std::{fmt, str, vec};
 #[derive(Debug)]
 pub(crate) struct Parse {
     /// Array frame iterator
-    parts: vec::IntoIter<Bing2BingFrame>,
+    parts: Peekable<vec::IntoIter<Bing2BingFrame>>,
 }
 
 #[derive(Debug)]
@@ -31,7 +31,7 @@ impl Parse {
         };
 
         Ok(Self {
-            parts: array.into_iter(),
+            parts: array.into_iter().peekable(),
         })
     }
 
@@ -81,11 +81,38 @@ impl Parse {
         }
     }
 
+    /// Like [Parse::next_text()], but for optional trailing fields: returns `None` (and leaves
+    /// the next frame alone) if the frame has been fully consumed, or the next frame isn't Text.
+    pub(crate) fn next_optional_text(&mut self) -> Result<Option<String>, ParseError> {
+        match self.parts.peek() {
+            Some(Bing2BingFrame::Text(_)) => self.next_text().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Like [Parse::next_number()], but for optional trailing fields: returns `None` (and leaves
+    /// the next frame alone) if the frame has been fully consumed, or the next frame isn't a Number.
+    pub(crate) fn next_optional_number(&mut self) -> Result<Option<u64>, ParseError> {
+        match self.parts.peek() {
+            Some(Bing2BingFrame::Number(_)) => self.next_number().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Like [Parse::next_array()], but for optional trailing fields: returns `None` (and leaves
+    /// the next frame alone) if the frame has been fully consumed, or the next frame isn't an Array.
+    pub(crate) fn next_optional_array(&mut self) -> Result<Option<Vec<Bing2BingFrame>>, ParseError> {
+        match self.parts.peek() {
+            Some(Bing2BingFrame::Array(_)) => self.next_array().map(Some),
+            _ => Ok(None),
+        }
+    }
+
     /// Returns the next Array frame, or errors otherwise.
     pub(crate) fn next_array(&mut self) -> Result<Vec<Bing2BingFrame>, ParseError> {
         match self.next()? {
             Bing2BingFrame::Array(array) => Ok(array),
-            frame => return Err(format!("protcol error; expected array, got {:?}", frame).into()),
+            frame => Err(format!("protcol error; expected array, got {:?}", frame).into()),
         }
     }
 
@@ -2,7 +2,8 @@ use crate::PeerRxChannel;
 use crate::{Bing2BingError, Connection, PeerControlMessage};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use std::cmp::Ordering;
+use std::path::Path;
+use std::time::Duration;
 use tokio::net::TcpStream;
 
 pub(crate) struct Peer {
@@ -11,19 +12,23 @@ pub(crate) struct Peer {
 }
 
 impl Peer {
-    pub(crate) fn new(name: String, ip_address: String, port: String, rx: PeerRxChannel) -> Self {
-        let addr: SocketAddr = format!("{}:{}", ip_address, port).parse().unwrap();
+    pub(crate) fn new(name: String, addr: SocketAddr, rx: PeerRxChannel) -> Self {
         Peer {
             info: PeerInfo { name, addr },
             rx,
         }
     }
 
-    pub(crate) async fn start(&mut self) -> Result<(), Bing2BingError> {
-        let tcp_stream = TcpStream::connect(self.info.addr).await.unwrap();
+    /// Opens the connection to the peer.
+    pub(crate) async fn connect(&self) -> Result<Connection, Bing2BingError> {
+        let tcp_stream = TcpStream::connect(self.info.addr).await?;
 
-        let mut connection = Connection::new(tcp_stream).await;
+        Ok(Connection::new(tcp_stream).await)
+    }
 
+    /// Sends whatever comes in over our channel across `connection` (see [Peer::connect()]),
+    /// until we are told to shut down or the connection breaks.
+    pub(crate) async fn run(&mut self, mut connection: Connection) -> Result<(), Bing2BingError> {
         loop {
             // let x = self.rx.recv().await;
             tokio::select! {
@@ -52,28 +57,158 @@ pub(crate) struct PeerInfo {
     addr: SocketAddr,
 }
 
+/// Latency value that is sent in an [Announce](crate::cmd::Announce) for links that
+/// we haven't actually measured yet. This is what peers have always sent for every link, so
+/// announces from older peers are read the same way.
+/// When routing, links with this latency fall back to using the geographic distance
+/// between the two peers as a weight (see [PeerData::link_weight()]).
+pub const UNMEASURED_LATENCY: u32 = 1;
+
+/// Where a peer is (or at least, claims to be) in the world.
+/// This is sent out as part of every [Announce](crate::cmd::Announce).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    city: String,
+    lat: f64,
+    lng: f64,
+}
+
+impl Location {
+    pub fn new(city: &str, lat: f64, lng: f64) -> Self {
+        Self {
+            city: city.to_string(),
+            lat,
+            lng,
+        }
+    }
+
+    /// Looks up `city` in a local location file.
+    ///
+    /// The file is expected to have one `city,lat,lng` entry per line.
+    /// Blank lines, lines starting with `#`, and lines whose lat/lng don't parse
+    /// (e.g., a header) are skipped. City names are matched case-insensitively.
+    pub fn lookup(path: impl AsRef<Path>, city: &str) -> Result<Self, Bing2BingError> {
+        let contents = std::fs::read_to_string(path)?;
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // we split from the right so that city names can contain commas
+            let mut fields = line.rsplitn(3, ',');
+            let (lng, lat, name) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(lng), Some(lat), Some(name)) => (lng, lat, name.trim()),
+                _ => continue,
+            };
+
+            if !name.eq_ignore_ascii_case(city) {
+                continue;
+            }
+
+            if let (Ok(lat), Ok(lng)) = (lat.trim().parse(), lng.trim().parse()) {
+                return Ok(Location::new(name, lat, lng));
+            }
+        }
+
+        Err(format!("could not find a location for {:?}", city).into())
+    }
+
+    pub fn city(&self) -> &str {
+        &self.city
+    }
+
+    pub fn lat(&self) -> f64 {
+        self.lat
+    }
+
+    pub fn lng(&self) -> f64 {
+        self.lng
+    }
+
+    /// Great circle distance (in kilometers) between two locations, using the haversine formula.
+    pub fn distance_km(&self, other: &Location) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lng = (other.lng - self.lng).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+/// Defaults to where every peer used to claim to be.
+impl Default for Location {
+    fn default() -> Self {
+        Location::new("New York", 40.6943, -73.9249)
+    }
+}
+
+/// How long after a peer's last [Announce](crate::cmd::Announce) we consider what it told us
+/// stale, and stop routing over its links.
+/// Peers announce every 5 seconds, so this is three announces in a row that didn't make it to us.
+/// (Its entry is dropped altogether after 30 seconds.)
+pub const STALE_AFTER: Duration = Duration::from_secs(15);
+
 /// POINTS AVAILABLE FOR CLEANING THIS UP (renaming/refactoring as needed?)
 /// This is a very poorly named structure that wraps the bits of data
 /// that come in over an [Announce](crate::cmd::Announce).
 #[derive(Debug, Clone)]
 pub struct PeerData {
-    city: String,
-    lat: f64,
-    lng: f64,
+    location: Location,
     peers: Vec<(String, u32)>,
+    /// The sequence number of the announce this came from.
+    sequence_number: u64,
+}
+
+/// Two `PeerData`s are the same if they describe the same neighborhood;
+/// which announce they came from doesn't matter.
+impl PartialEq for PeerData {
+    fn eq(&self, other: &Self) -> bool {
+        self.location == other.location && self.peers == other.peers
+    }
 }
 
 impl PeerData {
-    pub fn new(city: &str, lat: f64, lng: f64, peers: Vec<(String, u32)>) -> Self {
+    pub fn new(city: &str, lat: f64, lng: f64, peers: Vec<(String, u32)>, sequence_number: u64) -> Self {
         Self {
-            city: city.to_string(),
-            lat,
-            lng,
+            location: Location::new(city, lat, lng),
             peers,
+            sequence_number,
         }
     }
 
+    /// The sequence number of the announce this came from. Announces with a sequence number that
+    /// isn't higher than this are older news.
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
 	pub fn get_peers(&self) -> &Vec<(String, u32)> {
 		&(self.peers)
 	}
+
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    /// The weight of the link from this peer to `neighbor` for routing purposes.
+    ///
+    /// If the latency of the link has been measured, that is what we use.
+    /// Otherwise, we fall back to the distance (in km) between the two peers if we know
+    /// where `neighbor` is, and finally to a weight of 1.
+    pub fn link_weight(&self, latency: u32, neighbor: Option<&PeerData>) -> u32 {
+        if latency != UNMEASURED_LATENCY {
+            return latency;
+        }
+
+        match neighbor {
+            Some(neighbor) => (self.location.distance_km(&neighbor.location).round() as u32).max(1),
+            None => 1,
+        }
+    }
 }
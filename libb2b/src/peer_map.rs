@@ -1,16 +1,16 @@
 use std::collections::HashMap;
 
+use rand::seq::IteratorRandom;
+
 use tracing::{error, instrument, trace};
 
 use std::sync::Mutex;
 
 use std::sync::Arc;
 
-use crate::Bing2BingFrame;
+use crate::{util::DropCounter, Bing2BingError, Bing2BingFrame, ClientEvent, EventTxChannel};
 use crate::{PeerControlMessage, PeerTxChannel};
 
-use priority_queue::PriorityQueue;
-
 /// A `PeerMap` contains data and functionality related to peers that
 /// this peer has initiated connections with.
 /// I.e., the peers that this peer can send comands _to_.
@@ -25,7 +25,7 @@ use priority_queue::PriorityQueue;
 /// the lock going out of scope.
 ///
 /// You can, of course force the dropping of the lock. For example:
-/// ```
+/// ```ignore
 /// let mut mutex = Mutex::new(10);
 /// let x = 0;
 ///
@@ -48,11 +48,17 @@ pub struct PeerMap {
 #[derive(Debug)]
 struct Shared {
     state: Mutex<State>,
+    /// Where to send [ClientEvent]s about the network, if anywhere (see [PeerMap::with_events()]).
+    events: Option<EventTxChannel>,
+    /// How many flooded commands we didn't forward because of their hop limit.
+    hop_limit_drops: DropCounter,
 }
 
 #[derive(Debug)]
 struct State {
     entries: HashMap<String, PeerTxChannel>,
+    /// How many peers [PeerMap::gossip()] sends to, if it doesn't flood.
+    gossip_fanout: Option<usize>,
 }
 
 impl Default for PeerMap {
@@ -63,15 +69,53 @@ impl Default for PeerMap {
 
 impl PeerMap {
     pub fn new() -> Self {
+        Self::with_events(None, DropCounter::new())
+    }
+
+    /// Creates a `PeerMap` that passes [ClientEvent]s (see [PeerMap::notify()]) on to `events`,
+    /// and counts hop limit drops (see [PeerMap::take_hop()]) in `hop_limit_drops`.
+    pub(crate) fn with_events(events: Option<EventTxChannel>, hop_limit_drops: DropCounter) -> Self {
         let shared = Arc::new(Shared {
             state: Mutex::new(State {
                 entries: HashMap::new(),
+                gossip_fanout: None,
             }),
+            events,
+            hop_limit_drops,
         });
 
         PeerMap { shared }
     }
 
+    /// Lets our [Client](crate::Client) know that something happened.
+    /// Since everything that deals with the network has a `PeerMap` at hand, this is the
+    /// easiest place to do it from.
+    pub(crate) fn notify(&self, event: ClientEvent) {
+        if let Some(events) = &self.shared.events {
+            trace!("Notifying client of {:?}", event);
+            // the channel is unbounded, so this only fails if the client is gone
+            let _ = events.try_send(event);
+        }
+    }
+
+    /// Takes a hop off the hop limit of a flooded command that we are about to forward.
+    /// Returns whether it should be forwarded at all: if the command has already come as far
+    /// as it may, it is dropped (and counted) instead. Commands without a hop limit always go on.
+    pub(crate) fn take_hop(&self, hop_limit: &mut Option<u64>) -> bool {
+        match hop_limit {
+            Some(hops) if *hops <= 1 => {
+                trace!("Hop limit reached; not forwarding");
+                self.shared.hop_limit_drops.inc();
+                false
+            }
+            Some(hops) => {
+                *hops -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
     /// Does this `PeerMap` contain a peer with the given name?
     pub(crate) fn contains_peer(&self, name: String) -> bool {
         let state = self.shared.state.lock().unwrap();
@@ -104,6 +148,49 @@ impl PeerMap {
         state.entries.remove(&peer_name)
     }
 
+    /// Makes [PeerMap::gossip()] send to `fanout` random peers, or if `None`, every peer.
+    pub(crate) fn set_gossip_fanout(&self, fanout: Option<usize>) {
+        self.shared.state.lock().unwrap().gossip_fanout = fanout;
+    }
+
+    /// Picks one of our peers at random.
+    pub(crate) fn random_peer(&self) -> Option<String> {
+        let state = self.shared.state.lock().unwrap();
+        state.entries.keys().choose(&mut rand::thread_rng()).cloned()
+    }
+
+    /// Like [PeerMap::broadcast()], but if we are gossiping (see [PeerMap::set_gossip_fanout()]),
+    /// the message only goes to a few of our peers, picked at random.
+    #[instrument(level = "trace")]
+    pub(crate) fn gossip(&self, sender: String, frame: Bing2BingFrame) {
+        let state = self.shared.state.lock().unwrap();
+
+        let fanout = match state.gossip_fanout {
+            Some(fanout) => fanout,
+            None => {
+                drop(state);
+                return self.broadcast(sender, frame);
+            }
+        };
+
+        let peers = state
+            .entries
+            .iter()
+            .filter(|(peer_name, _)| **peer_name != sender)
+            .choose_multiple(&mut rand::thread_rng(), fanout);
+
+        for (peer_name, peer_tx) in peers {
+            trace!("Gossiping frame \"{:?}\" to {:?}", frame, peer_name);
+
+            if let Err(err) = peer_tx.send(PeerControlMessage::Frame(frame.clone())) {
+                error!(
+                    "There was an error when trying to gossip to peer {:?}: {:?}",
+                    peer_name, err
+                );
+            }
+        }
+    }
+
     /// Broadcasts a message to every one of our peers (out links)
     /// Idk if this needs to be async?
     #[instrument(level = "trace")]
@@ -133,21 +220,26 @@ impl PeerMap {
         }
     }
 
+	/// Sends `frame` to just `recipient`.
+	/// Errors if we don't have a connection to `recipient` (anymore).
 	#[instrument(level = "trace")]
-	pub fn send_to_peer(&self, sender: String, recipient: String, frame: Bing2BingFrame) {
-		let mut state = self.shared.state.lock().unwrap();
-
-		for (peer_addr, peer_tx) in state.entries.iter_mut() {
-			if *peer_addr == recipient {
-				let frame = PeerControlMessage::Frame(frame.clone());
-				if let Err(err) = peer_tx.send(frame) {
-					error!(
-						"There was an error when trying to broadcast to peer {:?}: {:?}",
-						*peer_addr, err
-					);
-				};
-			}
+	pub fn send_to_peer(&self, sender: String, recipient: String, frame: Bing2BingFrame) -> Result<(), Bing2BingError> {
+		let state = self.shared.state.lock().unwrap();
+
+		let peer_tx = match state.entries.get(&recipient) {
+			Some(peer_tx) => peer_tx,
+			None => return Err(format!("not connected to {}", recipient).into()),
+		};
+
+		let frame = PeerControlMessage::Frame(frame);
+		if let Err(err) = peer_tx.send(frame) {
+			error!(
+				"There was an error when trying to send to peer {:?}: {:?}",
+				recipient, err
+			);
+			return Err(format!("connection to {} closed", recipient).into());
 		}
+
+		Ok(())
 	}
 }
-
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use tokio::time::Instant;
+
+use crate::util::DropCounter;
+
+/// A limit on how fast frames may come in: `rate` a second on average, with bursts of up to
+/// `burst` at once (a token bucket).
+///
+/// As a string, this is `RATE/BURST` (e.g., `20/50`), or just `RATE`, in which case bursts
+/// can be twice as big.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    rate: f64,
+    burst: f64,
+}
+
+/// A sensible limit on frames from any one source (see [Server::set_source_rate_limit()](crate::Server::set_source_rate_limit())).
+pub const DEFAULT_SOURCE_RATE_LIMIT: RateLimit = RateLimit {
+    rate: 20.0,
+    burst: 50.0,
+};
+
+/// A sensible limit on frames over any one incoming connection
+/// (see [Server::set_link_rate_limit()](crate::Server::set_link_rate_limit())).
+/// This is a lot higher than [DEFAULT_SOURCE_RATE_LIMIT], since every flooded command in the
+/// network comes in over our links.
+pub const DEFAULT_LINK_RATE_LIMIT: RateLimit = RateLimit {
+    rate: 200.0,
+    burst: 500.0,
+};
+
+impl RateLimit {
+    /// `rate` frames a second, in bursts of up to `burst`.
+    pub fn new(rate: f64, burst: f64) -> Result<Self, String> {
+        if !(rate > 0.0 && burst >= 1.0) {
+            return Err(format!(
+                "a rate limit needs a positive rate and a burst of at least 1 (got {}/{})",
+                rate, burst
+            ));
+        }
+
+        Ok(Self { rate, burst })
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn burst(&self) -> f64 {
+        self.burst
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |n: &str| {
+            n.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("bad rate limit {:?} (expected RATE or RATE/BURST)", s))
+        };
+
+        match s.split_once('/') {
+            Some((rate, burst)) => RateLimit::new(number(rate)?, number(burst)?),
+            None => {
+                let rate = number(s)?;
+                RateLimit::new(rate, (rate * 2.0).max(1.0))
+            }
+        }
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.rate, self.burst)
+    }
+}
+
+/// What a [TokenBucket] let happen to a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Admission {
+    Allowed,
+    Dropped,
+    /// Dropped, and the frame before this one wasn't (so this is news).
+    StartedDropping,
+}
+
+/// Keeps track of how much of a [RateLimit] has been used up.
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+    dropping: bool,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst,
+            refilled_at: Instant::now(),
+            dropping: false,
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst);
+        self.refilled_at = now;
+    }
+
+    /// Takes a token for a frame, if there is one.
+    pub(crate) fn admit(&mut self) -> Admission {
+        self.refill();
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.dropping = false;
+            Admission::Allowed
+        } else if self.dropping {
+            Admission::Dropped
+        } else {
+            self.dropping = true;
+            Admission::StartedDropping
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.limit.burst
+    }
+}
+
+/// Once we are keeping track of this many sources, we forget about the ones whose buckets
+/// have filled back up (which is the same as starting over with a new bucket).
+const MAX_SOURCE_BUCKETS: usize = 1024;
+
+/// The rate limits a [Server](crate::Server) puts on the frames coming in to it: one
+/// [TokenBucket] per source (shared by every connection), and one per incoming connection.
+/// Frames that are over either limit are dropped and counted.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    per_source: Option<RateLimit>,
+    per_link: Option<RateLimit>,
+    sources: Arc<Mutex<HashMap<String, TokenBucket>>>,
+    drops: DropCounter,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(per_source: Option<RateLimit>, per_link: Option<RateLimit>, drops: DropCounter) -> Self {
+        Self {
+            per_source,
+            per_link,
+            sources: Arc::new(Mutex::new(HashMap::new())),
+            drops,
+        }
+    }
+
+    /// A bucket for a new incoming connection (`None` if connections aren't limited).
+    pub(crate) fn link_bucket(&self) -> Option<TokenBucket> {
+        self.per_link.map(TokenBucket::new)
+    }
+
+    /// Takes a token from a connection's bucket (see [RateLimiter::link_bucket()]).
+    pub(crate) fn admit_from_link(&self, bucket: &mut Option<TokenBucket>) -> Admission {
+        let admission = match bucket {
+            Some(bucket) => bucket.admit(),
+            None => Admission::Allowed,
+        };
+
+        self.count(admission)
+    }
+
+    /// Takes a token from `source`'s bucket.
+    pub(crate) fn admit_from_source(&self, source: &str) -> Admission {
+        let limit = match self.per_source {
+            Some(limit) => limit,
+            None => return Admission::Allowed,
+        };
+
+        let mut sources = self.sources.lock().unwrap();
+
+        if sources.len() >= MAX_SOURCE_BUCKETS && !sources.contains_key(source) {
+            sources.retain(|_, bucket| {
+                bucket.refill();
+                !bucket.is_full()
+            });
+        }
+
+        let admission = sources
+            .entry(source.to_string())
+            .or_insert_with(|| TokenBucket::new(limit))
+            .admit();
+
+        drop(sources);
+
+        self.count(admission)
+    }
+
+    fn count(&self, admission: Admission) -> Admission {
+        if admission != Admission::Allowed {
+            self.drops.inc();
+        }
+
+        admission
+    }
+}
@@ -0,0 +1,782 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
+use tracing::{debug, trace};
+
+use crate::{
+    peer::PeerData, peer_map::PeerMap, util::TtlMap, ClientEvent, Disjointness, Route, Topology,
+    STALE_AFTER,
+};
+
+/// Why there is no route to a peer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoutingError {
+    /// We haven't heard of this peer (or haven't heard from it in so long that we forgot about it).
+    UnknownDestination(String),
+    /// We know about this peer, but there is no path to it from here (e.g., the network is partitioned).
+    Unreachable(String),
+    /// That's us; there is nowhere to route to.
+    Ourselves(String),
+}
+
+impl fmt::Display for RoutingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoutingError::UnknownDestination(peer) => write!(f, "no such peer {:?}", peer),
+            RoutingError::Unreachable(peer) => write!(f, "no path to {:?}", peer),
+            RoutingError::Ourselves(peer) => write!(f, "{:?} is us", peer),
+        }
+    }
+}
+
+impl std::error::Error for RoutingError {}
+
+/// How peers figure out their routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoutingMode {
+    /// Every peer floods an [Announce](crate::cmd::Announce) of its neighborhood every 5 seconds,
+    /// and works out its routes from the whole graph (Dijkstra's).
+    #[default]
+    LinkState,
+    /// Peers only send their current routes (a [RouteVector](crate::cmd::RouteVector)) to their
+    /// direct neighbors, who take the best of what they are offered (Bellman-Ford, as in RIP).
+    /// Routes are by hop count, and we only know the next hop (not the whole path).
+    DistanceVector,
+}
+
+impl FromStr for RoutingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "link-state" => Ok(RoutingMode::LinkState),
+            "distance-vector" => Ok(RoutingMode::DistanceVector),
+            _ => Err(format!(
+                "unknown routing mode {:?} (expected link-state or distance-vector)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RoutingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoutingMode::LinkState => write!(f, "link-state"),
+            RoutingMode::DistanceVector => write!(f, "distance-vector"),
+        }
+    }
+}
+
+/// In [RoutingMode::DistanceVector], a route that costs this many hops (or more) doesn't exist.
+/// This bounds how long "counting to infinity" can take when a loop forms.
+pub const INFINITE_HOPS: u32 = 16;
+
+/// The best [Route] from us to every peer we can reach, kept up to date as the network changes.
+///
+/// Routes are only recomputed when the adjacency list actually changes (i.e., an
+/// [Announce](crate::cmd::Announce) told us something new, a peer went stale, or a peer's
+/// entry expired), so looking one up is just a hash map lookup.
+///
+/// In [RoutingMode::DistanceVector] there is no adjacency list to speak of; routes are rebuilt
+/// from the vectors our neighbors send us instead.
+#[derive(Debug, Clone)]
+pub struct RoutingTable {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    name: String,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    routes: HashMap<String, Route>,
+    /// Every peer we know about, reachable or not.
+    known: HashSet<String>,
+    /// The peers we haven't heard an announce from in a while (see [Topology::routes_from()]).
+    stale: HashSet<String>,
+    /// What the routes were computed from (empty in [RoutingMode::DistanceVector]).
+    topology: Topology,
+    /// When the next peer that isn't stale yet will be, if nothing is heard from it.
+    next_stale: Option<Instant>,
+}
+
+impl RoutingTable {
+    /// Creates an empty `RoutingTable` for routing from `name`.
+    pub(crate) fn new(name: &str) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                name: name.to_string(),
+                state: Mutex::new(State::default()),
+            }),
+        }
+    }
+
+    /// The route to `destination`.
+    pub fn route(&self, destination: &str) -> Result<Route, RoutingError> {
+        let state = self.shared.state.lock().unwrap();
+
+        if let Some(route) = state.routes.get(destination) {
+            return Ok(route.clone());
+        }
+
+        if destination == self.shared.name {
+            Err(RoutingError::Ourselves(destination.to_string()))
+        } else if state.known.contains(destination) {
+            Err(RoutingError::Unreachable(destination.to_string()))
+        } else {
+            Err(RoutingError::UnknownDestination(destination.to_string()))
+        }
+    }
+
+    /// Who we should send to in order to reach `destination`.
+    pub fn next_hop(&self, destination: &str) -> Result<String, RoutingError> {
+        self.route(destination).map(|route| route.next_hop)
+    }
+
+    /// Up to `k` routes to `destination` that don't share any links (or peers, depending on
+    /// `disjointness`), cheapest first (see [Topology::disjoint_routes_from()]).
+    /// Sending a copy of a message along each of them means it gets through as long as one does.
+    ///
+    /// In [RoutingMode::DistanceVector] we don't know the graph, so this is just [RoutingTable::route()].
+    pub fn disjoint_routes(
+        &self,
+        destination: &str,
+        k: usize,
+        disjointness: Disjointness,
+    ) -> Result<Vec<Route>, RoutingError> {
+        let route = self.route(destination)?;
+
+        let state = self.shared.state.lock().unwrap();
+        let routes = state
+            .topology
+            .disjoint_routes_from(&self.shared.name, destination, k, disjointness);
+
+        if routes.is_empty() {
+            Ok(vec![route])
+        } else {
+            Ok(routes)
+        }
+    }
+
+    /// Every route we have, sorted by destination.
+    pub fn routes(&self) -> Vec<Route> {
+        let state = self.shared.state.lock().unwrap();
+
+        let mut routes = state.routes.values().cloned().collect::<Vec<_>>();
+        routes.sort_by(|a, b| a.destination.cmp(&b.destination));
+
+        routes
+    }
+
+    /// Rebuilds every route from what is in `adjacency_list` right now.
+    /// Returns whether the set of stale peers changed since the last time.
+    pub(crate) fn recompute(&self, adjacency_list: &TtlMap<PeerData>) -> bool {
+        let topology = Topology::from_adjacency_list(adjacency_list);
+
+        let mut known = HashSet::new();
+        let mut stale = HashSet::new();
+        for node in &topology.nodes {
+            known.insert(node.name.clone());
+            known.extend(node.links.iter().map(|link| link.peer.clone()));
+            if node.stale {
+                stale.insert(node.name.clone());
+            }
+        }
+
+        let now = Instant::now();
+        let next_stale = topology
+            .nodes
+            .iter()
+            .filter(|node| !node.stale)
+            .map(|node| now + STALE_AFTER.saturating_sub(Duration::from_secs_f64(node.age_secs)))
+            .min();
+
+        let routes = topology
+            .routes_from(&self.shared.name)
+            .into_iter()
+            .map(|route| (route.destination.clone(), route))
+            .collect::<HashMap<_, _>>();
+
+        trace!("Recomputed {} routes for {} known peers", routes.len(), known.len());
+
+        let mut state = self.shared.state.lock().unwrap();
+        let stale_changed = state.stale != stale;
+        *state = State {
+            routes,
+            known,
+            stale,
+            topology,
+            next_stale,
+        };
+
+        stale_changed
+    }
+
+    /// Rebuilds every route from the vectors that our (current) `neighbors` sent us
+    /// (see [RoutingMode::DistanceVector]). Returns whether any route changed.
+    pub(crate) fn recompute_from_vectors(
+        &self,
+        neighbors: &[String],
+        vectors: &TtlMap<Vec<(String, u32)>>,
+    ) -> bool {
+        let name = &self.shared.name;
+
+        let mut neighbors = neighbors.to_vec();
+        // so that ties always go the same way
+        neighbors.sort();
+
+        let mut known = HashSet::new();
+        let mut routes = HashMap::new();
+
+        for neighbor in &neighbors {
+            known.insert(neighbor.clone());
+            routes.insert(
+                neighbor.clone(),
+                Route {
+                    destination: neighbor.clone(),
+                    next_hop: neighbor.clone(),
+                    cost: 1,
+                    path: vec![],
+                },
+            );
+        }
+
+        // a vector from a peer we can't send to (anymore) is no use to us
+        for neighbor in &neighbors {
+            let vector = match vectors.get(neighbor) {
+                Some(vector) => vector,
+                None => continue,
+            };
+
+            for (destination, cost) in vector {
+                if &destination == name {
+                    continue;
+                }
+
+                known.insert(destination.clone());
+
+                let cost = cost.saturating_add(1);
+                if cost >= INFINITE_HOPS {
+                    continue;
+                }
+
+                if routes.get(&destination).is_none_or(|route: &Route| cost < route.cost) {
+                    routes.insert(
+                        destination.clone(),
+                        Route {
+                            destination,
+                            next_hop: neighbor.clone(),
+                            cost,
+                            path: vec![],
+                        },
+                    );
+                }
+            }
+        }
+
+        trace!("Recomputed {} routes from {} neighbors' vectors", routes.len(), neighbors.len());
+
+        let mut state = self.shared.state.lock().unwrap();
+        let changed = state.routes != routes;
+        *state = State {
+            routes,
+            known,
+            stale: HashSet::new(),
+            topology: Topology::default(),
+            next_stale: None,
+        };
+
+        changed
+    }
+
+    /// The routes we tell `neighbor` about (see [RoutingMode::DistanceVector]).
+    /// Routes that go through `neighbor` are advertised back to it as [INFINITE_HOPS]
+    /// (split horizon with poisoned reverse), so it never thinks it can reach
+    /// something through us that we can only reach through it.
+    pub(crate) fn vector_for(&self, neighbor: &str) -> Vec<(String, u32)> {
+        self.routes()
+            .into_iter()
+            .map(|route| {
+                let cost = if route.next_hop == neighbor {
+                    INFINITE_HOPS
+                } else {
+                    route.cost
+                };
+
+                (route.destination, cost)
+            })
+            .collect()
+    }
+
+    fn next_stale(&self) -> Option<Instant> {
+        self.shared.state.lock().unwrap().next_stale
+    }
+
+    /// Keeps this table up to date with `adjacency_list` in the background, letting our
+    /// [Client](crate::Client) know (via `peer_map`) whenever the topology changes.
+    /// Besides changes and expirations, the only thing that needs a recompute is a peer going
+    /// stale, so we sleep until the next one would.
+    pub(crate) fn watch(&self, adjacency_list: &TtlMap<PeerData>, peer_map: &PeerMap) {
+        let routing_table = self.clone();
+        let adjacency_list = adjacency_list.clone();
+        let peer_map = peer_map.clone();
+
+        let mut changes = adjacency_list.changes();
+        let mut expirations = adjacency_list.expirations();
+
+        routing_table.recompute(&adjacency_list);
+
+        tokio::spawn(async move {
+            loop {
+                let next_stale = routing_table.next_stale();
+
+                let update = tokio::select! {
+                    update = changes.recv() => update,
+                    update = expirations.recv() => {
+                        if let Ok(peer_name) = &update {
+                            debug!("Haven't heard from {} in a while; forgetting about it", peer_name);
+                        }
+                        update
+                    }
+                    _ = sleep_until(next_stale) => {
+                        // (a stale peer coming back shows up in `changes`; see Announce::apply())
+                        if routing_table.recompute(&adjacency_list) {
+                            debug!("Peers went stale (or came back); recomputed routes");
+                            peer_map.notify(ClientEvent::TopologyChanged);
+                        }
+                        continue;
+                    }
+                };
+
+                match update {
+                    // if we missed some updates it doesn't matter, since we recompute from scratch
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+
+                routing_table.recompute(&adjacency_list);
+                peer_map.notify(ClientEvent::TopologyChanged);
+            }
+        });
+    }
+}
+
+/// Sleeps until `when`, or forever if there is no `when`.
+async fn sleep_until(when: Option<Instant>) {
+    match when {
+        Some(when) => tokio::time::sleep_until(when).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Who is linked to whom (both ways).
+    fn neighbors_in(links: &[(&str, &str)], name: &str) -> Vec<String> {
+        links
+            .iter()
+            .filter_map(|&(a, b)| {
+                if name == a {
+                    Some(b.to_string())
+                } else if name == b {
+                    Some(a.to_string())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// How a simulated run of one of the routing modes went. A round is however long it takes
+    /// a frame to cross a link.
+    #[derive(Debug)]
+    struct Convergence {
+        /// Rounds until nobody's next hops changed anymore.
+        rounds: usize,
+        /// Frames sent (`RouteVector`s or `Announce`s) until everything was quiet.
+        frames: usize,
+    }
+
+    /// Everyone's next hop to everyone they have a route to.
+    fn next_hops(tables: &HashMap<String, RoutingTable>) -> Vec<(String, String, String)> {
+        let mut hops = tables
+            .iter()
+            .flat_map(|(name, table)| {
+                table
+                    .routes()
+                    .into_iter()
+                    .map(move |route| (name.clone(), route.destination, route.next_hop))
+            })
+            .collect::<Vec<_>>();
+        hops.sort();
+        hops
+    }
+
+    /// Runs distance vector routing between `names` over `links` until no route changes
+    /// (or `max_rounds` have gone by), starting from whatever `tables` already hold.
+    /// Everyone sends their vector in the first round (as they do every 5 seconds), and after
+    /// that only those whose routes just changed do.
+    fn run_distance_vector(
+        tables: &HashMap<String, RoutingTable>,
+        links: &[(&str, &str)],
+        max_rounds: usize,
+    ) -> Convergence {
+        let mut convergence = Convergence { rounds: 0, frames: 0 };
+        let mut hops = next_hops(tables);
+        let mut senders = tables.keys().cloned().collect::<HashSet<_>>();
+
+        for round in 1..=max_rounds {
+            if senders.is_empty() {
+                break;
+            }
+
+            convergence.frames += senders
+                .iter()
+                .map(|name| neighbors_in(links, name).len())
+                .sum::<usize>();
+
+            // those who didn't send this round would have sent the same vector as last time
+            let vectors = tables
+                .keys()
+                .map(|name| {
+                    let received = TtlMap::new();
+                    for neighbor in neighbors_in(links, name) {
+                        received.set(neighbor.clone(), tables[&neighbor].vector_for(name), None);
+                    }
+                    (name.clone(), received)
+                })
+                .collect::<Vec<_>>();
+
+            senders = vectors
+                .into_iter()
+                .filter(|(name, received)| {
+                    tables[name].recompute_from_vectors(&neighbors_in(links, name), received)
+                })
+                .map(|(name, _)| name)
+                .collect();
+
+            let now = next_hops(tables);
+            if now != hops {
+                convergence.rounds = round;
+                hops = now;
+            }
+        }
+
+        convergence
+    }
+
+    /// Every link costs the same in link-state simulations, so costs are hop counts times this.
+    const LATENCY: u32 = 10;
+
+    /// Has everyone flood one `Announce` (numbered `sequence_number`) over `links`, as they do
+    /// every 5 seconds, and updates everyone's `adjacency_lists` and `tables` as they arrive.
+    /// Like [Announce](crate::cmd::Announce), each peer passes on an announce it hasn't seen yet
+    /// to all its neighbors except the one that sent it in the first place.
+    fn run_link_state(
+        adjacency_lists: &HashMap<String, TtlMap<PeerData>>,
+        tables: &HashMap<String, RoutingTable>,
+        links: &[(&str, &str)],
+        sequence_number: u64,
+    ) -> Convergence {
+        let announce = |name: &str| {
+            let peers = neighbors_in(links, name)
+                .into_iter()
+                .map(|peer| (peer, LATENCY))
+                .collect();
+            PeerData::new("", 0.0, 0.0, peers, sequence_number)
+        };
+        let recompute = || {
+            for (name, table) in tables {
+                table.recompute(&adjacency_lists[name]);
+            }
+        };
+
+        // (to, source) of every announce on its way somewhere
+        let mut in_flight = vec![];
+        for name in tables.keys() {
+            adjacency_lists[name].set(name.clone(), announce(name), None);
+            for neighbor in neighbors_in(links, name) {
+                in_flight.push((neighbor, name.clone()));
+            }
+        }
+        recompute();
+
+        let mut convergence = Convergence { rounds: 0, frames: 0 };
+        let mut hops = next_hops(tables);
+        let mut round = 0;
+
+        while !in_flight.is_empty() {
+            round += 1;
+            convergence.frames += in_flight.len();
+
+            let mut forwarded = vec![];
+            for (to, source) in in_flight {
+                let adjacency_list = &adjacency_lists[&to];
+                if adjacency_list
+                    .get(&source)
+                    .is_some_and(|known| known.sequence_number() >= sequence_number)
+                {
+                    continue;
+                }
+
+                adjacency_list.set(source.clone(), announce(&source), None);
+                for neighbor in neighbors_in(links, &to) {
+                    if neighbor != source {
+                        forwarded.push((neighbor, source.clone()));
+                    }
+                }
+            }
+            in_flight = forwarded;
+
+            recompute();
+            let now = next_hops(tables);
+            if now != hops {
+                convergence.rounds = round;
+                hops = now;
+            }
+        }
+
+        convergence
+    }
+
+    fn tables_for(names: &[&str]) -> HashMap<String, RoutingTable> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), RoutingTable::new(name)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn poisons_routes_back_through_the_neighbor_they_go_through() {
+        let table = RoutingTable::new("a");
+        let vectors = TtlMap::new();
+        vectors.set("b".to_string(), vec![("c".to_string(), 1)], None);
+
+        table.recompute_from_vectors(&["b".to_string(), "d".to_string()], &vectors);
+
+        let mut to_b = table.vector_for("b");
+        to_b.sort();
+        assert_eq!(
+            to_b,
+            vec![
+                ("b".to_string(), INFINITE_HOPS),
+                ("c".to_string(), INFINITE_HOPS),
+                ("d".to_string(), 1)
+            ]
+        );
+
+        let mut to_d = table.vector_for("d");
+        to_d.sort();
+        assert_eq!(
+            to_d,
+            vec![
+                ("b".to_string(), 1),
+                ("c".to_string(), 2),
+                ("d".to_string(), INFINITE_HOPS)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn routes_at_infinite_hops_are_dropped() {
+        let table = RoutingTable::new("a");
+        let vectors = TtlMap::new();
+        vectors.set(
+            "b".to_string(),
+            vec![
+                ("far".to_string(), INFINITE_HOPS - 2),
+                ("too far".to_string(), INFINITE_HOPS - 1),
+                ("poisoned".to_string(), INFINITE_HOPS),
+            ],
+            None,
+        );
+
+        table.recompute_from_vectors(&["b".to_string()], &vectors);
+
+        assert_eq!(table.route("far").unwrap().cost, INFINITE_HOPS - 1);
+        assert_eq!(
+            table.route("too far"),
+            Err(RoutingError::Unreachable("too far".to_string()))
+        );
+        assert_eq!(
+            table.route("poisoned"),
+            Err(RoutingError::Unreachable("poisoned".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn counting_to_infinity_stops() {
+        // a triangle with d hanging off of c; once d goes away, a and b can keep telling each
+        // other about it (poisoned reverse doesn't help in a loop of three) until it costs
+        // INFINITE_HOPS
+        let tables = tables_for(&["a", "b", "c", "d"]);
+        let links = [("a", "b"), ("b", "c"), ("c", "a"), ("c", "d")];
+
+        run_distance_vector(&tables, &links, 10);
+        assert_eq!(tables["a"].route("d").unwrap().cost, 2);
+
+        let links = [("a", "b"), ("b", "c"), ("c", "a")];
+        let rounds = run_distance_vector(&tables, &links, 100).rounds;
+
+        assert!(rounds < 100, "routes never settled");
+        for name in ["a", "b", "c"] {
+            assert!(tables[name].route("d").is_err(), "{} still has a route to d", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn distance_vector_agrees_with_link_state() {
+        //   a - b - c
+        //   |       |
+        //   e ----- d
+        let names = ["a", "b", "c", "d", "e"];
+        let links = [("a", "b"), ("b", "c"), ("c", "d"), ("d", "e"), ("e", "a")];
+
+        // every link costs the same, so link-state costs are hop counts times this
+        let latency = 10;
+
+        let adjacency_list = TtlMap::new();
+        for name in names {
+            let peers = neighbors_in(&links, name)
+                .into_iter()
+                .map(|peer| (peer, latency))
+                .collect();
+            adjacency_list.set(name.to_string(), PeerData::new("", 0.0, 0.0, peers, 1), None);
+        }
+
+        let tables = tables_for(&names);
+        run_distance_vector(&tables, &links, 10);
+
+        for name in names {
+            let link_state = RoutingTable::new(name);
+            link_state.recompute(&adjacency_list);
+
+            let expected = link_state
+                .routes()
+                .into_iter()
+                .map(|route| (route.destination, route.next_hop, route.cost / latency))
+                .collect::<Vec<_>>();
+            let got = tables[name]
+                .routes()
+                .into_iter()
+                .map(|route| (route.destination, route.next_hop, route.cost))
+                .collect::<Vec<_>>();
+
+            assert_eq!(got, expected, "routes from {}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn compares_convergence_and_traffic_of_both_modes() {
+        let names = ["a", "b", "c", "d", "e"];
+        let ring = [("a", "b"), ("b", "c"), ("c", "d"), ("d", "e"), ("e", "a")];
+        // a triangle with d hanging off of c (and e off of d)
+        let triangle_and_tail = [("a", "b"), ("b", "c"), ("c", "a"), ("c", "d"), ("d", "e")];
+        // ... with d and e cut off
+        let partitioned = [("a", "b"), ("b", "c"), ("c", "a"), ("d", "e")];
+
+        // each runs from wherever the one before it left off
+        let scenarios: [(&str, &[(&str, &str)]); 4] = [
+            ("ring", &ring),
+            ("triangle and tail", &triangle_and_tail),
+            ("partition", &partitioned),
+            ("heal", &triangle_and_tail),
+        ];
+
+        let distance_vector = tables_for(&names);
+        let link_state = tables_for(&names);
+        let adjacency_lists = names
+            .iter()
+            .map(|name| (name.to_string(), TtlMap::new()))
+            .collect::<HashMap<_, _>>();
+
+        for (sequence_number, (scenario, links)) in (1..).zip(scenarios) {
+            let dv = run_distance_vector(&distance_vector, links, 100);
+            let ls = run_link_state(&adjacency_lists, &link_state, links, sequence_number);
+
+            println!(
+                "{:>17}: distance vector took {} rounds and {} frames, link state took {} rounds and {} frames",
+                scenario, dv.rounds, dv.frames, ls.rounds, ls.frames
+            );
+
+            assert_eq!(next_hops(&distance_vector), next_hops(&link_state), "{}", scenario);
+
+            // link state hears about a change as fast as it can travel; distance vector needs
+            // a round per hop (or many more, counting to infinity)
+            assert!(ls.rounds <= dv.rounds, "{}: {:?} vs {:?}", scenario, ls, dv);
+            assert!(dv.rounds < 100, "{}: {:?}", scenario, dv);
+
+            // distance vector only talks to neighbors, unless it is counting to infinity
+            if scenario == "partition" {
+                assert!(dv.frames > ls.frames, "{}: {:?} vs {:?}", scenario, dv, ls);
+            } else {
+                assert!(dv.frames < ls.frames, "{}: {:?} vs {:?}", scenario, dv, ls);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn disjoint_routes_come_from_the_topology() {
+        //       a ----- d
+        //     /   \       \
+        //   s       b ----- t
+        //     \   /
+        //       c
+        // s - a - b - t is the shortest way, but only s - a - d - t and s - c - b - t are disjoint
+        let links = [("s", "a"), ("a", "b"), ("b", "t"), ("a", "d"), ("d", "t"), ("s", "c"), ("c", "b")];
+        let latency = |a: &str, b: &str| {
+            if [a, b].iter().any(|peer| ["c", "d"].contains(peer)) {
+                30
+            } else {
+                10
+            }
+        };
+
+        let adjacency_list = TtlMap::new();
+        for name in ["s", "a", "b", "c", "d", "t", "x"] {
+            let peers = neighbors_in(&links, name)
+                .into_iter()
+                .map(|peer| {
+                    let latency = latency(name, &peer);
+                    (peer, latency)
+                })
+                .collect();
+            adjacency_list.set(name.to_string(), PeerData::new("", 0.0, 0.0, peers, 1), None);
+        }
+
+        let table = RoutingTable::new("s");
+        table.recompute(&adjacency_list);
+
+        for disjointness in [Disjointness::Link, Disjointness::Node] {
+            let routes = table.disjoint_routes("t", 3, disjointness).unwrap();
+            let paths = routes.iter().map(|route| route.path.clone()).collect::<Vec<_>>();
+            assert_eq!(paths, vec![vec!["a", "d", "t"], vec!["c", "b", "t"]], "{:?}", disjointness);
+
+            // x announced itself, but nobody links to it
+            assert_eq!(
+                table.disjoint_routes("x", 3, disjointness),
+                Err(RoutingError::Unreachable("x".to_string()))
+            );
+        }
+
+        // without the graph, all we have is the one route
+        let table = RoutingTable::new("s");
+        let vectors = TtlMap::new();
+        vectors.set("a".to_string(), vec![("t".to_string(), 2)], None);
+        table.recompute_from_vectors(&["a".to_string()], &vectors);
+
+        let routes = table.disjoint_routes("t", 3, Disjointness::Node).unwrap();
+        assert_eq!(routes, vec![table.route("t").unwrap()]);
+    }
+}
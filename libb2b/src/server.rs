@@ -1,25 +1,29 @@
 use tokio::sync::mpsc;
 
 use std::net::SocketAddr;
+use std::path::Path;
 use tokio::net::TcpStream;
 
 use tokio::net::TcpListener;
 
-use std::time::{Duration, Instant};
+use std::time::Duration;
+
+use bytes::Bytes;
 
 use crate::{
-    cmd::{Announce, Say, Whisper},
-    peer::PeerData,
-    util::{ConnectionCounter, SequenceNumberGenerator},
-    ClientServerMessage, ClientTxChannel, Peer, ServerRxChannel,
+    cmd::{Announce, Deliver, Digest, RouteVector, Say, Whisper, RECENT_SAYS_WINDOW, VECTOR_TIMEOUT},
+    peer::{Location, PeerData, UNMEASURED_LATENCY},
+    rate_limit::Admission,
+    util::{
+        bind_listener, clock_epoch, next_session_epoch, parse_socket_addr, ConnectionCounter, DropCounter,
+        SequenceNumberGenerator,
+    },
+    ClientEvent, ClientServerMessage, Disjointness, EventTxChannel, IgnoreList, Peer, RateLimit, RateLimiter,
+    RoutingMode, RoutingTable, SequenceWindows, ServerRxChannel, Topology,
 };
 
 use tracing::{debug, instrument, trace};
 
-use std::collections::HashMap;
-use priority_queue::PriorityQueue;
-use std::cmp::Reverse;
-
 use crate::cmd::Register;
 use crate::Bing2BingError;
 use crate::{
@@ -29,6 +33,17 @@ use crate::{
 };
 use crate::{util::TtlMap, Bing2BingCommand};
 
+/// How many paths (that don't go through the same peers) important whispers and delivers
+/// are sent over.
+const IMPORTANT_PATHS: usize = 3;
+
+/// A sensible hop limit for [Server::set_hop_limit()]. By default there is none, since peers
+/// from before hop limits existed can't read `Say`s and `Announce`s that carry one.
+pub const DEFAULT_HOP_LIMIT: u64 = 16;
+
+/// How often a gossiping `Server` sends a [Digest] to one of its peers.
+const DIGEST_INTERVAL: Duration = Duration::from_secs(5);
+
 /// The "server" side of the P2P chat application.
 /// A server is primarily focused around network related activity and manages most everything related to the protocol itself.
 /// This includes receiving commands over the network, processing them, and sending commands out to the network.
@@ -38,93 +53,158 @@ pub struct Server {
     listener: TcpListener,
     sequence_numbers: SequenceNumberGenerator,
     name: String,
-    ip_address: String,
-    port: u64,
+    addr: SocketAddr,
+    location: Location,
+    adjacency_list: TtlMap<PeerData>,
+    /// The sequence number of the newest [Announce] from each source (see [Announce::apply()]).
+    latest_announces: TtlMap<u64>,
+    routing_mode: RoutingMode,
+    routing_table: RoutingTable,
+    /// The routes each of our neighbors last sent us (only used in [RoutingMode::DistanceVector]).
+    neighbor_vectors: TtlMap<Vec<(String, u32)>>,
     num_incoming_conns: ConnectionCounter,
-    client_tx: ClientTxChannel,
+    hop_limit: Option<u64>,
+    hop_limit_drops: DropCounter,
+    source_rate_limit: Option<RateLimit>,
+    link_rate_limit: Option<RateLimit>,
+    rate_limit_drops: DropCounter,
+    /// Shared with our [Client](crate::Client) (see [Client::set_strict_ignore()](crate::Client::set_strict_ignore())).
+    ignore_list: IgnoreList,
+    /// How many peers we forward `Say`s to (see [Server::set_gossip_fanout()]).
+    gossip_fanout: Option<usize>,
+    /// The `Say`s we have seen lately, by id (for answering [Digest]s).
+    recent_says: TtlMap<Say>,
+    client_tx: EventTxChannel,
     rx: ServerRxChannel,
 	//waiting_for_ping: bool,
 }
 
 impl Server {
+    /// Creates a new `Server` listening on `addr`.
+    /// Both IPv4 and IPv6 addresses work; binding to `::` will also accept IPv4 connections.
     pub async fn new(
         name: &str,
-        bind_address: &str,
-        port: &str,
-        client_tx: ClientTxChannel,
+        addr: SocketAddr,
+        client_tx: EventTxChannel,
         rx: ServerRxChannel,
     ) -> Result<Self, Bing2BingError> {
         Ok(Server {
-            listener: TcpListener::bind(format!("{}:{}", bind_address, port)).await?,
-            sequence_numbers: SequenceNumberGenerator::new(0),
+            listener: bind_listener(addr)?,
+            sequence_numbers: SequenceNumberGenerator::for_session(clock_epoch()),
             name: name.to_string(),
-            ip_address: bind_address.to_string(),
-            port: port.to_string().parse().unwrap(),
+            addr,
+            location: Location::default(),
+            adjacency_list: TtlMap::new(),
+            latest_announces: TtlMap::new(),
+            routing_mode: RoutingMode::default(),
+            routing_table: RoutingTable::new(name),
+            neighbor_vectors: TtlMap::new(),
             num_incoming_conns: ConnectionCounter::new(0),
+            hop_limit: None,
+            hop_limit_drops: DropCounter::new(),
+            source_rate_limit: None,
+            link_rate_limit: None,
+            rate_limit_drops: DropCounter::new(),
+            ignore_list: IgnoreList::new(),
+            gossip_fanout: None,
+            recent_says: TtlMap::new(),
             client_tx,
             rx,
 			//waiting_for_ping: false,
         })
     }
 
-	pub fn shortest_path(
-		peer_map: &PeerMap,
-		adjacency_list: TtlMap<PeerData>,
-		source: String,
-		destination: String,
-	) -> Vec<String> {
-		let mut vertices = PriorityQueue::new();
-		let mut change_vertices = HashMap::new();
-		let list_of_peers = adjacency_list.get_keys();
-		println!("peer names: {:?}", list_of_peers);
-		let mut previous = HashMap::new();
-		for peer in list_of_peers {
-			vertices.push(peer.clone(), Reverse(u32::MAX - 100000));
-			change_vertices.insert(peer, u32::MAX - 100000);
-		}
-		vertices.push(source.clone(), Reverse(0));
-		change_vertices.insert(source.clone(), 0);
-
-		while vertices.peek() != None {
-			let smallest = vertices.pop().unwrap();
-			println!("{:?}", smallest);
-			let peer_data = adjacency_list.get(&smallest.0).unwrap();
-			let children = peer_data.get_peers().clone();
-			for (child, latency) in children{
-				println!("{:?}", child);
-				match change_vertices.get(&child) {
-					Some(num) => {
-						let val = smallest.1.0;
-						if num > &(&val + latency) {
-							change_vertices.insert(child.to_string(), val+latency);
-							vertices.change_priority(&child, Reverse(val + latency));
-							previous.insert(child, smallest.0.clone());
-						}
-					}
+    /// Sets the location that this `Server` will [Announce] to the rest of the network.
+    /// This needs to be called before [Server::start()].
+    pub fn set_location(&mut self, location: Location) {
+        self.location = location;
+    }
 
-					None => {
-						let val = smallest.1.0;
+    /// Keeps track of our sessions in the file at `path`, so that this session's sequence
+    /// numbers are higher than the last one's even if the clock went backwards in between
+    /// (otherwise, our commands could be mistaken for ones other peers have already seen).
+    /// This needs to be called before [Server::start()].
+    pub fn set_session_file(&mut self, path: impl AsRef<Path>) -> Result<(), Bing2BingError> {
+        let epoch = next_session_epoch(path.as_ref())?;
+        self.sequence_numbers = SequenceNumberGenerator::for_session(epoch);
 
-						change_vertices.insert(child.to_string(), val+latency);
-						vertices.change_priority(&child, Reverse(val + latency));
-						previous.insert(child, smallest.0.clone());
-					}
-				}
-			}
-		}
+        Ok(())
+    }
 
-		let mut shortest_path = Vec::new();
+    /// Sets how this `Server` figures out its routes (see [RoutingMode]).
+    /// Every peer in the network should use the same mode.
+    /// This needs to be called before [Server::start()].
+    pub fn set_routing_mode(&mut self, routing_mode: RoutingMode) {
+        self.routing_mode = routing_mode;
+    }
 
-		let mut this_vertex = destination.clone();
+    /// Sets how many hops the `Say`s and `Announce`s we send may travel before peers stop
+    /// forwarding them (`None`, the default, for no limit, like before hop limits existed).
+    /// Peers from before hop limits existed can't read frames that carry one, so only set this
+    /// if every peer in the network is new enough.
+    /// This needs to be called before [Server::start()].
+    pub fn set_hop_limit(&mut self, hop_limit: Option<u64>) {
+        self.hop_limit = hop_limit;
+    }
 
-		while(this_vertex != source.clone()){
-			shortest_path.insert(0, this_vertex.clone());
-			this_vertex = previous.get(&this_vertex).unwrap().to_string();
-		}
+    /// Limits how fast we take frames from any one source (`None`, the default, for no limit;
+    /// see [DEFAULT_SOURCE_RATE_LIMIT](crate::DEFAULT_SOURCE_RATE_LIMIT) for a sensible one).
+    /// Frames from a source that is over its limit are dropped (so they aren't passed on
+    /// either), and our [Client](crate::Client) gets a [ClientServerMessage::Throttled] when
+    /// that starts happening.
+    /// This needs to be called before [Server::start()].
+    pub fn set_source_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.source_rate_limit = limit;
+    }
 
-		println!("{:?}", shortest_path);
-		shortest_path
-	}
+    /// Like [Server::set_source_rate_limit()], but for every frame that comes in over a single
+    /// incoming connection, whoever it is from (so that made up source names don't get around
+    /// the limit). There is no limit by default; see
+    /// [DEFAULT_LINK_RATE_LIMIT](crate::DEFAULT_LINK_RATE_LIMIT) for a sensible one.
+    /// This needs to be called before [Server::start()].
+    pub fn set_link_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.link_rate_limit = limit;
+    }
+
+    /// How many frames we have dropped for going over a rate limit.
+    pub fn rate_limit_drops(&self) -> u64 {
+        self.rate_limit_drops.get()
+    }
+
+    /// Makes this `Server` pass `Say`s on to only `fanout` of its peers (picked at random each
+    /// time) instead of all of them, which cuts down on duplicate traffic in well connected
+    /// networks. Since some peers will miss some `Say`s this way, we also periodically send a
+    /// [Digest] of the ones we have seen to a random peer, so that they can be caught up.
+    /// `None` (the default) floods `Say`s to every peer, like before.
+    /// This needs to be called before [Server::start()].
+    pub fn set_gossip_fanout(&mut self, fanout: Option<usize>) {
+        self.gossip_fanout = fanout;
+    }
+
+    /// How many flooded commands we have dropped instead of forwarding because their
+    /// hop limit ran out.
+    pub fn hop_limit_drops(&self) -> u64 {
+        self.hop_limit_drops.get()
+    }
+
+    /// Returns a snapshot of the network as we currently know it
+    /// (i.e., built from the [Announce]s we have received).
+    pub fn topology(&self) -> Topology {
+        Topology::from_adjacency_list(&self.adjacency_list)
+    }
+
+    pub(crate) fn adjacency_list(&self) -> TtlMap<PeerData> {
+        self.adjacency_list.clone()
+    }
+
+    pub(crate) fn ignore_list(&self) -> IgnoreList {
+        self.ignore_list.clone()
+    }
+
+    /// The routes we use to send [Whisper]s, which are kept up to date while the server runs.
+    pub fn routing_table(&self) -> RoutingTable {
+        self.routing_table.clone()
+    }
 
     /// Begin listening for inbound connections.
     #[instrument(level = "trace")]
@@ -132,20 +212,31 @@ impl Server {
         &self,
         peer_map: &PeerMap,
 		adjacency_list: TtlMap<PeerData>,
-        client_tx: ClientTxChannel,
+        client_tx: EventTxChannel,
     ) -> Result<(), Bing2BingError> {
         let peers = peer_map;
 		let list = adjacency_list;
-        let processed_commands: TtlMap<bool> = TtlMap::new();
+        let processed_commands = SequenceWindows::new();
+        let rate_limiter = RateLimiter::new(
+            self.source_rate_limit,
+            self.link_rate_limit,
+            self.rate_limit_drops.clone(),
+        );
 
         loop {
             let (stream, addr) = self.listener.accept().await?;
 			let name = self.name.clone();
             let peers = peers.clone(); //Arc::clone(&peers);
             let list = list.clone();
+            let latest_announces = self.latest_announces.clone();
 
             let processed_commands = processed_commands.clone();
             let connection_counter = self.num_incoming_conns.clone();
+            let routing_table = self.routing_table.clone();
+            let neighbor_vectors = self.neighbor_vectors.clone();
+            let recent_says = self.recent_says.clone();
+            let rate_limiter = rate_limiter.clone();
+            let ignore_list = self.ignore_list.clone();
 
             let client_tx = client_tx.clone();
 
@@ -158,7 +249,13 @@ impl Server {
 					name,
                     &peers,
                     list,
+                    latest_announces,
+                    routing_table,
+                    neighbor_vectors,
+                    recent_says,
                     processed_commands,
+                    rate_limiter,
+                    ignore_list,
                     stream,
                     addr,
                     client_tx,
@@ -182,16 +279,24 @@ impl Server {
     /// This method will also pass relevant [ClientServerMessage]s up to a
     /// [Client](crate::Client) for further use.
    #[instrument(level = "trace")]
+    #[allow(clippy::too_many_arguments)]
     pub async fn handle_connection(
 		name: String,
         peers: &PeerMap,
         adjacency_list: TtlMap<PeerData>,
-        processed_commands: TtlMap<bool>,
+        latest_announces: TtlMap<u64>,
+        routing_table: RoutingTable,
+        neighbor_vectors: TtlMap<Vec<(String, u32)>>,
+        recent_says: TtlMap<Say>,
+        processed_commands: SequenceWindows,
+        rate_limiter: RateLimiter,
+        ignore_list: IgnoreList,
         stream: TcpStream,
         addr: SocketAddr,
-        client_tx: ClientTxChannel,
+        client_tx: EventTxChannel,
     ) -> Result<(), Bing2BingError> {
         let mut connection = Connection::new(stream).await;
+        let mut link_bucket = rate_limiter.link_bucket();
 
         loop {
             let frame = connection.read_frame().await?;
@@ -205,6 +310,11 @@ impl Server {
                 }
             };
 
+            let admission = rate_limiter.admit_from_link(&mut link_bucket);
+            if Server::throttle(admission, &addr.to_string(), &client_tx).await? {
+                continue;
+            }
+
             // we expect to only see Command frames at this point.
             let command = Bing2BingCommand::from_frame(frame)?;
 
@@ -215,6 +325,21 @@ impl Server {
                 continue;
             }
 
+            // this is after the duplicate check, so that a command that reaches us over several
+            // links only counts once, and before the command counts as processed, so that a
+            // copy that comes in once the source is back under its limit still gets through
+            if let Some(source) = command.source() {
+                if ignore_list.refuses(source) {
+                    trace!("Dropping {:?}; we are ignoring {}", command, source);
+                    continue;
+                }
+
+                let admission = rate_limiter.admit_from_source(source);
+                if Server::throttle(admission, source, &client_tx).await? {
+                    continue;
+                }
+            }
+
             command.set_processed(&processed_commands);
 
             // now see which command it was and apply it.
@@ -225,31 +350,51 @@ impl Server {
                 Bing2BingCommand::Say(cmd) => {
                     trace!("Received a Say command on an incoming connection");
                     trace!("Sending to client");
-                    client_tx
-                        .send(ClientServerMessage::Say((
+                    let message = match &cmd.channel {
+                        Some(channel) => ClientServerMessage::ChannelSay((
                             cmd.source.clone(),
+                            channel.clone(),
                             cmd.message.clone(),
-                        )))
-                        .await?;
-                    cmd.apply(&peers).await?;
+                        )),
+                        None => ClientServerMessage::Say((cmd.source.clone(), cmd.message.clone())),
+                    };
+                    client_tx.send(ClientEvent::Message(message)).await?;
+                    cmd.apply(peers, &recent_says).await?;
+                }
+                Bing2BingCommand::Announce(cmd) => {
+                    cmd.apply(&name, peers, &adjacency_list, &latest_announces).await?
+                }
+                Bing2BingCommand::Broadcast(cmd) => cmd.apply(peers).await?,
+                Bing2BingCommand::Deliver(cmd) => {
+                    if name == cmd.destination {
+                        trace!("Sending a delivery to client");
+                        client_tx
+                            .send(ClientEvent::Delivered {
+                                from: cmd.source.clone(),
+                                data: cmd.data.to_vec(),
+                            })
+                            .await?;
+                    }
+                    cmd.apply(&name, peers, &routing_table).await?;
                 }
-                Bing2BingCommand::Announce(cmd) => cmd.apply(&peers, &adjacency_list).await?,
-                Bing2BingCommand::Broadcast(cmd) => cmd.apply(&peers).await?,
-                Bing2BingCommand::Deliver(cmd) => cmd.apply(&peers).await?,
                 Bing2BingCommand::Whisper(cmd) => {
                     trace!("Received a Whisper command on an incoming connection");					   if name.clone() == cmd.destination.clone() {
 						trace!("Sending to client");
 						client_tx
-							.send(ClientServerMessage::Whisper((
+							.send(ClientEvent::Message(ClientServerMessage::Whisper((
 								cmd.source.clone(),
 								cmd.destination.clone(),
 								cmd.message.clone(),
-							)))
+							))))
 							.await?;
 					}
-                    cmd.apply(&peers).await?;
+                    cmd.apply(&name, peers, &routing_table).await?;
                 }
-                Bing2BingCommand::Extension(cmd) => cmd.apply(&peers).await?,
+                Bing2BingCommand::Extension(cmd) => cmd.apply(peers).await?,
+                Bing2BingCommand::RouteVector(cmd) => {
+                    cmd.apply(&name, peers, &neighbor_vectors).await?
+                }
+                Bing2BingCommand::Digest(cmd) => cmd.apply(&name, peers, &recent_says).await?,
                 Bing2BingCommand::Register(cmd) => {
                     tracing::error!(
                         "REGISTER COMMAND NOT IMPLEMENTED BY DEFAULT ON SERVERS (peers) {:?}",
@@ -265,22 +410,129 @@ impl Server {
         Ok(())
     }
 
-    /// Convienence function that broadcasts a say message.
+    /// Whether a frame from `who` should be dropped, given what a [RateLimiter] said about it.
+    /// When we start dropping frames from `who`, our [Client](crate::Client) is told about it.
+    async fn throttle(
+        admission: Admission,
+        who: &str,
+        client_tx: &EventTxChannel,
+    ) -> Result<bool, Bing2BingError> {
+        match admission {
+            Admission::Allowed => Ok(false),
+            Admission::Dropped => Ok(true),
+            Admission::StartedDropping => {
+                debug!("{} is sending too fast; dropping frames", who);
+                client_tx
+                    .send(ClientEvent::Message(ClientServerMessage::Throttled(who.to_string())))
+                    .await?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Convienence function that broadcasts a say message, optionally tagged with a channel,
+    /// and optionally only `hop_limit` hops away.
+    /// It is remembered in `recent_says`, in case someone we gossip with missed it.
     /// This is useful for handling messages that are coming in from the associated [Client](crate::Client).
     /// I.e., our user wants to say something.
-    pub async fn say(peer_map: &PeerMap, from: String, message: String, sequence_number: u64) {
-        let frame = Say::new(from.to_string(), sequence_number, &message).into_frame();
+    pub async fn say(
+        peer_map: &PeerMap,
+        recent_says: &TtlMap<Say>,
+        from: String,
+        channel: Option<String>,
+        message: String,
+        sequence_number: u64,
+        hop_limit: Option<u64>,
+    ) {
+        let mut say = Say::new(from.to_string(), sequence_number, &message);
+        if let Some(channel) = channel {
+            say = say.with_channel(&channel);
+        }
+        if let Some(hop_limit) = hop_limit {
+            say = say.with_hop_limit(hop_limit);
+        }
+        recent_says.set(say.id(), say.clone(), Some(RECENT_SAYS_WINDOW));
+        let frame = say.into_frame();
 
-        peer_map.broadcast(from, frame);
+        peer_map.gossip(from, frame);
     }
 
-   pub async fn whisper(peer_map: &PeerMap, adjacency_list: TtlMap<PeerData>, from: String, to: String, message: String, sequence_number: u64) {
-	
-	   let mut path = Server::shortest_path(&peer_map.clone(), adjacency_list.clone(), from.clone(), to.clone());
+    /// Convienence function that sends a whisper along the best route we know of to `to`
+    /// (or if it's `important`, along several at once; see [Server::send_routed()]).
+    /// If there isn't one, or the first hop is gone, our [Client](crate::Client) gets a
+    /// [ClientEvent::DeliveryFailed].
+    pub async fn whisper(peer_map: &PeerMap, routing_table: &RoutingTable, from: String, to: String, message: String, sequence_number: u64, important: bool) {
+		let sent = Server::send_routed(peer_map, routing_table, &from, &to, important, |path| {
+			Whisper::new(from.clone(), sequence_number, &to, &message, path).into_frame()
+		});
+
+		if let Err(err) = sent {
+			debug!("Couldn't send whisper to {}: {}", to, err);
+			peer_map.notify(ClientEvent::DeliveryFailed {
+				to,
+				message,
+				reason: err.to_string(),
+			});
+		}
+    }
+
+    /// Convienence function that sends `data` to `to` in a [Deliver], the same way
+    /// [Server::whisper()] sends a message (including the [ClientEvent::DeliveryFailed]).
+    pub(crate) async fn deliver(peer_map: &PeerMap, routing_table: &RoutingTable, from: String, to: String, data: Bytes, sequence_number: u64, important: bool) {
+		let sent = Server::send_routed(peer_map, routing_table, &from, &to, important, |path| {
+			Deliver::new(from.clone(), sequence_number, &to, data.clone(), path).into_frame()
+		});
+
+		if let Err(err) = sent {
+			debug!("Couldn't deliver {} bytes to {}: {}", data.len(), to, err);
+			peer_map.notify(ClientEvent::DeliveryFailed {
+				to,
+				message: format!("{} bytes of data", data.len()),
+				reason: err.to_string(),
+			});
+		}
+    }
+
+    /// Sends the frame that `frame_for` builds (given the rest of the path) along the best route
+    /// to `to`, or if `important`, along each of up to [IMPORTANT_PATHS] routes that don't go
+    /// through the same peers. All of the copies have the same sequence number, so `to` drops
+    /// all but the first one to arrive as duplicates.
+    /// Errors if there is no route, or not a single copy could be sent.
+    fn send_routed(
+        peer_map: &PeerMap,
+        routing_table: &RoutingTable,
+        from: &str,
+        to: &str,
+        important: bool,
+        frame_for: impl Fn(Vec<String>) -> Bing2BingFrame,
+    ) -> Result<(), Bing2BingError> {
+		let routes = if important {
+			routing_table.disjoint_routes(to, IMPORTANT_PATHS, Disjointness::Node)?
+		} else {
+			vec![routing_table.route(to)?]
+		};
+
+		let mut sent = false;
+		let mut last_err = None;
+
+		for route in routes {
+			// the frame carries the rest of the path with it (if we know it; otherwise each
+			// peer on the way looks up the next hop itself)
+			let path = route.path.into_iter().skip(1).collect();
+
+			match peer_map.send_to_peer(from.to_string(), route.next_hop, frame_for(path)) {
+				Ok(()) => sent = true,
+				Err(err) => {
+					debug!("Couldn't send a copy to {}: {}", to, err);
+					last_err = Some(err);
+				}
+			}
+		}
 
-	   let next_step = path.remove(0);
-	   let frame = Whisper::new(from.to_string(), sequence_number, &to, &message, path).into_frame();	
-		peer_map.send_to_peer(from, next_step, frame);
+		match last_err {
+			Some(err) if !sent => Err(err),
+			_ => Ok(()),
+		}
     }
 	
     /// Convienence function that gets the next sequence number for a message originating from this peer.
@@ -295,11 +547,27 @@ impl Server {
     /// 2. We want to connect to peers that we get back from the tracker.
     /// 3. We want to start listening for incoming connections from other peers.
     /// 4. We want to start announcing our neighborhood to others.
+    ///
+    /// When this returns, the server has stopped, and our [Client](crate::Client) gets a
+    /// [ClientEvent::ServerStopped].
     #[instrument(level = "trace")]
     pub async fn start(
         &self,
-        tracker_ip: &str,
-        tracker_port: &str,
+        tracker_addr: SocketAddr,
+        max_incoming_connections: u64,
+    ) -> Result<(), Bing2BingError> {
+        let result = self.run(tracker_addr, max_incoming_connections).await;
+
+        debug!("Server stopped: {:?}", result);
+        // if the client is gone there is nobody to tell
+        let _ = self.client_tx.send(ClientEvent::ServerStopped).await;
+
+        result
+    }
+
+    async fn run(
+        &self,
+        tracker_addr: SocketAddr,
         max_incoming_connections: u64,
         // next_sequence_number: Arc<Mutex<u64>>,
     ) -> Result<(), Bing2BingError> {
@@ -308,7 +576,6 @@ impl Server {
         // 3) we want to start listening for incoming connections.
 
         // Connect to tracker
-        let tracker_addr = format!("{}:{}", tracker_ip, tracker_port);
         let tcp_stream = TcpStream::connect(tracker_addr).await?;
         let mut connection = Connection::new(tcp_stream).await;
 
@@ -317,8 +584,8 @@ impl Server {
         let frame = Register::new(
             &self.name,
             sequence_number,
-            &self.ip_address,
-            &self.port.to_string(),
+            &self.addr.ip().to_string(),
+            &self.addr.port().to_string(),
         )
         .into_frame();
 
@@ -328,14 +595,15 @@ impl Server {
         let response_frame = connection.read_frame().await.unwrap().unwrap();
         let received_peers = self.parse_register_response(response_frame)?;
         trace!("received peers from announce: {:?}", received_peers);
-        let peer_map = PeerMap::default();
-		let adjacency_list: TtlMap<PeerData> = TtlMap::new();
+        let peer_map = PeerMap::with_events(Some(self.client_tx.clone()), self.hop_limit_drops.clone());
+        peer_map.set_gossip_fanout(self.gossip_fanout);
+		let adjacency_list = self.adjacency_list.clone();
 
         // we need to add each of these to the peer map.
-        for (peer_name, ip_address, port) in received_peers {
+        for (peer_name, addr) in received_peers {
             trace!("Adding peer {} from Register list", peer_name);
             if peer_name != self.name {
-                Server::connect_to_peer(&peer_map, peer_name, ip_address, port);
+                Server::connect_to_peer(&peer_map, peer_name, addr);
             }
         }
 
@@ -343,44 +611,73 @@ impl Server {
         let peer_map_move = peer_map.clone();
 		let adjacency_list_move = adjacency_list.clone();
 
-        self.client_message_handler(&peer_map_move, adjacency_list_move.clone(), self.rx.clone());
+        self.client_message_handler(&peer_map_move, self.rx.clone());
 
         // start up an announce task
         let next_sequence_number = self.sequence_numbers.clone();
         let peer_map_move = peer_map.clone();
 
         let name = self.name.clone();
-        // let port = self.port;
-        let ip_address = self.ip_address.clone();
-
-        let port = self.port;
+        let addr = self.addr;
+        let location = self.location.clone();
+        let hop_limit = self.hop_limit;
 
         let num_incoming_conns = self.num_incoming_conns.clone();
 
-        // POINTS AVAILABLE
-        // this might be fine just doing a tokio spawn instead of a thread.
-        std::thread::spawn(move || {
-            start_announce(
-                name,
-                ip_address,
-                port,
-                &peer_map_move,
-				adjacency_list_move,
-                next_sequence_number,
-                num_incoming_conns,
-                max_incoming_connections,
-            )
-        });
+        match self.routing_mode {
+            RoutingMode::LinkState => {
+                self.routing_table.watch(&adjacency_list, &peer_map);
+
+                // POINTS AVAILABLE
+                // this might be fine just doing a tokio spawn instead of a thread.
+                std::thread::spawn(move || {
+                    start_announce(
+                        name,
+                        addr,
+                        location,
+                        &peer_map_move,
+                        adjacency_list_move,
+                        next_sequence_number,
+                        num_incoming_conns,
+                        max_incoming_connections,
+                        hop_limit,
+                    )
+                });
+            }
+            RoutingMode::DistanceVector => {
+                tokio::spawn(start_route_vectors(
+                    name,
+                    addr,
+                    location,
+                    peer_map_move,
+                    adjacency_list_move,
+                    self.routing_table.clone(),
+                    self.neighbor_vectors.clone(),
+                    next_sequence_number,
+                ));
+            }
+        }
+
+        if self.gossip_fanout.is_some() {
+            tokio::spawn(start_digests(
+                self.name.clone(),
+                peer_map.clone(),
+                self.recent_says.clone(),
+                self.sequence_numbers.clone(),
+            ));
+        }
 
         self.listen(&peer_map, adjacency_list, self.client_tx.clone()).await
     }
 
     /// This method handles messages that come in from the associated [Client](crate::Client)
     #[instrument(level = "trace")]
-    fn client_message_handler(&self, peer_map: &PeerMap, adjacency_list: TtlMap<PeerData>, rx: ServerRxChannel) {
+    fn client_message_handler(&self, peer_map: &PeerMap, rx: ServerRxChannel) {
         let peer_map = peer_map.clone();
-		let adjacency_list = adjacency_list.clone();
+		let routing_table = self.routing_table.clone();
+        let recent_says = self.recent_says.clone();
         let next_sequence_number = self.sequence_numbers.clone();
+        let hop_limit = self.hop_limit;
         tokio::spawn(async move {
             loop {
                 if let Ok(msg) = rx.recv().await {
@@ -393,14 +690,47 @@ impl Server {
 
                             trace!("exceutiong Server::say");
 
-                            Server::say(&peer_map, from, message, sequence_number).await;
+                            Server::say(&peer_map, &recent_says, from, None, message, sequence_number, hop_limit).await;
+                        },
+                        ClientServerMessage::ScopedSay((from, hops, message)) => {
+                            trace!("matched a ClientServerMessage::ScopedSay message");
+                            let sequence_number = next_sequence_number.next();
+
+                            Server::say(&peer_map, &recent_says, from, None, message, sequence_number, Some(hops)).await;
+                        },
+                        ClientServerMessage::ChannelSay((from, channel, message)) => {
+                            trace!("matched a ClientServerMessage::ChannelSay message");
+                            let sequence_number = next_sequence_number.next();
+
+                            Server::say(&peer_map, &recent_says, from, Some(channel), message, sequence_number, hop_limit).await;
                         },
 						ClientServerMessage::Whisper((from, to, message)) => {
 							trace!("matched a ClientServerMessage::Whisper message");
 							let sequence_number = next_sequence_number.next();
 
 							trace!("executing Server::whisper");
-							Server::whisper(&peer_map, adjacency_list.clone(), from, to, message, sequence_number).await;
+							Server::whisper(&peer_map, &routing_table, from, to, message, sequence_number, false).await;
+						}
+						ClientServerMessage::ImportantWhisper((from, to, message)) => {
+							trace!("matched a ClientServerMessage::ImportantWhisper message");
+							let sequence_number = next_sequence_number.next();
+
+							Server::whisper(&peer_map, &routing_table, from, to, message, sequence_number, true).await;
+						}
+						ClientServerMessage::Deliver((from, to, data)) => {
+							trace!("matched a ClientServerMessage::Deliver message");
+							let sequence_number = next_sequence_number.next();
+
+							Server::deliver(&peer_map, &routing_table, from, to, data.into(), sequence_number, false).await;
+						}
+						ClientServerMessage::ImportantDeliver((from, to, data)) => {
+							trace!("matched a ClientServerMessage::ImportantDeliver message");
+							let sequence_number = next_sequence_number.next();
+
+							Server::deliver(&peer_map, &routing_table, from, to, data.into(), sequence_number, true).await;
+						}
+						ClientServerMessage::Throttled(_) => {
+							trace!("ignoring a ClientServerMessage::Throttled; those only go to the client");
 						}
                     }
                 }
@@ -411,10 +741,10 @@ impl Server {
     fn parse_register_response(
         &self,
         response: Bing2BingFrame,
-    ) -> Result<Vec<(String, String, String)>, Bing2BingError> {
+    ) -> Result<Vec<(String, SocketAddr)>, Bing2BingError> {
         let mut parse = Parse::new(response)?;
 
-        let mut ret: Vec<(String, String, String)> = Vec::new();
+        let mut ret: Vec<(String, SocketAddr)> = Vec::new();
 
         loop {
             match parse.next() {
@@ -431,13 +761,12 @@ impl Server {
                     let port = peer_info_parse.next_string()?;
 
                     peer_info_parse.finish()?;
-                    ret.push((peer_name, ip_address, port));
+                    ret.push((peer_name, parse_socket_addr(&ip_address, &port)?));
                 }
                 Err(ParseError::EndOfStream) => break,
                 Err(err) => return Err(Box::new(err)),
                 _ => {
-                    return Err(Box::new(std::io::Error::new(
-                        std::io::ErrorKind::Other,
+                    return Err(Box::new(std::io::Error::other(
                         "Found a tracker register response that was not an array!",
                     )))
                 }
@@ -448,12 +777,7 @@ impl Server {
     }
 
     #[instrument(level = "trace")]
-    pub(crate) fn connect_to_peer(
-        peer_map: &PeerMap,
-        peer_name: String,
-        ip_address: String,
-        port: String,
-    ) {
+    pub(crate) fn connect_to_peer(peer_map: &PeerMap, peer_name: String, addr: SocketAddr) {
         let mut peer_map = peer_map.clone();
 
         tokio::spawn(async move {
@@ -461,47 +785,63 @@ impl Server {
 
             // POINTS AVAILABLE
             // It is likely possible to remove all these clones with some refactoring, but I got lazy
-            let mut peer = Peer::new(peer_name.clone(), ip_address.clone(), port.clone(), peer_rx);
+            let mut peer = Peer::new(peer_name.clone(), addr, peer_rx);
 
             peer_map.insert(peer_name.clone(), peer_tx);
 
-            peer.start()
-                .await
-                .unwrap_or_else(|x| debug!("peer.start() errored out {}", x));
+            let connected = match peer.connect().await {
+                Ok(connection) => {
+                    peer_map.notify(ClientEvent::PeerConnected(peer_name.clone()));
+
+                    peer.run(connection)
+                        .await
+                        .unwrap_or_else(|x| debug!("peer.run() errored out {}", x));
+                    true
+                }
+                Err(err) => {
+                    debug!("Couldn't connect to {}: {}", peer_name, err);
+                    false
+                }
+            };
 
             // this is not the greatest way to handle a disconnect coming from a peer
             // but, we could send a [Peer] a [PeerControlMessage::ShutDown] and then it should break from the loop
             peer_map.remove(peer_name.clone());
+
+            if connected {
+                peer_map.notify(ClientEvent::PeerDisconnected(peer_name));
+            }
         });
     }
 }
 
-/// *POINTS AVAILABLE*
 /// Right now, this method will announce the peer to the rest
 /// of the network every 5 seconds.
 /// As part of this announcement, the peer will transmit the name of the city
-/// it's in, as well as lat and longitude.
-/// It would be nice to have this be configurable instead of hard coded
-/// as it currently is.
+/// it's in, as well as lat and longitude (see [Server::set_location()]).
 #[tokio::main]
 #[instrument(level = "trace")]
+#[allow(clippy::too_many_arguments)]
 async fn start_announce(
     name: String,
-    ip_address: String,
-    port: u64,
+    addr: SocketAddr,
+    location: Location,
     peer_map: &PeerMap,
 	adjacency_list: TtlMap<PeerData>,
     next_sequence_number: SequenceNumberGenerator,
     num_incoming_conns: ConnectionCounter,
     max_incoming_conns: u64,
+    hop_limit: Option<u64>,
 ) {
     loop {
         let sequence_number = next_sequence_number.next();
 
-        let peers : Vec<(String, u32)> = peer_map.peer_names()
+        let mut peers : Vec<(String, u32)> = peer_map.peer_names()
 			.into_iter()
-			.map(|x| (x, 1))
+			.map(|x| (x, UNMEASURED_LATENCY))
 			.collect();
+		// so that the same neighborhood always looks the same (see TtlMap::set_if_changed())
+		peers.sort();
 
         let num_incoming_conns = num_incoming_conns.get();
 
@@ -510,11 +850,10 @@ async fn start_announce(
             false => 0,
         };
 
-		adjacency_list.set(
+		// the routing table picks up on changes to our own neighborhood this way
+		adjacency_list.set_if_changed(
 			name.clone(),
-			PeerData::new(
-			"New York", 
-			40.6943, -73.9249, peers.clone()),
+			PeerData::new(location.city(), location.lat(), location.lng(), peers.clone(), sequence_number),
             Some(Duration::from_secs(30)),
 		);
 
@@ -529,20 +868,20 @@ async fn start_announce(
 
 		}
 	*/
-        // POINTS AVAILABLE
-        // Try making a configuration setting that does this with some more
-        // configurability
-        let announce = Announce::new(
+        let mut announce = Announce::new(
             name.clone(),
             sequence_number,
-            ip_address.clone(),
-            port,
+            addr.ip().to_string(),
+            addr.port() as u64,
             available_incoming,
-            "New York".to_string(),
-            40.6943,
-            -73.9249,
+            location.city().to_string(),
+            location.lat(),
+            location.lng(),
             peers,
         );
+        if let Some(hop_limit) = hop_limit {
+            announce = announce.with_hop_limit(hop_limit);
+        }
 
         let announce_frame = announce.into_frame();
         trace!("Broadcasting announce frame: {:?}", announce_frame);
@@ -554,3 +893,216 @@ async fn start_announce(
         trace!("announce woke up!");
     }
 }
+
+/// The [RoutingMode::DistanceVector] counterpart of [start_announce()].
+/// Every 5 seconds, and whenever our routes change, we send each of our neighbors the routes we
+/// have (with poisoned reverse, see [RoutingTable::vector_for()]).
+/// Neighbors coming and going, and their vectors changing or timing out, are checked for every
+/// second.
+///
+/// Nothing is flooded, so the only thing we know about the rest of the network is our routes;
+/// our own entry in the adjacency list is kept up to date so the [Topology] at least has us.
+#[instrument(level = "trace")]
+#[allow(clippy::too_many_arguments)]
+async fn start_route_vectors(
+    name: String,
+    addr: SocketAddr,
+    location: Location,
+    peer_map: PeerMap,
+    adjacency_list: TtlMap<PeerData>,
+    routing_table: RoutingTable,
+    neighbor_vectors: TtlMap<Vec<(String, u32)>>,
+    next_sequence_number: SequenceNumberGenerator,
+) {
+    let mut advertise = tokio::time::interval(Duration::from_secs(5));
+    let mut check = tokio::time::interval(Duration::from_secs(1));
+    let mut changes = neighbor_vectors.changes();
+
+    loop {
+        let periodic = tokio::select! {
+            _ = advertise.tick() => true,
+            _ = check.tick() => false,
+            // if we missed some changes it doesn't matter, since we recompute from scratch
+            _ = changes.recv() => false,
+        };
+
+        let mut neighbors = peer_map.peer_names();
+        neighbors.sort();
+
+        let sequence_number = next_sequence_number.next();
+
+        adjacency_list.set_if_changed(
+            name.clone(),
+            PeerData::new(
+                location.city(),
+                location.lat(),
+                location.lng(),
+                neighbors.iter().map(|neighbor| (neighbor.clone(), UNMEASURED_LATENCY)).collect(),
+                sequence_number,
+            ),
+            Some(VECTOR_TIMEOUT),
+        );
+
+        let changed = routing_table.recompute_from_vectors(&neighbors, &neighbor_vectors);
+
+        if changed {
+            debug!("Routes changed; sending our vector to {:?}", neighbors);
+            peer_map.notify(ClientEvent::TopologyChanged);
+        } else if !periodic {
+            continue;
+        }
+
+        for neighbor in neighbors {
+            let frame = RouteVector::new(
+                name.clone(),
+                sequence_number,
+                addr.ip().to_string(),
+                addr.port() as u64,
+                routing_table.vector_for(&neighbor),
+            )
+            .into_frame();
+
+            if let Err(err) = peer_map.send_to_peer(name.clone(), neighbor, frame) {
+                trace!("Couldn't send route vector: {}", err);
+            }
+        }
+    }
+}
+
+/// Anti-entropy for gossiping servers (see [Server::set_gossip_fanout()]): every so often we
+/// send a random peer a [Digest] of the `Say`s we have seen lately, so that it can send us
+/// the ones we missed (and we can send it the ones it missed).
+#[instrument(level = "trace")]
+async fn start_digests(
+    name: String,
+    peer_map: PeerMap,
+    recent_says: TtlMap<Say>,
+    next_sequence_number: SequenceNumberGenerator,
+) {
+    let mut interval = tokio::time::interval(DIGEST_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let peer = match peer_map.random_peer() {
+            Some(peer) => peer,
+            None => continue,
+        };
+
+        let ids = recent_says.snapshot().into_iter().map(|(id, _, _)| id).collect();
+        let frame = Digest::new(name.clone(), next_sequence_number.next(), true, ids).into_frame();
+
+        trace!("Sending digest to {}", peer);
+        if let Err(err) = peer_map.send_to_peer(name.clone(), peer, frame) {
+            trace!("Couldn't send digest: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::PeerControlMessage;
+
+    /// The adjacency list that `s` would build from everyone's announces, for a network where
+    /// s - a - b - t is the shortest path, but s - a - d - t and s - c - b - t are the only
+    /// two that don't go through the same peers.
+    fn adjacency_list() -> TtlMap<PeerData> {
+        let links = [
+            ("s", "a", 10),
+            ("a", "b", 10),
+            ("b", "t", 10),
+            ("a", "d", 30),
+            ("d", "t", 30),
+            ("s", "c", 30),
+            ("c", "b", 30),
+        ];
+
+        let adjacency_list = TtlMap::new();
+        for name in ["s", "a", "b", "c", "d", "t"] {
+            let peers = links
+                .iter()
+                .filter_map(|&(a, b, latency)| match name {
+                    _ if name == a => Some((b.to_string(), latency)),
+                    _ if name == b => Some((a.to_string(), latency)),
+                    _ => None,
+                })
+                .collect();
+            adjacency_list.set(name.to_string(), PeerData::new("", 0.0, 0.0, peers, 1), None);
+        }
+
+        adjacency_list
+    }
+
+    /// The whispers that were sent over `rx`.
+    fn whispers(rx: &mut mpsc::UnboundedReceiver<PeerControlMessage>) -> Vec<Whisper> {
+        let mut whispers = vec![];
+
+        while let Ok(PeerControlMessage::Frame(frame)) = rx.try_recv() {
+            match Bing2BingCommand::from_frame(frame).unwrap() {
+                Bing2BingCommand::Whisper(whisper) => whispers.push(whisper),
+                command => panic!("unexpected {:?}", command),
+            }
+        }
+
+        whispers
+    }
+
+    #[tokio::test]
+    async fn important_whispers_go_along_disjoint_routes_and_arrive_once() {
+        let routing_table = RoutingTable::new("s");
+        routing_table.recompute(&adjacency_list());
+
+        let mut peer_map = PeerMap::new();
+        let (a_tx, mut a_rx) = mpsc::unbounded_channel();
+        let (c_tx, mut c_rx) = mpsc::unbounded_channel();
+        peer_map.insert("a".to_string(), a_tx);
+        peer_map.insert("c".to_string(), c_tx);
+
+        // unimportant whispers just take the shortest route
+        Server::whisper(&peer_map, &routing_table, "s".to_string(), "t".to_string(), "psst".to_string(), 1, false).await;
+
+        let via_a = whispers(&mut a_rx);
+        assert_eq!(via_a.len(), 1);
+        assert_eq!(via_a[0].path, vec!["b", "t"]);
+        assert!(whispers(&mut c_rx).is_empty());
+
+        Server::whisper(&peer_map, &routing_table, "s".to_string(), "t".to_string(), "psst".to_string(), 2, true).await;
+
+        let via_a = whispers(&mut a_rx);
+        let via_c = whispers(&mut c_rx);
+        assert_eq!(via_a.len(), 1);
+        assert_eq!(via_c.len(), 1);
+        assert_eq!(via_a[0].path, vec!["d", "t"]);
+        assert_eq!(via_c[0].path, vec!["b", "t"]);
+
+        // t takes whichever copy gets there first, and drops the other one
+        let processed_commands = SequenceWindows::new();
+        let mut copies = via_a.into_iter().chain(via_c).map(Bing2BingCommand::Whisper);
+
+        let first = copies.next().unwrap();
+        assert!(!first.check_duplicate(&processed_commands));
+        first.set_processed(&processed_commands);
+
+        let second = copies.next().unwrap();
+        assert!(second.check_duplicate(&processed_commands));
+    }
+
+    #[tokio::test]
+    async fn important_whispers_get_through_if_any_first_hop_is_there() {
+        let routing_table = RoutingTable::new("s");
+        routing_table.recompute(&adjacency_list());
+
+        // we haven't got a connection to a (anymore), which the best routes all go through
+        let (events_tx, events_rx) = async_channel::unbounded();
+        let mut peer_map = PeerMap::with_events(Some(events_tx), DropCounter::new());
+        let (c_tx, mut c_rx) = mpsc::unbounded_channel();
+        peer_map.insert("c".to_string(), c_tx);
+
+        Server::whisper(&peer_map, &routing_table, "s".to_string(), "t".to_string(), "psst".to_string(), 1, true).await;
+
+        assert_eq!(whispers(&mut c_rx).len(), 1);
+        assert!(events_rx.try_recv().is_err(), "the whisper shouldn't have failed");
+    }
+}
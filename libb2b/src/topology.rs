@@ -0,0 +1,623 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Write as _;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    peer::{PeerData, STALE_AFTER},
+    util::TtlMap,
+    Bing2BingError,
+};
+
+/// A point in time snapshot of the network, as seen by one peer.
+///
+/// This is built out of the adjacency list that a [Server](crate::Server) fills in from
+/// the [Announce](crate::cmd::Announce)s it receives, and can be rendered as
+/// [Graphviz DOT](https://graphviz.org/doc/info/lang.html) or JSON.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Topology {
+    pub nodes: Vec<TopologyNode>,
+}
+
+/// A single peer in a [Topology].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopologyNode {
+    pub name: String,
+    pub city: String,
+    pub lat: f64,
+    pub lng: f64,
+    /// How long ago (in seconds) we last heard an `Announce` for this peer.
+    pub age_secs: f64,
+    /// The sequence number of the last `Announce` we heard for this peer.
+    #[serde(default)]
+    pub sequence_number: u64,
+    /// Whether we haven't heard an `Announce` for this peer in so long (see [STALE_AFTER])
+    /// that its links can't be trusted anymore. Routes don't go through stale peers.
+    #[serde(default)]
+    pub stale: bool,
+    /// The peers this peer has outgoing connections to.
+    pub links: Vec<TopologyLink>,
+}
+
+/// A (directed) link between two peers in a [Topology].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopologyLink {
+    pub peer: String,
+    /// The latency that was announced for this link.
+    pub latency: u32,
+    /// The weight this link has when routing (see [PeerData::link_weight()]).
+    pub weight: u32,
+}
+
+/// The shortest path from one peer to another in a [Topology].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Route {
+    pub destination: String,
+    /// The first peer on the path, i.e., who we would send to.
+    pub next_hop: String,
+    /// The sum of the routing weights along the path.
+    pub cost: u32,
+    /// Every peer on the path, not including the source but including the destination.
+    /// Empty if we only know the next hop (see [RoutingMode::DistanceVector](crate::RoutingMode::DistanceVector)).
+    pub path: Vec<String>,
+}
+
+/// What the paths found by [Topology::disjoint_routes_from()] don't have in common.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disjointness {
+    /// No two paths use the same link, but they may go through the same peers.
+    Link,
+    /// No two paths go through the same peer (other than the source and destination).
+    Node,
+}
+
+impl Topology {
+    pub(crate) fn from_adjacency_list(adjacency_list: &TtlMap<PeerData>) -> Self {
+        let mut nodes = adjacency_list
+            .snapshot()
+            .into_iter()
+            .map(|(name, peer_data, age)| {
+                let mut links = peer_data
+                    .get_peers()
+                    .iter()
+                    .map(|(peer, latency)| TopologyLink {
+                        peer: peer.clone(),
+                        latency: *latency,
+                        weight: peer_data.link_weight(*latency, adjacency_list.get(peer).as_ref()),
+                    })
+                    .collect::<Vec<_>>();
+                links.sort_by(|a, b| a.peer.cmp(&b.peer));
+
+                let location = peer_data.location();
+
+                TopologyNode {
+                    name,
+                    city: location.city().to_string(),
+                    lat: location.lat(),
+                    lng: location.lng(),
+                    age_secs: age.as_secs_f64(),
+                    sequence_number: peer_data.sequence_number(),
+                    stale: age >= STALE_AFTER,
+                    links,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // HashMap ordering is random, but it's much nicer to diff snapshots if they're sorted
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self { nodes }
+    }
+
+    /// Looks up the node for the peer called `name`.
+    pub fn node(&self, name: &str) -> Option<&TopologyNode> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    /// Computes the shortest route from `source` to every peer that is reachable from it
+    /// (Dijkstra's over the link weights). Routes are sorted by destination name.
+    ///
+    /// Stale peers can still be reached (if a fresh peer links to them), but routes don't go
+    /// through them, since the links they last told us about may well be gone by now.
+    /// `source` itself is never considered stale.
+    pub fn routes_from(&self, source: &str) -> Vec<Route> {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| (node.name.as_str(), node))
+            .collect::<HashMap<_, _>>();
+
+        let mut costs: HashMap<&str, u32> = HashMap::new();
+        let mut previous: HashMap<&str, &str> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+
+        costs.insert(source, 0);
+        queue.push(Reverse((0, source)));
+
+        while let Some(Reverse((cost, current))) = queue.pop() {
+            if cost > costs[current] {
+                // we already found a better way here
+                continue;
+            }
+
+            // peers we haven't received an announce from (recently) are dead ends
+            let links = match nodes.get(current) {
+                Some(node) if !node.stale || current == source => &node.links,
+                _ => continue,
+            };
+
+            for link in links {
+                let next_cost = cost.saturating_add(link.weight);
+
+                if costs.get(link.peer.as_str()).is_none_or(|&known| next_cost < known) {
+                    costs.insert(&link.peer, next_cost);
+                    previous.insert(&link.peer, current);
+                    queue.push(Reverse((next_cost, &link.peer)));
+                }
+            }
+        }
+
+        let mut routes = costs
+            .iter()
+            .filter(|(&destination, _)| destination != source)
+            .map(|(&destination, &cost)| {
+                let mut path = vec![destination.to_string()];
+                let mut hop = destination;
+
+                while let Some(&prev) = previous.get(hop) {
+                    if prev == source {
+                        break;
+                    }
+                    path.insert(0, prev.to_string());
+                    hop = prev;
+                }
+
+                Route {
+                    destination: destination.to_string(),
+                    next_hop: path[0].clone(),
+                    cost,
+                    path,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        routes.sort_by(|a, b| a.destination.cmp(&b.destination));
+
+        routes
+    }
+
+    /// Finds up to `k` routes from `source` to `destination` that are [Disjointness::Link] or
+    /// [Disjointness::Node] disjoint, so that losing a link (or peer) can only take out one
+    /// of them. Routes are sorted by cost, cheapest first.
+    ///
+    /// This finds as many disjoint routes as there are (up to `k`), and of those, the ones that
+    /// cost the least in total (a min-cost flow, the generalization of Suurballe's algorithm).
+    /// Taking the shortest route and then looking for others that avoid it isn't enough, since
+    /// the shortest route can block every other one. For the same reason, the cheapest route
+    /// returned isn't always the shortest route there is (unless `k` is 1).
+    pub fn disjoint_routes_from(
+        &self,
+        source: &str,
+        destination: &str,
+        k: usize,
+        disjointness: Disjointness,
+    ) -> Vec<Route> {
+        let mut graph = FlowGraph::new(self, source, destination, disjointness);
+
+        let (start, end) = match (graph.index.get(source), graph.index.get(destination)) {
+            (Some(&source), Some(&destination)) if source != destination => {
+                (graph.out_vertex(source), graph.in_vertex(destination))
+            }
+            _ => return vec![],
+        };
+
+        let mut found = 0;
+        while found < k && graph.augment(start, end) {
+            found += 1;
+        }
+
+        let mut routes = (0..found)
+            .map(|_| graph.take_path(start, end))
+            .map(|hops| Route {
+                destination: destination.to_string(),
+                next_hop: hops[0].0.clone(),
+                cost: hops.iter().fold(0u32, |cost, (_, weight)| cost.saturating_add(*weight)),
+                path: hops.into_iter().map(|(peer, _)| peer).collect(),
+            })
+            .collect::<Vec<_>>();
+
+        routes.sort_by(|a, b| a.cost.cmp(&b.cost).then_with(|| a.path.cmp(&b.path)));
+
+        routes
+    }
+
+    /// Renders this `Topology` as a Graphviz DOT digraph.
+    /// Nodes are labeled with their location and age, and edges with their routing weight.
+    /// Stale nodes are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph b2b {\n");
+
+        for node in &self.nodes {
+            let label = format!(
+                "{}\n{} ({:.4}, {:.4})\nage {:.1}s",
+                node.name, node.city, node.lat, node.lng, node.age_secs
+            );
+
+            let style = if node.stale { " style=dashed" } else { "" };
+
+            // writing to a String can't fail
+            let _ = writeln!(dot, "    {} [label={}{}];", quote(&node.name), quote(&label), style);
+        }
+
+        for node in &self.nodes {
+            for link in &node.links {
+                let _ = writeln!(
+                    dot,
+                    "    {} -> {} [label=\"{}\"];",
+                    quote(&node.name),
+                    quote(&link.peer),
+                    link.weight
+                );
+            }
+        }
+
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    /// Renders this `Topology` as (pretty printed) JSON.
+    pub fn to_json(&self) -> Result<String, Bing2BingError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Writes this `Topology` to `path`.
+    /// Files ending in `.dot` or `.gv` get DOT; everything else gets JSON.
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), Bing2BingError> {
+        let path = path.as_ref();
+
+        let contents = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("dot") | Some("gv") => self.to_dot(),
+            _ => self.to_json()?,
+        };
+
+        std::fs::write(path, contents)?;
+
+        Ok(())
+    }
+}
+
+/// The graph that [Topology::disjoint_routes_from()] sends flow through. Every link can carry
+/// one route, and with [Disjointness::Node], every peer is split into an "in" vertex and an
+/// "out" vertex joined by an edge that can carry one route, too.
+///
+/// Edges come in pairs: an edge and its residual (reverse) edge, at `e` and `e ^ 1`.
+struct FlowGraph {
+    /// The index of every peer, by name.
+    index: HashMap<String, usize>,
+    names: Vec<String>,
+    split: bool,
+    edges: Vec<FlowEdge>,
+    /// The edges leaving each vertex.
+    outgoing: Vec<Vec<usize>>,
+}
+
+struct FlowEdge {
+    from: usize,
+    to: usize,
+    /// How many more routes can go along this edge.
+    capacity: u32,
+    cost: i64,
+    /// The weight of the link this edge stands for, if it does (residual edges and the edges
+    /// between a peer's in and out vertices don't).
+    weight: Option<u32>,
+}
+
+impl FlowGraph {
+    fn new(topology: &Topology, source: &str, destination: &str, disjointness: Disjointness) -> Self {
+        let mut names = vec![];
+        let mut index = HashMap::new();
+        for name in topology
+            .nodes
+            .iter()
+            .flat_map(|node| std::iter::once(&node.name).chain(node.links.iter().map(|link| &link.peer)))
+        {
+            if !index.contains_key(name) {
+                index.insert(name.clone(), names.len());
+                names.push(name.clone());
+            }
+        }
+
+        let split = disjointness == Disjointness::Node;
+        let vertices = if split { names.len() * 2 } else { names.len() };
+
+        let mut graph = Self {
+            index,
+            names,
+            split,
+            edges: vec![],
+            outgoing: vec![vec![]; vertices],
+        };
+
+        if split {
+            for peer in 0..graph.names.len() {
+                graph.add_edge(graph.in_vertex(peer), graph.out_vertex(peer), 0, None);
+            }
+        }
+
+        for node in &topology.nodes {
+            // like in routes_from(), routes don't go through stale peers, and there is no
+            // point in going back through the source or on from the destination
+            if (node.stale && node.name != source) || node.name == destination {
+                continue;
+            }
+
+            let from = graph.out_vertex(graph.index[&node.name]);
+            for link in node.links.iter().filter(|link| link.peer != source) {
+                let to = graph.in_vertex(graph.index[&link.peer]);
+                graph.add_edge(from, to, link.weight as i64, Some(link.weight));
+            }
+        }
+
+        graph
+    }
+
+    fn in_vertex(&self, peer: usize) -> usize {
+        if self.split {
+            peer * 2
+        } else {
+            peer
+        }
+    }
+
+    fn out_vertex(&self, peer: usize) -> usize {
+        if self.split {
+            peer * 2 + 1
+        } else {
+            peer
+        }
+    }
+
+    /// Which peer a vertex belongs to.
+    fn peer(&self, vertex: usize) -> usize {
+        if self.split {
+            vertex / 2
+        } else {
+            vertex
+        }
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, cost: i64, weight: Option<u32>) {
+        self.outgoing[from].push(self.edges.len());
+        self.edges.push(FlowEdge { from, to, capacity: 1, cost, weight });
+        self.outgoing[to].push(self.edges.len());
+        self.edges.push(FlowEdge { from: to, to: from, capacity: 0, cost: -cost, weight: None });
+    }
+
+    /// Sends one more route from `start` to `end` along the cheapest way there is with what
+    /// capacity is left (possibly taking back part of a route that went before).
+    /// Returns false if there is no way left.
+    ///
+    /// Residual edges have negative costs, so this is Bellman-Ford rather than Dijkstra's.
+    fn augment(&mut self, start: usize, end: usize) -> bool {
+        let mut costs: Vec<Option<i64>> = vec![None; self.outgoing.len()];
+        let mut via = vec![None; self.outgoing.len()];
+        costs[start] = Some(0);
+
+        for _ in 0..self.outgoing.len() {
+            let mut changed = false;
+
+            for (e, edge) in self.edges.iter().enumerate() {
+                let cost = match costs[edge.from] {
+                    Some(cost) if edge.capacity > 0 => cost + edge.cost,
+                    _ => continue,
+                };
+
+                if costs[edge.to].is_none_or(|known| cost < known) {
+                    costs[edge.to] = Some(cost);
+                    via[edge.to] = Some(e);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        if costs[end].is_none() {
+            return false;
+        }
+
+        let mut vertex = end;
+        while let Some(e) = via[vertex].filter(|_| vertex != start) {
+            self.edges[e].capacity -= 1;
+            self.edges[e ^ 1].capacity += 1;
+            vertex = self.edges[e].from;
+        }
+
+        true
+    }
+
+    /// Takes one of the routes that [FlowGraph::augment()] sent from `start` to `end` out of the
+    /// graph, as the peers along it (after `start`) and the weights of the links to them.
+    fn take_path(&mut self, start: usize, end: usize) -> Vec<(String, u32)> {
+        let mut hops: Vec<(String, u32)> = vec![];
+        let mut vertex = start;
+
+        while vertex != end {
+            // a route went along an edge if its residual edge can take it back
+            let e = self.outgoing[vertex]
+                .iter()
+                .copied()
+                .find(|&e| e % 2 == 0 && self.edges[e ^ 1].capacity > 0)
+                .expect("routes only end at the destination");
+            self.edges[e ^ 1].capacity -= 1;
+            vertex = self.edges[e].to;
+
+            if let Some(weight) = self.edges[e].weight {
+                let peer = &self.names[self.peer(vertex)];
+
+                // links that cost nothing can make a route go around in a circle on its way
+                match hops.iter().position(|(hop, _)| hop == peer) {
+                    Some(i) => hops.truncate(i + 1),
+                    None => hops.push((peer.clone(), weight)),
+                }
+            }
+        }
+
+        hops
+    }
+}
+
+/// Quotes a string for use as a DOT identifier.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `Topology` with `links` going both ways, each with the given weight.
+    fn topology(links: &[(&str, &str, u32)]) -> Topology {
+        let mut nodes: HashMap<&str, Vec<TopologyLink>> = HashMap::new();
+        for &(a, b, weight) in links {
+            for (from, to) in [(a, b), (b, a)] {
+                nodes.entry(from).or_default().push(TopologyLink {
+                    peer: to.to_string(),
+                    latency: weight,
+                    weight,
+                });
+            }
+        }
+
+        let mut nodes = nodes
+            .into_iter()
+            .map(|(name, links)| TopologyNode {
+                name: name.to_string(),
+                city: String::new(),
+                lat: 0.0,
+                lng: 0.0,
+                age_secs: 0.0,
+                sequence_number: 1,
+                stale: false,
+                links,
+            })
+            .collect::<Vec<_>>();
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Topology { nodes }
+    }
+
+    /// s - a - b - t is the shortest path, but it is the only one that goes through a - b.
+    /// Once it is taken, there is no other way to t, even though s - a - d - t and s - c - b - t
+    /// have nothing in common.
+    fn trap() -> Topology {
+        topology(&[
+            ("s", "a", 1),
+            ("a", "b", 1),
+            ("b", "t", 1),
+            ("a", "d", 3),
+            ("d", "t", 3),
+            ("s", "c", 3),
+            ("c", "b", 3),
+        ])
+    }
+
+    fn paths(routes: &[Route]) -> Vec<Vec<&str>> {
+        routes
+            .iter()
+            .map(|route| route.path.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn finds_disjoint_routes_the_shortest_route_would_block() {
+        for disjointness in [Disjointness::Link, Disjointness::Node] {
+            let routes = trap().disjoint_routes_from("s", "t", 2, disjointness);
+
+            assert_eq!(
+                paths(&routes),
+                vec![vec!["a", "d", "t"], vec!["c", "b", "t"]],
+                "{:?}",
+                disjointness
+            );
+            assert_eq!(routes[0].next_hop, "a");
+            assert_eq!(routes[0].cost, 7);
+
+            // on its own, the shortest route is still the one we get
+            let routes = trap().disjoint_routes_from("s", "t", 1, disjointness);
+            assert_eq!(paths(&routes), vec![vec!["a", "b", "t"]], "{:?}", disjointness);
+        }
+    }
+
+    #[test]
+    fn no_disjoint_routes_where_there_is_no_route() {
+        let topology = topology(&[("s", "a", 1), ("x", "y", 1)]);
+
+        for disjointness in [Disjointness::Link, Disjointness::Node] {
+            assert!(topology.disjoint_routes_from("s", "y", 2, disjointness).is_empty());
+            assert!(topology.disjoint_routes_from("s", "nobody", 2, disjointness).is_empty());
+            assert!(topology.disjoint_routes_from("s", "s", 2, disjointness).is_empty());
+        }
+    }
+
+    #[test]
+    fn only_as_many_disjoint_routes_as_there_are() {
+        for disjointness in [Disjointness::Link, Disjointness::Node] {
+            // s only has two links
+            assert_eq!(trap().disjoint_routes_from("s", "t", 5, disjointness).len(), 2);
+        }
+
+        // two ways to a, but both go through the same link from m
+        //   s - l - m - a
+        //    \     /
+        //      r
+        let topology = topology(&[("s", "l", 1), ("l", "m", 1), ("s", "r", 1), ("r", "m", 1), ("m", "a", 1)]);
+        assert_eq!(topology.disjoint_routes_from("s", "a", 3, Disjointness::Link).len(), 1);
+        assert_eq!(topology.disjoint_routes_from("s", "a", 3, Disjointness::Node).len(), 1);
+
+        // ... but two ways to m itself
+        assert_eq!(topology.disjoint_routes_from("s", "m", 3, Disjointness::Link).len(), 2);
+        assert_eq!(topology.disjoint_routes_from("s", "m", 3, Disjointness::Node).len(), 2);
+    }
+
+    #[test]
+    fn direct_neighbors_are_one_hop_away() {
+        // s - t directly (which costs more), or by way of a, then maybe b
+        let topology = topology(&[("s", "t", 5), ("s", "a", 1), ("a", "t", 1), ("a", "b", 1), ("b", "t", 1)]);
+
+        for disjointness in [Disjointness::Link, Disjointness::Node] {
+            let routes = topology.disjoint_routes_from("s", "t", 3, disjointness);
+
+            assert_eq!(paths(&routes), vec![vec!["a", "t"], vec!["t"]], "{:?}", disjointness);
+            assert_eq!(routes[1].next_hop, "t");
+            assert_eq!(routes[1].cost, 5);
+        }
+    }
+
+    #[test]
+    fn link_disjoint_routes_can_share_peers() {
+        //   s ----- m ----- t
+        //    \     / \     /
+        //     x --     -- y
+        // (both routes go through m, but not over the same link)
+        let topology = topology(&[
+            ("s", "m", 1),
+            ("m", "t", 1),
+            ("s", "x", 1),
+            ("x", "m", 1),
+            ("m", "y", 1),
+            ("y", "t", 1),
+        ]);
+
+        assert_eq!(
+            paths(&topology.disjoint_routes_from("s", "t", 2, Disjointness::Link)),
+            vec![vec!["m", "t"], vec!["x", "m", "y", "t"]]
+        );
+        assert_eq!(topology.disjoint_routes_from("s", "t", 2, Disjointness::Node).len(), 1);
+    }
+}
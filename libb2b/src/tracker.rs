@@ -3,7 +3,11 @@ use tokio::net::TcpStream;
 
 use tokio::net::TcpListener;
 
-use crate::{cmd::Register, util::TtlMap, Bing2BingError, Bing2BingFrame, Connection};
+use crate::{
+    cmd::Register,
+    util::{bind_listener, TtlMap},
+    Bing2BingError, Bing2BingFrame, Connection,
+};
 
 use tracing::{debug, instrument, trace};
 
@@ -15,9 +19,11 @@ pub struct Tracker {
 }
 
 impl Tracker {
-    pub async fn new(bind_address: &str, port: &str) -> Result<Self, Bing2BingError> {
+    /// Creates a new `Tracker` listening on `addr`.
+    /// Binding to `::` will listen on both IPv6 and IPv4.
+    pub async fn new(addr: SocketAddr) -> Result<Self, Bing2BingError> {
         Ok(Self {
-            listener: TcpListener::bind(format!("{}:{}", bind_address, port)).await?,
+            listener: bind_listener(addr)?,
         })
     }
 
@@ -45,8 +51,7 @@ impl Tracker {
 
         match command {
             Bing2BingCommand::Register(cmd) => Ok(cmd),
-            _ => Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            _ => Err(Box::new(std::io::Error::other(
                 "RECEIVED NON REGISTER COMMAND",
             ))),
         }
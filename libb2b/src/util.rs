@@ -3,4 +3,12 @@ pub(crate) use ttl_map::TtlMap;
 
 mod counters;
 pub(crate) use counters::ConnectionCounter;
+pub(crate) use counters::DropCounter;
 pub(crate) use counters::SequenceNumberGenerator;
+pub(crate) use counters::{clock_epoch, next_session_epoch, SESSION_COUNTER_BITS};
+
+mod sequence_windows;
+pub use sequence_windows::SequenceWindows;
+
+mod net;
+pub(crate) use net::{bind_listener, parse_socket_addr};
@@ -1,4 +1,51 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Bing2BingError;
+
+/// How many of the low bits of a sequence number count the commands sent within a session.
+/// The bits above them are the session's epoch, which is higher for every session a peer
+/// starts (see [clock_epoch()] and [next_session_epoch()]).
+///
+/// That way every command carries the epoch it was sent in, and a peer that restarts carries
+/// on with higher sequence numbers than it had before, instead of starting over from 0 (where
+/// its new commands would look like ones we have already seen).
+///
+/// Epochs are in seconds, which keeps sequence numbers below 2^53 (the biggest integer a
+/// JSON number can be sure to hold exactly, e.g., in JavaScript) until the year 2242.
+pub(crate) const SESSION_COUNTER_BITS: u32 = 20;
+
+/// The epoch for a session starting now: the seconds since the unix epoch.
+/// This is only higher than the last session's if the clock hasn't gone backwards (and at
+/// least a second has gone by); [next_session_epoch()] makes sure of it.
+pub(crate) fn clock_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0)
+}
+
+/// The epoch for a new session that is higher than the one saved at `path` (if there is one),
+/// even if the clock went backwards since then. The new epoch is saved there for next time.
+pub(crate) fn next_session_epoch(path: &Path) -> Result<u64, Bing2BingError> {
+    let last_epoch = match fs::read_to_string(path) {
+        Ok(contents) => Some(contents.trim().parse::<u64>()?),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    let epoch = match last_epoch {
+        Some(last_epoch) => clock_epoch().max(last_epoch + 1),
+        None => clock_epoch(),
+    };
+
+    fs::write(path, format!("{}\n", epoch))?;
+
+    Ok(epoch)
+}
 
 #[derive(Debug, Clone)]
 pub(crate) struct SequenceNumberGenerator {
@@ -14,6 +61,11 @@ impl SequenceNumberGenerator {
         }
     }
 
+    /// Starts counting at the beginning of the session with `epoch` (see [SESSION_COUNTER_BITS]).
+    pub(crate) fn for_session(epoch: u64) -> Self {
+        Self::new(epoch << SESSION_COUNTER_BITS)
+    }
+
     pub(crate) fn next(&self) -> u64 {
         let mut current_number = self.shared.current_number.lock().unwrap();
 
@@ -58,3 +110,64 @@ impl ConnectionCounter {
         *current_number += 1;
     }
 }
+
+/// Counts frames that we dropped for some reason, rather than passing them on.
+#[derive(Debug, Clone)]
+pub(crate) struct DropCounter {
+    shared: Arc<State>,
+}
+
+impl DropCounter {
+    pub(crate) fn new() -> Self {
+        Self {
+            shared: Arc::new(State {
+                current_number: Mutex::new(0),
+            }),
+        }
+    }
+
+    pub(crate) fn get(&self) -> u64 {
+        *self.shared.current_number.lock().unwrap()
+    }
+
+    pub(crate) fn inc(&self) {
+        let mut current_number = self.shared.current_number.lock().unwrap();
+        *current_number += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn session_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("libb2b-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn session_epochs_always_go_up() {
+        let path = session_file("epochs-go-up");
+
+        let first = next_session_epoch(&path).unwrap();
+        let second = next_session_epoch(&path).unwrap();
+        assert!(first >= clock_epoch() - 1);
+        assert!(second > first);
+
+        // as if the clock went back a day since the last session
+        let future = clock_epoch() + 24 * 60 * 60;
+        fs::write(&path, future.to_string()).unwrap();
+        assert_eq!(next_session_epoch(&path).unwrap(), future + 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sequence_numbers_fit_in_json() {
+        let generator = SequenceNumberGenerator::for_session(clock_epoch());
+        assert!(generator.next() < 1 << 53);
+    }
+}
@@ -0,0 +1,195 @@
+use std::net::{IpAddr, SocketAddr};
+
+use socket2::{Domain, Socket, Type};
+use tokio::net::TcpListener;
+
+use crate::Bing2BingError;
+
+/// Builds a [SocketAddr] out of the separate ip address and port strings that
+/// show up in the protocol (e.g., in a [Register](crate::cmd::Register) or
+/// [Announce](crate::cmd::Announce)).
+///
+/// We can't just `format!("{}:{}", ip, port)` because IPv6 literals need to be wrapped
+/// in `[` `]` when a port is attached. Brackets are accepted (and stripped) if present.
+pub(crate) fn parse_socket_addr(ip_address: &str, port: &str) -> Result<SocketAddr, Bing2BingError> {
+    let ip_address = ip_address.trim_start_matches('[').trim_end_matches(']');
+
+    let ip_address: IpAddr = ip_address.parse()?;
+    let port: u16 = port.parse()?;
+
+    Ok(SocketAddr::new(ip_address, port))
+}
+
+/// Binds a [TcpListener] to `addr`.
+///
+/// If `addr` is the IPv6 unspecified address (`::`) the socket is explicitly put into
+/// dual-stack mode so that IPv4 peers can still reach us (as IPv4-mapped addresses).
+/// Whether this is the default differs between platforms, so we don't rely on it.
+pub(crate) fn bind_listener(addr: SocketAddr) -> Result<TcpListener, Bing2BingError> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+
+    if let IpAddr::V6(ip) = addr.ip() {
+        if ip.is_unspecified() {
+            socket.set_only_v6(false)?;
+        }
+    }
+
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::time::Duration;
+
+    use tokio::net::TcpStream;
+
+    use crate::{
+        cmd::Register, Bing2BingFrame, ClientEvent, ClientServerMessage, Connection, EventRxChannel, Server,
+        ServerTxChannel, Tracker,
+    };
+
+    #[test]
+    fn parses_ipv4() {
+        let addr = parse_socket_addr("127.0.0.1", "7000").unwrap();
+        assert_eq!(addr, SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 7000));
+    }
+
+    #[test]
+    fn parses_ipv6_with_and_without_brackets() {
+        let expected = SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 7000);
+
+        assert_eq!(parse_socket_addr("::1", "7000").unwrap(), expected);
+        assert_eq!(parse_socket_addr("[::1]", "7000").unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_bad_addresses() {
+        assert!(parse_socket_addr("not an address", "7000").is_err());
+        assert!(parse_socket_addr("::1", "not a port").is_err());
+        assert!(parse_socket_addr("::1", "70000").is_err());
+    }
+
+    /// Binds to `addr` and checks that a connection to `connect_to` (at the port we got) goes through.
+    async fn accepts_connection(addr: &str, connect_to: IpAddr) {
+        let listener = bind_listener(addr.parse().unwrap()).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let (accepted, connected) = tokio::join!(
+            listener.accept(),
+            TcpStream::connect(SocketAddr::new(connect_to, port))
+        );
+
+        accepted.unwrap();
+        connected.unwrap();
+    }
+
+    #[tokio::test]
+    async fn binds_ipv6_loopback() {
+        accepts_connection("[::1]:0", Ipv6Addr::LOCALHOST.into()).await;
+    }
+
+    #[tokio::test]
+    async fn binds_ipv6_unspecified_for_both_families() {
+        accepts_connection("[::]:0", Ipv6Addr::LOCALHOST.into()).await;
+        accepts_connection("[::]:0", Ipv4Addr::LOCALHOST.into()).await;
+    }
+
+    /// A port on `[::1]` that nothing is listening on (as of just now).
+    fn free_ipv6_port() -> SocketAddr {
+        let listener = std::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    /// Starts a peer called `name` on `[::1]` that registers with the tracker at `tracker_addr`.
+    async fn start_peer(name: &str, tracker_addr: SocketAddr) -> (SocketAddr, ServerTxChannel, EventRxChannel) {
+        let addr = free_ipv6_port();
+        let (server_tx, server_rx) = async_channel::unbounded();
+        let (events_tx, events_rx) = async_channel::unbounded();
+
+        let server = Server::new(name, addr, events_tx, server_rx).await.unwrap();
+        tokio::spawn(async move { server.start(tracker_addr, 2).await });
+
+        (addr, server_tx, events_rx)
+    }
+
+    /// Registers with the tracker at `tracker_addr` (as a peer that isn't really there), and
+    /// returns the addresses of the peers it tells us about, as `ip` and `port` put back together.
+    async fn registered_peers(tracker_addr: SocketAddr) -> Vec<(String, String)> {
+        let mut connection = Connection::new(TcpStream::connect(tracker_addr).await.unwrap()).await;
+        let register = Register::new("probe", 1, &Ipv6Addr::LOCALHOST.to_string(), "1").into_frame();
+        connection.write_frame(register).await.unwrap();
+
+        let peers = match connection.read_frame().await.unwrap() {
+            Some(Bing2BingFrame::Array(peers)) => peers,
+            frame => panic!("expected a list of peers, got {:?}", frame),
+        };
+
+        peers
+            .into_iter()
+            .filter_map(|peer| match peer {
+                Bing2BingFrame::Array(fields) => match &fields[..] {
+                    [Bing2BingFrame::Text(name), Bing2BingFrame::Text(ip), Bing2BingFrame::Text(port)] => {
+                        let addr = parse_socket_addr(ip, port).unwrap();
+                        (name != "probe").then(|| (name.clone(), addr.to_string()))
+                    }
+                    fields => panic!("expected name, ip and port, got {:?}", fields),
+                },
+                frame => panic!("expected a peer, got {:?}", frame),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn peers_find_each_other_through_a_tracker_over_ipv6() {
+        let tracker_addr = free_ipv6_port();
+        let tracker = Tracker::new(tracker_addr).await.unwrap();
+        tokio::spawn(async move { tracker.listen().await });
+
+        let (p1_addr, _p1_tx, p1_events) = start_peer("p1", tracker_addr).await;
+
+        // the tracker hands out p1's address as it registered it
+        let expected = vec![("p1".to_string(), format!("[::1]:{}", p1_addr.port()))];
+        let mut peers = vec![];
+        for _ in 0..50 {
+            peers = registered_peers(tracker_addr).await;
+            if !peers.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(peers, expected);
+
+        // so p2 gets it too when it registers, and connects to p1 with it
+        let (_, p2_tx, p2_events) = start_peer("p2", tracker_addr).await;
+
+        let connected = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let ClientEvent::PeerConnected(peer) = p2_events.recv().await.unwrap() {
+                    return peer;
+                }
+            }
+        });
+        assert_eq!(connected.await.unwrap(), "p1");
+
+        // and what p2 says makes it over that connection
+        let message = ClientServerMessage::Say(("p2".to_string(), "hi over IPv6".to_string()));
+        p2_tx.send(message.clone()).await.unwrap();
+
+        let heard = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let ClientEvent::Message(message) = p1_events.recv().await.unwrap() {
+                    return message;
+                }
+            }
+        });
+        assert_eq!(heard.await.unwrap(), message);
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::SESSION_COUNTER_BITS;
+
+/// How far behind the highest sequence number we have seen from a source we can still tell
+/// whether a sequence number was seen. Anything older than that is taken to be a duplicate.
+const WINDOW_SIZE: u64 = 1024;
+
+const WINDOW_WORDS: usize = (WINDOW_SIZE / 64) as usize;
+
+// a new session has to be more than a window ahead of the last one, or its first few
+// sequence numbers could be mistaken for the old session's
+const _: () = assert!(WINDOW_SIZE < 1 << SESSION_COUNTER_BITS);
+
+/// How many sources we keep a window for. Past that, the one we heard from least recently
+/// is forgotten to make room.
+const MAX_SOURCES: usize = 4096;
+
+/// Remembers which `(source, sequence number)` pairs have been seen, which is how a
+/// [Server](crate::Server) recognizes commands it has already processed.
+///
+/// For every source there is a sliding window over the last [WINDOW_SIZE] sequence numbers
+/// (up to the highest one seen), with a bit for each. Nothing is forgotten as time passes, and
+/// memory use is bounded (by the window size times [MAX_SOURCES]).
+///
+/// A sequence number from a newer session than the source's highest one (see
+/// [SESSION_COUNTER_BITS]) means the source restarted, so its window starts over.
+/// Anything still on its way from the old session is too far behind to be let through.
+///
+/// Cloning a `SequenceWindows` gives another handle to the same windows.
+#[derive(Debug, Clone)]
+pub struct SequenceWindows {
+    shared: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    windows: HashMap<String, Window>,
+    /// Counts inserts, so that we know which window was used least recently.
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct Window {
+    highest: u64,
+    /// Bit `i` is set if `highest - i` has been seen.
+    seen: [u64; WINDOW_WORDS],
+    last_used: u64,
+}
+
+impl Default for SequenceWindows {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SequenceWindows {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Have we seen `sequence_number` from `source`?
+    pub fn contains(&self, source: &str, sequence_number: u64) -> bool {
+        let state = self.shared.lock().unwrap();
+
+        state
+            .windows
+            .get(source)
+            .is_some_and(|window| window.contains(sequence_number))
+    }
+
+    /// Remembers that we have seen `sequence_number` from `source`.
+    pub fn insert(&self, source: &str, sequence_number: u64) {
+        let mut state = self.shared.lock().unwrap();
+
+        state.clock += 1;
+        let now = state.clock;
+
+        if let Some(window) = state.windows.get_mut(source) {
+            window.insert(sequence_number);
+            window.last_used = now;
+            return;
+        }
+
+        if state.windows.len() >= MAX_SOURCES {
+            let least_recent = state
+                .windows
+                .iter()
+                .min_by_key(|(_, window)| window.last_used)
+                .map(|(source, _)| source.clone());
+
+            if let Some(least_recent) = least_recent {
+                state.windows.remove(&least_recent);
+            }
+        }
+
+        state
+            .windows
+            .insert(source.to_string(), Window::new(sequence_number, now));
+    }
+}
+
+impl Window {
+    fn new(sequence_number: u64, now: u64) -> Self {
+        let mut seen = [0; WINDOW_WORDS];
+        seen[0] = 1;
+
+        Self {
+            highest: sequence_number,
+            seen,
+            last_used: now,
+        }
+    }
+
+    fn contains(&self, sequence_number: u64) -> bool {
+        if sequence_number > self.highest {
+            return false;
+        }
+
+        let age = self.highest - sequence_number;
+        if age >= WINDOW_SIZE {
+            // too old to tell, so better safe than sorry
+            return true;
+        }
+
+        self.seen[(age / 64) as usize] & (1 << (age % 64)) != 0
+    }
+
+    fn insert(&mut self, sequence_number: u64) {
+        // a source that restarted is in a new session, which is always far enough ahead
+        // that the whole window slides past everything from the old one
+        if sequence_number > self.highest {
+            self.slide(sequence_number - self.highest);
+            self.highest = sequence_number;
+        }
+
+        let age = self.highest - sequence_number;
+        if age < WINDOW_SIZE {
+            self.seen[(age / 64) as usize] |= 1 << (age % 64);
+        }
+    }
+
+    /// Moves the window `by` sequence numbers forward (i.e., everything gets that much older).
+    fn slide(&mut self, by: u64) {
+        if by >= WINDOW_SIZE {
+            self.seen = [0; WINDOW_WORDS];
+            return;
+        }
+
+        let words = (by / 64) as usize;
+        let bits = by % 64;
+
+        for i in (0..WINDOW_WORDS).rev() {
+            let mut word = 0;
+
+            if i >= words {
+                word = self.seen[i - words] << bits;
+
+                // the bits that were pushed out of the top of the next younger word
+                if bits > 0 && i > words {
+                    word |= self.seen[i - words - 1] >> (64 - bits);
+                }
+            }
+
+            self.seen[i] = word;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remembers_sequence_numbers_in_the_window() {
+        let windows = SequenceWindows::new();
+
+        for sequence_number in [5, 3, 10] {
+            assert!(!windows.contains("a", sequence_number));
+            windows.insert("a", sequence_number);
+            assert!(windows.contains("a", sequence_number));
+        }
+
+        // gaps and anything newer are still new
+        for sequence_number in [4, 6, 9, 11] {
+            assert!(!windows.contains("a", sequence_number));
+        }
+
+        // other sources have their own windows
+        assert!(!windows.contains("b", 5));
+    }
+
+    #[test]
+    fn keeps_bits_when_sliding_across_words() {
+        let windows = SequenceWindows::new();
+        windows.insert("a", 100);
+        windows.insert("a", 170);
+        windows.insert("a", 300);
+
+        assert!(windows.contains("a", 100));
+        assert!(windows.contains("a", 170));
+        assert!(!windows.contains("a", 101));
+        assert!(!windows.contains("a", 299));
+    }
+
+    #[test]
+    fn treats_anything_a_window_behind_as_seen() {
+        let windows = SequenceWindows::new();
+        let highest = 5000;
+        windows.insert("a", highest);
+
+        assert!(!windows.contains("a", highest - (WINDOW_SIZE - 1)));
+        assert!(windows.contains("a", highest - WINDOW_SIZE));
+        assert!(windows.contains("a", 0));
+    }
+
+    #[test]
+    fn forgets_everything_after_a_jump_of_more_than_a_window() {
+        let windows = SequenceWindows::new();
+        for sequence_number in 0..=10 {
+            windows.insert("a", sequence_number);
+        }
+
+        let jump = 10 + WINDOW_SIZE + 500;
+        windows.insert("a", jump);
+
+        assert!(windows.contains("a", jump));
+        // these fell out of the window, so they count as seen
+        assert!(windows.contains("a", 10));
+        assert!(windows.contains("a", jump - WINDOW_SIZE));
+        // and nothing left in the window was seen
+        for sequence_number in (jump - WINDOW_SIZE + 1)..jump {
+            assert!(!windows.contains("a", sequence_number), "{} was never inserted", sequence_number);
+        }
+    }
+
+    #[test]
+    fn starts_over_in_a_new_session() {
+        let windows = SequenceWindows::new();
+        let old_session = 1_700_000_000 << SESSION_COUNTER_BITS;
+        let new_session = (1_700_000_000 + 1) << SESSION_COUNTER_BITS;
+
+        windows.insert("a", old_session + 5);
+        windows.insert("a", new_session);
+
+        assert!(windows.contains("a", new_session));
+        assert!(!windows.contains("a", new_session + 1));
+        // anything still on its way from the old session is far out of the window
+        assert!(windows.contains("a", old_session + 6));
+
+        windows.insert("a", new_session + 1);
+        assert!(windows.contains("a", new_session + 1));
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_source() {
+        let windows = SequenceWindows::new();
+        for i in 0..MAX_SOURCES {
+            windows.insert(&format!("s{}", i), 1);
+        }
+
+        // s0 is now the most recently used, leaving s1 as the least
+        windows.insert("s0", 2);
+        windows.insert("new", 1);
+
+        assert_eq!(windows.shared.lock().unwrap().windows.len(), MAX_SOURCES);
+        assert!(windows.contains("new", 1));
+        assert!(windows.contains("s0", 1));
+        assert!(windows.contains("s2", 1));
+        assert!(!windows.contains("s1", 1));
+    }
+}
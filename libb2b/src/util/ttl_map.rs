@@ -6,7 +6,7 @@ use std::sync::Mutex;
 
 use std::time::Duration;
 
-use tokio::sync::Notify;
+use tokio::sync::{broadcast, Notify};
 
 use tokio::time::{self, Instant};
 
@@ -27,8 +27,16 @@ pub struct TtlMap<T> {
 pub struct Shared<T> {
     state: Mutex<State<T>>,
     background_task: Notify,
+    /// Keys that expired, for anyone who wants to know (see [TtlMap::expirations()]).
+    expired: broadcast::Sender<String>,
+    /// Keys whose values changed (see [TtlMap::changes()]).
+    changed: broadcast::Sender<String>,
 }
 
+/// How many expired (or changed) keys can pile up for a slow [TtlMap::expirations()]
+/// (or [TtlMap::changes()]) receiver before it starts missing some.
+const EXPIRATIONS_CAPACITY: usize = 64;
+
 #[derive(Debug)]
 struct State<T> {
     entries: HashMap<String, Entry<T>>,
@@ -46,6 +54,9 @@ struct Entry<T> {
     /// Stored data
     data: T,
 
+    /// Instant at which the entry was (last) set
+    set_at: Instant,
+
     // Instant at which the entry expires and should be removed
     expires_at: Option<Instant>,
 }
@@ -59,6 +70,8 @@ impl<T: 'static + Clone + std::marker::Send> TtlMap<T> {
                 next_id: 0,
             }),
             background_task: Notify::new(),
+            expired: broadcast::channel(EXPIRATIONS_CAPACITY).0,
+            changed: broadcast::channel(EXPIRATIONS_CAPACITY).0,
         });
 
         tokio::spawn(purge_expired_tasks(shared.clone()));
@@ -111,6 +124,7 @@ impl<T: 'static + Clone + std::marker::Send> TtlMap<T> {
             Entry {
                 id,
                 data: value,
+                set_at: Instant::now(),
                 expires_at,
             },
         );
@@ -128,15 +142,41 @@ impl<T: 'static + Clone + std::marker::Send> TtlMap<T> {
         }
     }
 
-	pub(crate) fn get_keys(&self) -> Vec<String>{
-		let state = self.shared.state.lock().unwrap();
-		let keys : Vec<String> = state
-			.entries
-			.keys()
-			.map(|x| x.to_string())
-			.collect();
-		keys
-	}
+    /// Returns a copy of every key/value in this `TtlMap`, along with how long ago
+    /// each entry was set.
+    pub(crate) fn snapshot(&self) -> Vec<(String, T, Duration)> {
+        let state = self.shared.state.lock().unwrap();
+
+        state
+            .entries
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.data.clone(), entry.set_at.elapsed()))
+            .collect()
+    }
+
+    /// How long ago the entry for `key` was (last) set.
+    pub(crate) fn age(&self, key: &str) -> Option<Duration> {
+        let state = self.shared.state.lock().unwrap();
+        state.entries.get(key).map(|entry| entry.set_at.elapsed())
+    }
+
+    /// Lets anyone listening to [TtlMap::changes()] know that something about `key` changed,
+    /// even though its value didn't.
+    pub(crate) fn notify_changed(&self, key: String) {
+        // there is nothing to do if nobody is listening
+        let _ = self.shared.changed.send(key);
+    }
+
+    /// Gets the key of every entry that expires from now on.
+    /// (Keys that are [TtlMap::remove()]d or overwritten don't count.)
+    pub(crate) fn expirations(&self) -> broadcast::Receiver<String> {
+        self.shared.expired.subscribe()
+    }
+
+    /// Gets the key of every entry whose value is changed by [TtlMap::set_if_changed()] from now on.
+    pub(crate) fn changes(&self) -> broadcast::Receiver<String> {
+        self.shared.changed.subscribe()
+    }
 
     /// Gets `n` random key/values from this `TtlMap`.
     pub(crate) fn random_keys_vals(&self, n: usize) -> Vec<(String, T)> {
@@ -148,10 +188,7 @@ impl<T: 'static + Clone + std::marker::Send> TtlMap<T> {
         let random_keys = state
             .entries
             .keys()
-            .choose_multiple(&mut rng, n)
-            .iter()
-            .copied()
-            .collect::<Vec<_>>();
+            .choose_multiple(&mut rng, n);
 
         let mut ret = vec![];
 
@@ -163,6 +200,24 @@ impl<T: 'static + Clone + std::marker::Send> TtlMap<T> {
     }
 }
 
+impl<T: 'static + Clone + PartialEq + std::marker::Send> TtlMap<T> {
+    /// Like [TtlMap::set()] (so the expiration is pushed back either way), but also lets anyone
+    /// listening to [TtlMap::changes()] know if `value` is different from what was there before.
+    /// Returns whether it was.
+    pub(crate) fn set_if_changed(&self, key: String, value: T, expire: Option<Duration>) -> bool {
+        let changed = self.get(&key).as_ref() != Some(&value);
+
+        self.set(key.clone(), value, expire);
+
+        if changed {
+            // there is nothing to do if nobody is listening
+            let _ = self.shared.changed.send(key);
+        }
+
+        changed
+    }
+}
+
 impl<T> Shared<T> {
     /// Purge all expired keys and return the [Instant] that the next key
     /// expires (i.e., when background task should sleep until)
@@ -187,7 +242,13 @@ impl<T> Shared<T> {
             }
 
             // expired key; remove it.
-            state.entries.remove(key);
+            // (unless it was removed by hand and then set again, in which case the entry
+            // there now isn't the one that expired)
+            if state.entries.get(key).is_some_and(|entry| entry.id == id) {
+                state.entries.remove(key);
+                // there is nothing to do if nobody is listening
+                let _ = self.expired.send(key.clone());
+            }
             state.expirations.remove(&(when, id));
         }
 
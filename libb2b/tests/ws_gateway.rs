@@ -0,0 +1,70 @@
+//! Drives the WebSocket side of `ws_gateway` (see [serve_websocket()]) with a real WebSocket
+//! client, against a [Client] whose [Server] we stand in for.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use libb2b::control::serve_websocket;
+use libb2b::{Client, ClientEvent, ClientServerMessage, Server};
+
+type WebSocket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// The next JSON response on `ws`.
+async fn next_response(ws: &mut WebSocket) -> Value {
+    let frame = tokio::time::timeout(Duration::from_secs(5), ws.next())
+        .await
+        .expect("no response from the gateway")
+        .expect("the gateway closed the connection")
+        .unwrap();
+
+    match frame {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        frame => panic!("expected a text frame, got {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn says_and_gets_messages_over_a_websocket() {
+    let (server_tx, server_rx) = async_channel::unbounded();
+    let (events_tx, events_rx) = async_channel::unbounded();
+
+    // the server is never started, so we get to see everything the client sends it
+    let from_client = server_rx.clone();
+    let localhost = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0);
+    let server = Server::new("us", localhost, events_tx.clone(), server_rx).await.unwrap();
+    let client = Client::new("us".to_string(), server_tx, events_rx, &server);
+
+    let listener = TcpListener::bind(localhost).await.unwrap();
+    let gateway_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        serve_websocket(stream, client).await.unwrap();
+    });
+
+    let (mut ws, _) = connect_async(format!("ws://{}", gateway_addr)).await.unwrap();
+
+    let request = json!({"id": 1, "request": "say", "message": "hello everyone"});
+    ws.send(Message::text(request.to_string())).await.unwrap();
+
+    assert_eq!(next_response(&mut ws).await, json!({"id": 1, "response": "ok"}));
+    assert_eq!(
+        from_client.recv().await.unwrap(),
+        ClientServerMessage::Say(("us".to_string(), "hello everyone".to_string()))
+    );
+
+    // messages show up without having to subscribe first
+    let message = ClientServerMessage::Say(("them".to_string(), "hi!".to_string()));
+    events_tx.send(ClientEvent::Message(message)).await.unwrap();
+
+    assert_eq!(
+        next_response(&mut ws).await,
+        json!({"id": null, "response": "message", "message": {"Say": ["them", "hi!"]}})
+    );
+
+    ws.close(None).await.unwrap();
+}